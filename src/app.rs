@@ -1,5 +1,10 @@
+use std::path::PathBuf;
+
 use clap::Parser;
 
+use crate::sender::Transport;
+use crate::tip::TipStrategy;
+
 #[derive(Parser, Debug)]
 pub struct App {
     #[clap(flatten)]
@@ -11,7 +16,29 @@ pub struct App {
 
 #[derive(Parser, Debug)]
 #[command(version)]
-pub struct Args {}
+pub struct Args {
+    /// jito-http, jito-grpc, rpc, tpu or race, overrides TX_SENDER
+    #[arg(long, global = true)]
+    pub sender: Option<Transport>,
+
+    /// fixed:<lamports>, percentile:<p> or position:<bps>, overrides
+    /// TIP_STRATEGY
+    #[arg(long, global = true)]
+    pub tip: Option<TipStrategy>,
+
+    /// scale the tip with how the bundles land, on top of --tip
+    #[arg(long, global = true)]
+    pub tune_tips: bool,
+
+    /// sign and serialize the txs but log them instead of sending, also
+    /// DRY_RUN=1
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// append the dry run txs there as json lines, overrides DRY_RUN_FILE
+    #[arg(long, global = true)]
+    pub dry_run_file: Option<PathBuf>,
+}
 
 #[derive(Debug, Parser)]
 pub enum Command {
@@ -79,9 +106,16 @@ pub enum Command {
         pubkey: String,
     },
     Subscribe {},
-    Seller {},
+    Seller {
+        #[arg(long, default_value = "500")]
+        slippage_bps: u64,
+    },
     BenchPump {},
     BenchPortal {},
+    BenchBlockEngines {
+        #[arg(long, default_value = "5")]
+        rounds: usize,
+    },
     SnipePortal {
         #[arg(long)]
         lamports: u64,
@@ -116,6 +150,14 @@ pub enum Command {
     SweepPump {
         #[arg(long)]
         wallet_path: String,
+
+        #[arg(long, default_value = "500")]
+        slippage_bps: u64,
+    },
+    LookupTable {
+        /// extend this table instead of creating a new one
+        #[arg(long)]
+        address: Option<String>,
     },
     SwapMode {
         #[arg(long)]
@@ -123,5 +165,8 @@ pub enum Command {
 
         #[arg(long, default_value = "false")]
         sell: bool,
+
+        #[arg(long, default_value = "500")]
+        slippage_bps: u64,
     },
 }
@@ -1,4 +1,3 @@
-use jito_searcher_client::send_bundle_no_wait;
 use log::{info, warn};
 use solana_account_decoder::{
     parse_account_data::ParsedAccount, UiAccountData,
@@ -15,16 +14,22 @@ use std::error::Error;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use crate::bundle_tracker::BundleTracker;
 use crate::constants::TOKEN_PROGRAM;
-use crate::jito::SearcherClient;
+use crate::dry_run::get_dry_run;
+use crate::jito::{send_bundle, SearcherClient};
 use crate::util::get_jito_tip_pubkey;
 
+const CLOSE_OUTCOME_TIMEOUT: std::time::Duration =
+    std::time::Duration::from_secs(30);
+
 /// this is dangerous, be careful
 pub async fn close_all_atas(
     rpc_client: Arc<RpcClient>,
     keypair: &Keypair,
     burn_close: bool,
     searcher_client: &mut SearcherClient,
+    tracker: &BundleTracker,
 ) -> Result<(), Box<dyn Error>> {
     let tip = 5_000; // super low
     if !burn_close {
@@ -41,6 +46,7 @@ pub async fn close_all_atas(
         .await?;
     info!("Total ATAs: {}", atas.len());
     let owner = keypair.pubkey();
+    let mut sent = vec![];
     for ata in atas {
         if let UiAccountData::Json(ParsedAccount {
             program: _,
@@ -53,6 +59,9 @@ pub async fn close_all_atas(
                 .expect("amount");
             if amount_str == "0" {
                 info!("{}", ata.pubkey);
+                let mint = parsed["info"]["mint"]
+                    .as_str()
+                    .and_then(|mint| Pubkey::from_str(mint).ok());
                 let rpc_client = rpc_client.clone();
                 let tx = VersionedTransaction::from(
                     Transaction::new_signed_with_payer(
@@ -72,7 +81,19 @@ pub async fn close_all_atas(
                     ),
                 );
                 // rpc_client.send_transaction(&tx).await?;
-                send_bundle_no_wait(&[tx], searcher_client).await?;
+                let signature = tx.signatures[0];
+                let uuid = send_bundle(&[tx], searcher_client).await?;
+                tracker
+                    .track(
+                        uuid.clone(),
+                        vec![signature],
+                        mint,
+                        tip,
+                        None,
+                        None,
+                    )
+                    .await;
+                sent.push(uuid);
             } else if burn_close {
                 info!("Burn-closing: {}", ata.pubkey);
                 // don't do that no mo
@@ -81,5 +102,20 @@ pub async fn close_all_atas(
         }
     }
 
+    // no results stream reports on dry run bundles
+    if get_dry_run().is_some() {
+        info!("Recorded {} ATA closes", sent.len());
+        return Ok(());
+    }
+    let mut landed = 0;
+    for uuid in &sent {
+        match tracker.wait_for_outcome(uuid, CLOSE_OUTCOME_TIMEOUT).await {
+            Ok(record) if record.state.landed() => landed += 1,
+            Ok(record) => warn!("Close {} failed: {:?}", uuid, record.state),
+            Err(e) => warn!("{}", e),
+        }
+    }
+    info!("Closed {}/{} ATAs", landed, sent.len());
+
     Ok(())
 }
@@ -1,8 +1,11 @@
 use std::error::Error;
+use std::time::Duration;
 
 use fastwebsockets::{Frame, OpCode, Payload};
 use log::{info, warn};
 
+use crate::block_engine::BlockEngineRegistry;
+
 use crate::snipe::{get_message_type, MessageType, NewCoin};
 use crate::snipe_portal::NewPumpPortalToken;
 use crate::ws::{
@@ -94,3 +97,28 @@ pub async fn bench_pump_portal_connection() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// bench_block_engines probes every region rounds times, a second apart,
+/// and prints them fastest first
+pub async fn bench_block_engines(
+    rounds: usize,
+) -> Result<(), Box<dyn Error>> {
+    let registry = BlockEngineRegistry::mainnet();
+    for round in 0..rounds {
+        registry.probe().await;
+        info!("Probed block engines ({}/{})", round + 1, rounds);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    for region in registry.stats().await {
+        println!(
+            "{:<10} {:>10} {:>10} {:>3}/{:<3} {}",
+            region.region,
+            format!("{:.1?}", region.latency.unwrap_or_default()),
+            format!("{:.1?}", region.last_latency.unwrap_or_default()),
+            region.probes - region.failures,
+            region.probes,
+            region.url,
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,393 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use log::{debug, info, warn};
+use solana_sdk::transaction::VersionedTransaction;
+use tokio::sync::{OnceCell, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+
+use crate::dry_run::{get_dry_run, DryRunSender};
+use crate::jito::{block_engine_url, JITO_REGIONS};
+use crate::jito_client::JitoClient;
+use crate::sender::{
+    AcceptRaceSender, JitoHttpSender, SendResult, Transport, TxSender,
+};
+
+/// latencies move slowly, no need to probe more often than this
+pub const BLOCK_ENGINE_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// weight of the newest probe in the smoothed latency
+const LATENCY_EWMA_ALPHA: f64 = 0.3;
+
+/// a single dropped probe is not enough to stop sending to a region
+const UNHEALTHY_AFTER_FAILURES: u64 = 3;
+
+/// shared by every jito http send of the process, probed once
+static MAINNET_REGISTRY: OnceCell<Arc<BlockEngineRegistry>> =
+    OnceCell::const_new();
+
+#[derive(Debug, Clone)]
+pub struct RegionStats {
+    pub region: String,
+    pub url: String,
+    /// smoothed round trip, None until the first successful probe
+    pub latency: Option<Duration>,
+    pub last_latency: Option<Duration>,
+    pub probes: u64,
+    pub failures: u64,
+    /// failed probes since the last one that went through
+    pub consecutive_failures: u64,
+    /// probed fine once and not failing UNHEALTHY_AFTER_FAILURES in a row
+    pub healthy: bool,
+}
+
+impl RegionStats {
+    fn new(region: String, url: String) -> Self {
+        Self {
+            region,
+            url,
+            latency: None,
+            last_latency: None,
+            probes: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            healthy: false,
+        }
+    }
+
+    fn record(&mut self, res: Result<Duration, String>) {
+        self.probes += 1;
+        match res {
+            Ok(rtt) => {
+                self.latency = Some(match self.latency {
+                    Some(latency) => {
+                        latency.mul_f64(1. - LATENCY_EWMA_ALPHA)
+                            + rtt.mul_f64(LATENCY_EWMA_ALPHA)
+                    }
+                    None => rtt,
+                });
+                self.last_latency = Some(rtt);
+                self.consecutive_failures = 0;
+                self.healthy = true;
+            }
+            Err(e) => {
+                warn!("Block engine {} probe failed: {}", self.region, e);
+                self.failures += 1;
+                self.consecutive_failures += 1;
+                if self.consecutive_failures >= UNHEALTHY_AFTER_FAILURES {
+                    self.healthy = false;
+                }
+            }
+        }
+    }
+}
+
+/// BlockEngineRegistry keeps the round trip to each block engine region,
+/// sends go to the fastest healthy ones
+pub struct BlockEngineRegistry {
+    regions: RwLock<Vec<RegionStats>>,
+    client: reqwest::Client,
+}
+
+impl BlockEngineRegistry {
+    /// regions are (name, base url) pairs
+    pub fn new(regions: Vec<(String, String)>) -> Self {
+        Self {
+            regions: RwLock::new(
+                regions
+                    .into_iter()
+                    .map(|(region, url)| RegionStats::new(region, url))
+                    .collect(),
+            ),
+            client: reqwest::Client::builder()
+                .timeout(PROBE_TIMEOUT)
+                .build()
+                .expect("build client"),
+        }
+    }
+
+    pub fn mainnet() -> Self {
+        Self::new(
+            JITO_REGIONS
+                .iter()
+                .map(|region| (region.to_string(), block_engine_url(region)))
+                .collect(),
+        )
+    }
+
+    /// a probe is a getTipAccounts round trip, cheap and it goes through
+    /// the same path as the sends
+    async fn probe_url(
+        client: &reqwest::Client,
+        url: &str,
+    ) -> Result<Duration, String> {
+        let start = Instant::now();
+        JitoClient::with_client(url.to_string(), client.clone())
+            .get_tip_accounts()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(start.elapsed())
+    }
+
+    /// probe measures all of the regions at once
+    pub async fn probe(&self) {
+        let urls = self
+            .regions
+            .read()
+            .await
+            .iter()
+            .map(|region| region.url.clone())
+            .collect::<Vec<_>>();
+        let results = join_all(
+            urls.iter().map(|url| Self::probe_url(&self.client, url)),
+        )
+        .await;
+        let mut regions = self.regions.write().await;
+        for (region, res) in regions.iter_mut().zip(results) {
+            region.record(res);
+            debug!("Block engine {}: {:?}", region.region, region.latency);
+        }
+    }
+
+    /// stats sorted fastest first, unhealthy regions last
+    pub async fn stats(&self) -> Vec<RegionStats> {
+        let mut stats = self.regions.read().await.clone();
+        stats.sort_by_key(|region| {
+            (!region.healthy, region.latency.unwrap_or(Duration::MAX))
+        });
+        stats
+    }
+
+    /// top_k urls of the fastest healthy regions, if nothing is healthy
+    /// (yet) it falls back to the configured order so that sends still go
+    /// out
+    pub async fn top_k(&self, k: usize) -> Vec<String> {
+        let stats = self.stats().await;
+        let healthy = stats
+            .iter()
+            .filter(|region| region.healthy)
+            .map(|region| region.url.clone())
+            .take(k)
+            .collect::<Vec<_>>();
+        if !healthy.is_empty() {
+            return healthy;
+        }
+        self.regions
+            .read()
+            .await
+            .iter()
+            .map(|region| region.url.clone())
+            .take(k)
+            .collect()
+    }
+
+    pub async fn fastest(&self) -> Option<String> {
+        self.top_k(1).await.into_iter().next()
+    }
+}
+
+/// start_probing re-probes the registry every probe_interval
+pub fn start_probing(
+    registry: Arc<BlockEngineRegistry>,
+    probe_interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(probe_interval);
+        loop {
+            interval.tick().await;
+            registry.probe().await;
+        }
+    })
+}
+
+/// RegionalJitoSender sends through the block engine JSON-RPC of the
+/// fastest region, or races the fastest fanout regions
+pub struct RegionalJitoSender {
+    registry: Arc<BlockEngineRegistry>,
+    fanout: usize,
+    client: reqwest::Client,
+}
+
+impl RegionalJitoSender {
+    pub fn new(registry: Arc<BlockEngineRegistry>, fanout: usize) -> Self {
+        Self {
+            registry,
+            fanout: fanout.max(1),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl TxSender for RegionalJitoSender {
+    fn transport(&self) -> Transport {
+        Transport::JitoHttp
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>> {
+        let mut senders = self
+            .registry
+            .top_k(self.fanout)
+            .await
+            .into_iter()
+            .map(|url| {
+                Arc::new(JitoHttpSender::with_client(
+                    url,
+                    self.client.clone(),
+                )) as Arc<dyn TxSender>
+            })
+            .collect::<Vec<_>>();
+        match senders.len() {
+            0 => Err("No block engine regions".into()),
+            1 => senders.remove(0).send_transaction(tx).await,
+            _ => AcceptRaceSender::new(senders).send_transaction(tx).await,
+        }
+    }
+}
+
+/// mainnet_registry probes the mainnet regions on the first call and keeps
+/// probing in the background, later calls get the same registry
+pub async fn mainnet_registry() -> Arc<BlockEngineRegistry> {
+    MAINNET_REGISTRY
+        .get_or_init(|| async {
+            let registry = Arc::new(BlockEngineRegistry::mainnet());
+            registry.probe().await;
+            for region in registry.stats().await {
+                info!(
+                    "Block engine {}: {:?} (healthy: {})",
+                    region.region, region.latency, region.healthy
+                );
+            }
+            start_probing(registry.clone(), BLOCK_ENGINE_PROBE_INTERVAL);
+            registry
+        })
+        .await
+        .clone()
+}
+
+/// make_regional_jito_sender sends through the fastest fanout regions of
+/// the mainnet registry, a dry run records without probing any region
+pub async fn make_regional_jito_sender(fanout: usize) -> Arc<dyn TxSender> {
+    if let Some(dry_run) = get_dry_run() {
+        return Arc::new(DryRunSender::new(Transport::JitoHttp, dry_run));
+    }
+    Arc::new(RegionalJitoSender::new(mainnet_registry().await, fanout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_jito::{make_tx, MockBlockEngine};
+    use solana_sdk::signature::Keypair;
+
+    fn registry(urls: &[&str]) -> BlockEngineRegistry {
+        BlockEngineRegistry::new(
+            urls.iter()
+                .enumerate()
+                .map(|(i, url)| (format!("region-{}", i), url.to_string()))
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_registry_ranking() {
+        let registry = registry(&["http://a", "http://b", "http://c"]);
+        // nothing measured yet, configured order
+        assert_eq!(registry.top_k(2).await, vec!["http://a", "http://b"]);
+
+        {
+            let mut regions = registry.regions.write().await;
+            regions[0].record(Ok(Duration::from_millis(120)));
+            regions[1].record(Ok(Duration::from_millis(40)));
+            regions[2].record(Ok(Duration::from_millis(10)));
+            regions[2].record(Err("timeout".to_string()));
+        }
+        // one failed probe is not enough
+        assert_eq!(registry.fastest().await.as_deref(), Some("http://c"));
+        {
+            let mut regions = registry.regions.write().await;
+            for _ in 1..UNHEALTHY_AFTER_FAILURES {
+                regions[2].record(Err("timeout".to_string()));
+            }
+        }
+        assert_eq!(registry.fastest().await.as_deref(), Some("http://b"));
+        assert_eq!(
+            registry.top_k(5).await,
+            vec!["http://b", "http://a"],
+            "unhealthy regions are skipped"
+        );
+        let stats = registry.stats().await;
+        assert_eq!(stats[2].region, "region-2");
+        assert_eq!(stats[2].failures, UNHEALTHY_AFTER_FAILURES);
+        assert_eq!(stats[2].probes, UNHEALTHY_AFTER_FAILURES + 1);
+
+        // it comes back with the next probe that goes through
+        {
+            let mut regions = registry.regions.write().await;
+            regions[2].record(Ok(Duration::from_millis(10)));
+            assert_eq!(regions[2].consecutive_failures, 0);
+            regions[2].record(Ok(Duration::from_millis(500)));
+        }
+        assert!(registry.stats().await.iter().all(|region| region.healthy));
+
+        // a single slow probe does not throw a region out of the lead
+        {
+            let mut regions = registry.regions.write().await;
+            regions[1].record(Ok(Duration::from_millis(200)));
+        }
+        let stats = registry.stats().await;
+        assert_eq!(stats[0].region, "region-1");
+        assert_eq!(stats[0].last_latency, Some(Duration::from_millis(200)));
+        assert_eq!(stats[0].latency, Some(Duration::from_millis(88)));
+    }
+
+    #[tokio::test]
+    async fn test_regional_jito_sender() {
+        let first = MockBlockEngine::start().await.unwrap();
+        let second = MockBlockEngine::start().await.unwrap();
+        let registry = Arc::new(registry(&[
+            "http://127.0.0.1:1",
+            &first.http_url,
+            &second.http_url,
+        ]));
+        registry.probe().await;
+        let stats = registry.stats().await;
+        assert!(stats[..2].iter().all(|region| region.healthy));
+        assert!(!stats[2].healthy);
+        assert_eq!(stats[2].url, "http://127.0.0.1:1");
+
+        let payer = Keypair::new();
+        let tx = make_tx(&payer, 1);
+        RegionalJitoSender::new(registry.clone(), 1)
+            .send_transaction(&tx)
+            .await
+            .unwrap();
+        let sent = [first.transactions().await, second.transactions().await];
+        assert_eq!(sent.iter().map(Vec::len).sum::<usize>(), 1);
+
+        let tx = make_tx(&payer, 2);
+        RegionalJitoSender::new(registry.clone(), 3)
+            .send_transaction(&tx)
+            .await
+            .unwrap();
+        // the losing racers keep going in the background
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !(first.transactions().await.contains(&tx)
+                && second.transactions().await.contains(&tx))
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("both regions got the tx");
+    }
+}
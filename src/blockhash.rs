@@ -0,0 +1,357 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use log::{debug, error, warn};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::clock::{Slot, MAX_PROCESSING_AGE};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::util::env;
+
+/// hashes this close to last_valid_block_height are not signed with, ~8s
+/// for the tx to land
+pub const DEFAULT_EXPIRY_MARGIN: u64 = 20;
+
+/// a new hash every other slot, the old timer fetched one a second
+pub const DEFAULT_REFRESH_SLOTS: u64 = 2;
+
+/// CachedBlockhash is a hash with what is needed to tell when it expires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachedBlockhash {
+    pub hash: Hash,
+    pub last_valid_block_height: u64,
+    /// slot the hash was fetched at
+    pub slot: Slot,
+    pub fetched_at: Instant,
+}
+
+impl CachedBlockhash {
+    /// blocks_left counts every slot since the fetch as a block, skipped
+    /// slots make it err on the early side
+    pub fn blocks_left(&self, current_slot: Slot) -> u64 {
+        let block_height = self
+            .last_valid_block_height
+            .saturating_sub(MAX_PROCESSING_AGE as u64)
+            + current_slot.saturating_sub(self.slot);
+        self.last_valid_block_height.saturating_sub(block_height)
+    }
+}
+
+/// BlockhashCache keeps the latest blockhash, refreshed on slot
+/// notifications, to be shared between all of the flows signing txs
+pub struct BlockhashCache {
+    rpc_client: Arc<RpcClient>,
+    commitment: CommitmentConfig,
+    expiry_margin: u64,
+    refresh_slots: u64,
+    latest: RwLock<Option<CachedBlockhash>>,
+    current_slot: RwLock<Slot>,
+    updates: broadcast::Sender<Hash>,
+}
+
+impl BlockhashCache {
+    pub fn new(rpc_client: Arc<RpcClient>) -> Self {
+        let (updates, _) = broadcast::channel(16);
+        Self {
+            rpc_client,
+            commitment: CommitmentConfig::confirmed(),
+            expiry_margin: DEFAULT_EXPIRY_MARGIN,
+            refresh_slots: DEFAULT_REFRESH_SLOTS,
+            latest: RwLock::new(None),
+            current_slot: RwLock::new(0),
+            updates,
+        }
+    }
+
+    pub fn with_expiry_margin(mut self, blocks: u64) -> Self {
+        self.expiry_margin = blocks;
+        self
+    }
+
+    pub fn with_refresh_slots(mut self, slots: u64) -> Self {
+        self.refresh_slots = slots.max(1);
+        self
+    }
+
+    /// sync_slot asks the rpc for the current slot, for before the first
+    /// slot notification
+    pub async fn sync_slot(&self) -> Result<Slot, Box<dyn Error>> {
+        let slot = self
+            .rpc_client
+            .get_slot_with_commitment(self.commitment)
+            .await?;
+        let mut current_slot = self.current_slot.write().await;
+        *current_slot = (*current_slot).max(slot);
+        Ok(*current_slot)
+    }
+
+    /// refresh fetches a hash regardless of when the last one was fetched
+    pub async fn refresh(&self) -> Result<Hash, Box<dyn Error>> {
+        let mut slot = self.current_slot().await;
+        if slot == 0 {
+            // a hash fetched at slot 0 would look expired right away
+            slot = self.sync_slot().await?;
+        }
+        let (hash, last_valid_block_height) = self
+            .rpc_client
+            .get_latest_blockhash_with_commitment(self.commitment)
+            .await?;
+        let mut latest = self.latest.write().await;
+        // a slow response should not replace a newer hash
+        if let Some(cached) = *latest {
+            if cached.last_valid_block_height > last_valid_block_height {
+                return Ok(cached.hash);
+            }
+        }
+        let changed = latest.map(|cached| cached.hash) != Some(hash);
+        *latest = Some(CachedBlockhash {
+            hash,
+            last_valid_block_height,
+            slot,
+            fetched_at: Instant::now(),
+        });
+        if changed {
+            debug!(
+                "Updated latest blockhash: {} valid until {}",
+                hash, last_valid_block_height
+            );
+            let _ = self.updates.send(hash);
+        }
+        Ok(hash)
+    }
+
+    /// on_slot takes a slot notification, a hash is fetched every
+    /// refresh_slots slots
+    pub async fn on_slot(&self, slot: Slot) -> Result<(), Box<dyn Error>> {
+        {
+            let mut current_slot = self.current_slot.write().await;
+            if slot <= *current_slot {
+                return Ok(());
+            }
+            *current_slot = slot;
+        }
+        let due = match *self.latest.read().await {
+            Some(cached) => {
+                slot >= cached.slot.saturating_add(self.refresh_slots)
+            }
+            None => true,
+        };
+        if due {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn current_slot(&self) -> Slot {
+        *self.current_slot.read().await
+    }
+
+    /// latest is the cached hash even if it is about to expire
+    pub async fn latest(&self) -> Option<CachedBlockhash> {
+        *self.latest.read().await
+    }
+
+    /// get is the hash to sign with, it errors when there is none yet or
+    /// when it is within the expiry margin
+    pub async fn get(&self) -> Result<Hash, Box<dyn Error>> {
+        let cached = self.latest().await.ok_or("No blockhash yet")?;
+        let blocks_left = cached.blocks_left(self.current_slot().await);
+        if blocks_left <= self.expiry_margin {
+            return Err(format!(
+                "Blockhash {} expires in {} blocks",
+                cached.hash, blocks_left
+            )
+            .into());
+        }
+        Ok(cached.hash)
+    }
+
+    /// subscribe receives every new hash
+    pub fn subscribe(&self) -> broadcast::Receiver<Hash> {
+        self.updates.subscribe()
+    }
+
+    /// wait_for_new waits for a usable hash other than previous
+    pub async fn wait_for_new(
+        &self,
+        previous: Hash,
+        timeout: Duration,
+    ) -> Result<Hash, Box<dyn Error>> {
+        let mut updates = self.subscribe();
+        if let Ok(hash) = self.get().await {
+            if hash != previous {
+                return Ok(hash);
+            }
+        }
+        tokio::time::timeout(timeout, async {
+            loop {
+                match updates.recv().await {
+                    Ok(hash) if hash != previous => return Ok(hash),
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => {
+                        return Err("Blockhash updates closed".into())
+                    }
+                }
+            }
+        })
+        .await
+        .map_err(|_| "Timed out waiting for a new blockhash")?
+    }
+}
+
+/// start_blockhash_updates refreshes the cache on slot notifications from
+/// ws_url, reconnecting when the subscription drops
+pub fn start_blockhash_updates(
+    cache: Arc<BlockhashCache>,
+    ws_url: String,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let pubsub_client = match PubsubClient::new(&ws_url).await {
+                Ok(pubsub_client) => pubsub_client,
+                Err(e) => {
+                    error!("Failed to connect to {}: {}", ws_url, e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            let (mut stream, unsub) =
+                match pubsub_client.slot_subscribe().await {
+                    Ok(subscription) => subscription,
+                    Err(e) => {
+                        error!("Failed to subscribe to slots: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+            while let Some(slot_info) = stream.next().await {
+                let Err(e) = cache.on_slot(slot_info.slot).await else {
+                    continue;
+                };
+                warn!("Failed to refresh blockhash: {}", e);
+            }
+            unsub().await;
+            warn!("Slot subscription dropped, reconnecting");
+            // wait for a second before reconnecting
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    })
+}
+
+/// make_blockhash_cache fetches a first hash from RPC_URL and keeps it
+/// fresh with the slots from WS_URL
+pub async fn make_blockhash_cache(
+) -> Result<Arc<BlockhashCache>, Box<dyn Error>> {
+    let cache = Arc::new(BlockhashCache::new(Arc::new(RpcClient::new(env(
+        "RPC_URL",
+    )))));
+    cache.refresh().await?;
+    start_blockhash_updates(cache.clone(), env("WS_URL"));
+    Ok(cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rpc::{MockRpcServer, RpcFixtures};
+
+    fn fixtures() -> RpcFixtures {
+        RpcFixtures {
+            slot: 100,
+            block_height: 1_000,
+            blockhash: Hash::new_unique(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blockhash_cache_expiry() {
+        let fixtures = fixtures();
+        let blockhash = fixtures.blockhash;
+        let server = MockRpcServer::start(fixtures).await.unwrap();
+        let cache = BlockhashCache::new(Arc::new(RpcClient::new(
+            server.rpc_url.clone(),
+        )))
+        .with_refresh_slots(1_000);
+        assert!(cache.get().await.is_err());
+
+        cache.on_slot(100).await.unwrap();
+        assert_eq!(cache.get().await.unwrap(), blockhash);
+        let cached = cache.latest().await.unwrap();
+        assert_eq!(cached.last_valid_block_height, 1_150);
+        assert_eq!(cached.blocks_left(100), 150);
+
+        // 21 blocks left is still fine, 20 is within the margin
+        cache.on_slot(229).await.unwrap();
+        assert_eq!(cache.get().await.unwrap(), blockhash);
+        cache.on_slot(230).await.unwrap();
+        let err = cache.get().await.unwrap_err();
+        assert!(err.to_string().contains("expires in 20 blocks"));
+        // old slots are ignored
+        cache.on_slot(101).await.unwrap();
+        assert_eq!(cache.current_slot().await, 230);
+    }
+
+    #[tokio::test]
+    async fn test_blockhash_cache_first_refresh() {
+        let fixtures = fixtures();
+        let blockhash = fixtures.blockhash;
+        let server = MockRpcServer::start(fixtures).await.unwrap();
+        let cache = BlockhashCache::new(Arc::new(RpcClient::new(
+            server.rpc_url.clone(),
+        )));
+        // no slot notification yet, the slot comes from the rpc
+        assert_eq!(cache.refresh().await.unwrap(), blockhash);
+        assert_eq!(cache.current_slot().await, 100);
+        assert_eq!(cache.latest().await.unwrap().slot, 100);
+        assert_eq!(cache.get().await.unwrap(), blockhash);
+    }
+
+    #[tokio::test]
+    async fn test_blockhash_updates_on_slots() {
+        let fixtures = fixtures();
+        let first = fixtures.blockhash;
+        let server = MockRpcServer::start(fixtures).await.unwrap();
+        let cache = Arc::new(BlockhashCache::new(Arc::new(RpcClient::new(
+            server.rpc_url.clone(),
+        ))));
+        cache.on_slot(100).await.unwrap();
+        start_blockhash_updates(cache.clone(), server.ws_url.clone());
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while server.slot_subscribers() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("subscribed to slots");
+
+        // not due yet
+        server.push_slot(101).await;
+        let second = Hash::new_unique();
+        server.set_blockhash(second).await;
+        server.push_slot(102).await;
+        assert_eq!(
+            cache
+                .wait_for_new(first, Duration::from_secs(5))
+                .await
+                .unwrap(),
+            second
+        );
+        assert_eq!(cache.latest().await.unwrap().slot, 102);
+
+        server.set_blockhash(Hash::new_unique()).await;
+        server.push_slot(103).await;
+        assert!(cache
+            .wait_for_new(second, Duration::from_millis(300))
+            .await
+            .is_err());
+        assert_eq!(cache.get().await.unwrap(), second);
+    }
+}
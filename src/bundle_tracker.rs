@@ -0,0 +1,715 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use jito_protos::bundle::{
+    bundle_result, rejected, BundleResult, DroppedReason, Rejected,
+};
+use log::{debug, info, warn};
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+
+use crate::jito_client::{InflightBundleStatus, InflightStatus, JitoClient};
+use crate::preflight::program_ids;
+
+/// getInflightBundleStatuses takes up to 5 ids per call
+const INFLIGHT_STATUSES_BATCH: usize = 5;
+
+pub const INFLIGHT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// getInflightBundleStatuses only knows about the last 5 minutes of bundles
+pub const INFLIGHT_STATUS_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// final records are dropped this long after their last update
+pub const FINAL_RECORD_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleState {
+    /// sent, nothing heard back yet
+    Pending,
+    Accepted {
+        slot: Slot,
+    },
+    Processed {
+        slot: Slot,
+    },
+    Finalized,
+    /// from getInflightBundleStatuses
+    Landed {
+        slot: Option<Slot>,
+    },
+    /// from getInflightBundleStatuses, bundle expired without landing
+    Failed,
+    /// from getInflightBundleStatuses, unknown id or older than 5 minutes
+    Invalid,
+    Rejected(Rejection),
+    Dropped(String),
+}
+
+/// Rejection is why the block engine turned a bundle down
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// a tx failed in simulation, a reverted deadline included
+    SimulationFailure(String),
+    /// lost the auction to higher tips
+    Outbid(String),
+    Other(String),
+}
+
+impl Rejection {
+    pub fn reason(&self) -> &str {
+        match self {
+            Self::SimulationFailure(reason)
+            | Self::Outbid(reason)
+            | Self::Other(reason) => reason,
+        }
+    }
+}
+
+impl BundleState {
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Self::Finalized
+                | Self::Landed { .. }
+                | Self::Failed
+                | Self::Rejected(_)
+                | Self::Dropped(_)
+        )
+    }
+
+    pub fn landed(&self) -> bool {
+        matches!(
+            self,
+            Self::Processed { .. } | Self::Finalized | Self::Landed { .. }
+        )
+    }
+
+    /// states only move forward, an accepted that comes in after processed
+    /// is stale
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Pending | Self::Invalid => 0,
+            Self::Accepted { .. } => 1,
+            Self::Processed { .. } => 2,
+            _ => 3,
+        }
+    }
+
+    /// rejection or drop reason
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Self::Rejected(rejection) => Some(rejection.reason()),
+            Self::Dropped(reason) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Rejected> for Rejection {
+    fn from(rejected: &Rejected) -> Self {
+        match &rejected.reason {
+            Some(rejected::Reason::SimulationFailure(failure)) => {
+                Self::SimulationFailure(format!(
+                    "simulation failure {}: {}",
+                    failure.tx_signature,
+                    failure.msg.as_deref().unwrap_or_default()
+                ))
+            }
+            Some(rejected::Reason::StateAuctionBidRejected(bid)) => {
+                Self::Outbid(format!(
+                    "state auction bid rejected, simulated bid {} lamports: {}",
+                    bid.simulated_bid_lamports,
+                    bid.msg.as_deref().unwrap_or_default()
+                ))
+            }
+            Some(rejected::Reason::WinningBatchBidRejected(bid)) => {
+                Self::Outbid(format!(
+                    "winning batch bid rejected, simulated bid {} lamports: {}",
+                    bid.simulated_bid_lamports,
+                    bid.msg.as_deref().unwrap_or_default()
+                ))
+            }
+            Some(rejected::Reason::InternalError(e)) => {
+                Self::Other(format!("internal error: {}", e.msg))
+            }
+            Some(rejected::Reason::DroppedBundle(dropped)) => {
+                Self::Other(format!("dropped: {}", dropped.msg))
+            }
+            None => Self::Other("unknown".to_string()),
+        }
+    }
+}
+
+impl From<bundle_result::Result> for BundleState {
+    fn from(result: bundle_result::Result) -> Self {
+        match result {
+            bundle_result::Result::Accepted(accepted) => Self::Accepted {
+                slot: accepted.slot,
+            },
+            bundle_result::Result::Processed(processed) => Self::Processed {
+                slot: processed.slot,
+            },
+            bundle_result::Result::Finalized(_) => Self::Finalized,
+            bundle_result::Result::Rejected(rejected) => {
+                Self::Rejected((&rejected).into())
+            }
+            bundle_result::Result::Dropped(dropped) => Self::Dropped(
+                DroppedReason::try_from(dropped.reason)
+                    .map(|reason| format!("{:?}", reason))
+                    .unwrap_or_else(|_| format!("reason {}", dropped.reason)),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BundleRecord {
+    pub uuid: String,
+    pub signatures: Vec<Signature>,
+    /// program of each ix of the tx, tells what a simulation failure is
+    /// about, empty unless tracked with track_tx
+    pub programs: Vec<Pubkey>,
+    pub mint: Option<Pubkey>,
+    pub tip: u64,
+    /// slot the bundle was meant to land in, e.g. the creation slot of the
+    /// mint on snipes
+    pub target_slot: Option<Slot>,
+    /// deadline instruction slot, the bundle reverts past it
+    pub deadline: Option<Slot>,
+    pub landed_slot: Option<Slot>,
+    /// None if the result came in before the send was tracked
+    pub sent_at: Option<Instant>,
+    pub state: BundleState,
+    pub updated_at: Instant,
+    /// counted by the tip tuner, goes away with the record
+    observed: bool,
+}
+
+impl BundleRecord {
+    fn new(uuid: String) -> Self {
+        Self {
+            uuid,
+            signatures: vec![],
+            programs: vec![],
+            mint: None,
+            tip: 0,
+            target_slot: None,
+            deadline: None,
+            landed_slot: None,
+            sent_at: None,
+            state: BundleState::Pending,
+            updated_at: Instant::now(),
+            observed: false,
+        }
+    }
+
+    /// time from the send to the latest state
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.sent_at
+            .map(|sent_at| self.updated_at.saturating_duration_since(sent_at))
+    }
+
+    /// slot_delta is how many slots after the target the bundle landed
+    pub fn slot_delta(&self) -> Option<u64> {
+        Some(self.landed_slot?.saturating_sub(self.target_slot?))
+    }
+
+    /// is_final is the state being final, or invalid for a bundle sent
+    /// longer ago than the status window, it is not going to show up anymore
+    pub fn is_final(&self) -> bool {
+        self.state.is_final()
+            || (self.state == BundleState::Invalid
+                && self.sent_at.is_some_and(|sent_at| {
+                    sent_at.elapsed() >= INFLIGHT_STATUS_WINDOW
+                }))
+    }
+
+    /// expired records are final, or results for bundles that were never
+    /// tracked, and have not changed for FINAL_RECORD_TTL
+    fn expired(&self) -> bool {
+        (self.is_final() || self.sent_at.is_none())
+            && self.updated_at.elapsed() >= FINAL_RECORD_TTL
+    }
+}
+
+/// BundleTracker joins the bundles that were sent with what the block engine
+/// says about them, either through SubscribeBundleResults or
+/// getInflightBundleStatuses
+pub struct BundleTracker {
+    bundles: RwLock<HashMap<String, BundleRecord>>,
+    /// uuids of the bundles that changed state
+    updates: broadcast::Sender<String>,
+}
+
+impl Default for BundleTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BundleTracker {
+    pub fn new() -> Self {
+        let (updates, _) = broadcast::channel(1024);
+        Self {
+            bundles: RwLock::new(HashMap::new()),
+            updates,
+        }
+    }
+
+    /// track records a sent bundle, results that came in before it are kept
+    pub async fn track(
+        &self,
+        uuid: String,
+        signatures: Vec<Signature>,
+        mint: Option<Pubkey>,
+        tip: u64,
+        target_slot: Option<Slot>,
+        deadline: Option<Slot>,
+    ) {
+        let mut bundles = self.bundles.write().await;
+        bundles.retain(|_, record| !record.expired());
+        let record = bundles
+            .entry(uuid.clone())
+            .or_insert_with(|| BundleRecord::new(uuid));
+        record.signatures = signatures;
+        record.mint = mint;
+        record.tip = tip;
+        record.target_slot = target_slot;
+        record.deadline = deadline;
+        record.sent_at = Some(Instant::now());
+    }
+
+    /// track_tx is track for a single tx bundle, it keeps the programs of
+    /// the tx too
+    pub async fn track_tx(
+        &self,
+        uuid: String,
+        tx: &VersionedTransaction,
+        mint: Option<Pubkey>,
+        tip: u64,
+        target_slot: Option<Slot>,
+        deadline: Option<Slot>,
+    ) {
+        self.track(
+            uuid.clone(),
+            vec![tx.signatures[0]],
+            mint,
+            tip,
+            target_slot,
+            deadline,
+        )
+        .await;
+        if let Some(record) = self.bundles.write().await.get_mut(&uuid) {
+            record.programs = program_ids(tx);
+        }
+    }
+
+    pub async fn update(&self, uuid: &str, state: BundleState) {
+        let mut bundles = self.bundles.write().await;
+        let record = bundles
+            .entry(uuid.to_string())
+            .or_insert_with(|| BundleRecord::new(uuid.to_string()));
+        if record.is_final()
+            || state.rank() < record.state.rank()
+            || record.state == state
+        {
+            debug!("Bundle {} ignoring {:?}", uuid, state);
+            return;
+        }
+        match state.reason() {
+            Some(reason) => {
+                warn!("Bundle {} {:?}: {}", uuid, record.mint, reason)
+            }
+            None => info!("Bundle {} {:?}: {:?}", uuid, record.mint, state),
+        }
+        match state {
+            BundleState::Processed { slot }
+            | BundleState::Landed { slot: Some(slot) } => {
+                record.landed_slot = Some(slot);
+            }
+            _ => {}
+        }
+        record.state = state;
+        record.updated_at = Instant::now();
+        let _ = self.updates.send(uuid.to_string());
+    }
+
+    pub async fn apply_result(&self, result: BundleResult) {
+        if let Some(state) = result.result {
+            self.update(&result.bundle_id, state.into()).await;
+        }
+    }
+
+    /// mark_observed is true the first time it is called for a bundle, so
+    /// a final bundle is only counted once whatever the updates it gets
+    pub async fn mark_observed(&self, uuid: &str) -> bool {
+        match self.bundles.write().await.get_mut(uuid) {
+            Some(record) if !record.observed => {
+                record.observed = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// subscribe gets the uuids of the bundles as they change state
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.updates.subscribe()
+    }
+
+    pub async fn get(&self, uuid: &str) -> Option<BundleRecord> {
+        self.bundles.read().await.get(uuid).cloned()
+    }
+
+    pub async fn records(&self) -> Vec<BundleRecord> {
+        self.bundles.read().await.values().cloned().collect()
+    }
+
+    /// uuids of the sent bundles without a final state
+    pub async fn in_flight(&self) -> Vec<String> {
+        self.bundles
+            .read()
+            .await
+            .values()
+            .filter(|record| record.sent_at.is_some() && !record.is_final())
+            .map(|record| record.uuid.clone())
+            .collect()
+    }
+
+    /// wait_for_outcome resolves once the bundle reaches a final state
+    pub async fn wait_for_outcome(
+        &self,
+        uuid: &str,
+        timeout: Duration,
+    ) -> Result<BundleRecord, Box<dyn Error>> {
+        // subscribe before checking, not to miss an update in between
+        let mut updates = self.subscribe();
+        let outcome = tokio::time::timeout(timeout, async {
+            loop {
+                if let Some(record) = self.get(uuid).await {
+                    if record.is_final() {
+                        return Some(record);
+                    }
+                }
+                loop {
+                    match updates.recv().await {
+                        Ok(updated) if updated == uuid => break,
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(_)) => break,
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return None
+                        }
+                    }
+                }
+            }
+        })
+        .await;
+        match outcome {
+            Ok(Some(record)) => Ok(record),
+            Ok(None) => Err("Bundle tracker closed".into()),
+            Err(_) => Err(format!(
+                "Bundle {} not final after {:?}: {:?}",
+                uuid,
+                timeout,
+                self.get(uuid).await.map(|record| record.state)
+            )
+            .into()),
+        }
+    }
+}
+
+impl From<&InflightBundleStatus> for BundleState {
+    fn from(status: &InflightBundleStatus) -> Self {
+        match status.status {
+            InflightStatus::Pending => Self::Pending,
+            InflightStatus::Landed => Self::Landed {
+                slot: status.landed_slot,
+            },
+            InflightStatus::Failed => Self::Failed,
+            InflightStatus::Invalid => Self::Invalid,
+        }
+    }
+}
+
+/// poll_inflight_statuses keeps asking the block engine about the bundles
+/// that are still in flight, for when there is no gRPC results stream
+pub fn poll_inflight_statuses(
+    tracker: Arc<BundleTracker>,
+    block_engine_url: String,
+    poll_interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let jito_client = JitoClient::new(block_engine_url);
+        let mut interval = interval(poll_interval);
+        loop {
+            interval.tick().await;
+            let in_flight = tracker.in_flight().await;
+            for uuids in in_flight.chunks(INFLIGHT_STATUSES_BATCH) {
+                let statuses = match jito_client
+                    .get_inflight_bundle_statuses(uuids)
+                    .await
+                {
+                    Ok(statuses) => statuses,
+                    Err(e) => {
+                        warn!("Failed to get inflight statuses: {}", e);
+                        continue;
+                    }
+                };
+                for status in &statuses {
+                    tracker.update(&status.bundle_id, status.into()).await;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jito::{_send_out_bundle, start_bundle_results_listener};
+    use crate::mock_jito::{make_tx, BundleOutcome, MockBlockEngine};
+    use crate::sender::{JitoGrpcSender, TxSender};
+    use futures::future::join_all;
+    use jito_searcher_client::get_searcher_client;
+    use solana_sdk::signature::Keypair;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_bundle_states_only_move_forward() {
+        let tracker = BundleTracker::new();
+        // results can beat the send
+        tracker
+            .update("a", BundleState::Processed { slot: 2 })
+            .await;
+        tracker
+            .track("a".to_string(), vec![], None, 1_000, Some(1), None)
+            .await;
+        tracker.update("a", BundleState::Accepted { slot: 1 }).await;
+        let record = tracker.get("a").await.unwrap();
+        assert_eq!(record.state, BundleState::Processed { slot: 2 });
+        assert_eq!(record.tip, 1_000);
+        assert_eq!(record.slot_delta(), Some(1));
+        assert_eq!(tracker.in_flight().await, vec!["a".to_string()]);
+
+        tracker.update("a", BundleState::Finalized).await;
+        tracker.update("a", BundleState::Failed).await;
+        assert_eq!(
+            tracker.get("a").await.unwrap().state,
+            BundleState::Finalized
+        );
+        assert!(tracker.in_flight().await.is_empty());
+
+        let outcome = tracker
+            .wait_for_outcome("a", Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert!(outcome.state.landed());
+        assert!(tracker
+            .wait_for_outcome("b", Duration::from_millis(10))
+            .await
+            .is_err());
+
+        assert!(tracker.mark_observed("a").await);
+        assert!(!tracker.mark_observed("a").await);
+        assert!(!tracker.mark_observed("b").await);
+    }
+
+    #[tokio::test]
+    async fn test_bundle_tracker_grpc_results() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        let auth = Arc::new(Keypair::new());
+        let searcher_client = Arc::new(Mutex::new(
+            get_searcher_client(&engine.url, &auth).await.unwrap(),
+        ));
+        let tracker = Arc::new(BundleTracker::new());
+        start_bundle_results_listener(
+            searcher_client.clone(),
+            tracker.clone(),
+        )
+        .await;
+        let sender = JitoGrpcSender::new(searcher_client);
+        let payer = Keypair::new();
+        let mint = Pubkey::new_unique();
+
+        engine
+            .script(vec![BundleOutcome::Accepted, BundleOutcome::Finalized])
+            .await;
+        engine
+            .script(vec![
+                BundleOutcome::Accepted,
+                BundleOutcome::Rejected(
+                    "custom program error: 0x1772".into(),
+                ),
+            ])
+            .await;
+        let mut uuids = vec![];
+        for lamports in [1, 2] {
+            let res = sender
+                .send_transaction(&make_tx(&payer, lamports))
+                .await
+                .unwrap();
+            let uuid = res.bundle_id.unwrap();
+            tracker
+                .track(
+                    uuid.clone(),
+                    vec![res.signature],
+                    Some(mint),
+                    50_000,
+                    None,
+                    None,
+                )
+                .await;
+            uuids.push((uuid, res.signature));
+        }
+
+        let (uuid, signature) = &uuids[0];
+        let record = tracker
+            .wait_for_outcome(uuid, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(record.state, BundleState::Finalized);
+        assert_eq!(record.signatures, vec![*signature]);
+        assert_eq!(record.mint, Some(mint));
+        assert!(record.elapsed().is_some());
+
+        let (uuid, signature) = &uuids[1];
+        let record = tracker
+            .wait_for_outcome(uuid, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(!record.state.landed());
+        let reason = record.state.reason().unwrap();
+        assert!(reason.contains(&signature.to_string()));
+        assert!(reason.contains("0x1772"));
+    }
+
+    #[tokio::test]
+    async fn test_bundle_tracker_inflight_statuses() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        engine.set_slot(420).await;
+        let tracker = Arc::new(BundleTracker::new());
+        let payer = Keypair::new();
+
+        engine.script(vec![BundleOutcome::Dropped]).await;
+        engine.script(vec![BundleOutcome::Finalized]).await;
+        for lamports in [1, 2] {
+            let tx = make_tx(&payer, lamports);
+            let handles = _send_out_bundle(
+                std::slice::from_ref(&engine.http_url),
+                std::slice::from_ref(&tx),
+            )
+            .await
+            .unwrap();
+            join_all(handles).await;
+        }
+        let bundles = engine.bundles().await;
+        for bundle in &bundles {
+            tracker
+                .track(
+                    bundle.uuid.clone(),
+                    vec![bundle.transactions[0].signatures[0]],
+                    None,
+                    10_000,
+                    None,
+                    None,
+                )
+                .await;
+        }
+        tracker
+            .track("unknown".to_string(), vec![], None, 0, None, None)
+            .await;
+        poll_inflight_statuses(
+            tracker.clone(),
+            engine.http_url.clone(),
+            Duration::from_millis(50),
+        );
+
+        let dropped = tracker
+            .wait_for_outcome(&bundles[0].uuid, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(dropped.state, BundleState::Failed);
+        let landed = tracker
+            .wait_for_outcome(&bundles[1].uuid, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(landed.state, BundleState::Landed { slot: Some(420) });
+
+        // invalid is not final, the id might not have propagated yet
+        assert!(tracker
+            .wait_for_outcome("unknown", Duration::from_millis(200))
+            .await
+            .is_err());
+        assert_eq!(
+            tracker.get("unknown").await.unwrap().state,
+            BundleState::Invalid
+        );
+        assert_eq!(tracker.in_flight().await, vec!["unknown".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_bundle_tracker_expiry() {
+        // Instant counts from boot, a host up for less than the ttl can't
+        // rewind the records that far
+        if Instant::now().checked_sub(FINAL_RECORD_TTL).is_none()
+            || Instant::now()
+                .checked_sub(INFLIGHT_STATUS_WINDOW + Duration::from_secs(1))
+                .is_none()
+        {
+            return;
+        }
+        let tracker = BundleTracker::new();
+        for uuid in ["old", "new", "landed"] {
+            tracker
+                .track(uuid.to_string(), vec![], None, 0, None, None)
+                .await;
+            tracker.update(uuid, BundleState::Invalid).await;
+        }
+        tracker.update("landed", BundleState::Finalized).await;
+        tracker
+            .update("untracked", BundleState::Accepted { slot: 1 })
+            .await;
+        {
+            let mut bundles = tracker.bundles.write().await;
+            let record = bundles.get_mut("old").unwrap();
+            record.sent_at = Some(
+                Instant::now()
+                    - INFLIGHT_STATUS_WINDOW
+                    - Duration::from_secs(1),
+            );
+        }
+        // past the status window an invalid bundle is never going to land
+        assert!(tracker.get("old").await.unwrap().is_final());
+        assert_eq!(tracker.in_flight().await, vec!["new".to_string()]);
+        let record = tracker
+            .wait_for_outcome("old", Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert_eq!(record.state, BundleState::Invalid);
+
+        {
+            let mut bundles = tracker.bundles.write().await;
+            for record in bundles.values_mut() {
+                record.updated_at -= FINAL_RECORD_TTL;
+            }
+        }
+        tracker
+            .track("next".to_string(), vec![], None, 0, None, None)
+            .await;
+        let mut uuids = tracker
+            .records()
+            .await
+            .into_iter()
+            .map(|record| record.uuid)
+            .collect::<Vec<_>>();
+        uuids.sort();
+        assert_eq!(uuids, vec!["new".to_string(), "next".to_string()]);
+    }
+}
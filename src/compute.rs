@@ -0,0 +1,501 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::{v0, Message, VersionedMessage};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::system_program;
+use solana_sdk::transaction::VersionedTransaction;
+use tokio::sync::RwLock;
+
+use crate::constants::{MEMO_PROGRAM, PUMP_FEE_PROGRAM, PUMP_FUN_PROGRAM};
+use crate::moonshot::MOONSHOT_PROGRAM;
+use crate::util::is_jito_tip_account;
+
+/// most a tx can ask for
+pub const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+
+/// 20% on top of what the simulation consumed, ata and volume accumulator
+/// inits cost more than the simulated tx might have
+pub const DEFAULT_MARGIN_BPS: u64 = 2_000;
+
+pub const DEFAULT_FEE_PERCENTILE: f64 = 75.;
+
+/// micro lamports per unit, 2x the old fixed price of the service buys
+pub const DEFAULT_MAX_PRICE: u64 = 2_000_000;
+
+/// recent fees cover the last 150 slots, no need to refetch more often
+pub const DEFAULT_PRICE_TTL: Duration = Duration::from_secs(10);
+
+/// a shape is simulated again after this, whether the ata exists or the
+/// curve is about to complete changes what it consumes
+pub const DEFAULT_UNITS_TTL: Duration = Duration::from_secs(60);
+
+/// getRecentPrioritizationFees takes at most 128 accounts
+const MAX_FEE_ACCOUNTS: usize = 128;
+
+/// ComputeBudget is the price in micro lamports per unit and the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComputeBudget {
+    pub price: u64,
+    pub units: u32,
+}
+
+/// IxShape is what the units of an ix depend on, the program, the method
+/// and the number of accounts, not the amounts
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IxShape {
+    pub program_id: Pubkey,
+    pub discriminator: Vec<u8>,
+    pub accounts: usize,
+}
+
+impl From<&Instruction> for IxShape {
+    fn from(ix: &Instruction) -> Self {
+        let program_id = ix.program_id.to_string();
+        // anchor methods are 8 bytes, system ones 4, moonshot trades and
+        // memos have none, the token programs 1
+        let len = match program_id.as_str() {
+            PUMP_FUN_PROGRAM | PUMP_FEE_PROGRAM => 8,
+            MOONSHOT_PROGRAM | MEMO_PROGRAM => 0,
+            _ if ix.program_id == system_program::id() => 4,
+            _ => 1,
+        };
+        Self {
+            program_id: ix.program_id,
+            discriminator: ix.data.iter().take(len).copied().collect(),
+            accounts: ix.accounts.len(),
+        }
+    }
+}
+
+/// shape of the ixs, without the compute budget ones
+pub fn tx_shape(ixs: &[Instruction]) -> Vec<IxShape> {
+    ixs.iter()
+        .filter(|ix| ix.program_id != compute_budget::id())
+        .map(IxShape::from)
+        .collect()
+}
+
+/// ComputeEstimator sets the unit limit from a simulation, once per tx
+/// shape and units_ttl, and the price from the recent fees of the written
+/// accounts
+pub struct ComputeEstimator {
+    rpc_client: Arc<RpcClient>,
+    margin_bps: u64,
+    fee_percentile: f64,
+    max_price: u64,
+    price_ttl: Duration,
+    units_ttl: Duration,
+    units: RwLock<HashMap<Vec<IxShape>, (u32, Instant)>>,
+    prices: RwLock<HashMap<Vec<Pubkey>, (u64, Instant)>>,
+    /// price of the last estimate of a shape, for cached
+    shape_prices: RwLock<HashMap<Vec<IxShape>, u64>>,
+    /// when a shape was last refreshed in the background
+    refreshed: RwLock<HashMap<Vec<IxShape>, Instant>>,
+}
+
+impl ComputeEstimator {
+    pub fn new(rpc_client: Arc<RpcClient>) -> Self {
+        Self {
+            rpc_client,
+            margin_bps: DEFAULT_MARGIN_BPS,
+            fee_percentile: DEFAULT_FEE_PERCENTILE,
+            max_price: DEFAULT_MAX_PRICE,
+            price_ttl: DEFAULT_PRICE_TTL,
+            units_ttl: DEFAULT_UNITS_TTL,
+            units: RwLock::new(HashMap::new()),
+            prices: RwLock::new(HashMap::new()),
+            shape_prices: RwLock::new(HashMap::new()),
+            refreshed: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_margin_bps(mut self, margin_bps: u64) -> Self {
+        self.margin_bps = margin_bps;
+        self
+    }
+
+    pub fn with_fee_percentile(mut self, percentile: f64) -> Self {
+        self.fee_percentile = percentile.clamp(0., 100.);
+        self
+    }
+
+    pub fn with_max_price(mut self, max_price: u64) -> Self {
+        self.max_price = max_price;
+        self
+    }
+
+    pub fn with_units_ttl(mut self, units_ttl: Duration) -> Self {
+        self.units_ttl = units_ttl;
+        self
+    }
+
+    /// units simulates the ixs with the max limit unless a tx of the same
+    /// shape was simulated within units_ttl, a buy that creates the ata is
+    /// the same shape as one that does not so the most consumed is kept
+    pub async fn units(
+        &self,
+        payer: &Pubkey,
+        ixs: &[Instruction],
+        lookup_tables: &[AddressLookupTableAccount],
+    ) -> Result<u32, Box<dyn Error>> {
+        let shape = tx_shape(ixs);
+        if let Some((units, simulated_at)) =
+            self.units.read().await.get(&shape)
+        {
+            if simulated_at.elapsed() < self.units_ttl {
+                return Ok(*units);
+            }
+        }
+
+        let mut simulated =
+            vec![ComputeBudgetInstruction::set_compute_unit_limit(
+                MAX_COMPUTE_UNITS,
+            )];
+        simulated.extend(
+            ixs.iter()
+                .filter(|ix| ix.program_id != compute_budget::id())
+                .cloned(),
+        );
+        let message = if lookup_tables.is_empty() {
+            VersionedMessage::Legacy(Message::new(&simulated, Some(payer)))
+        } else {
+            VersionedMessage::V0(v0::Message::try_compile(
+                payer,
+                &simulated,
+                lookup_tables,
+                Hash::default(),
+            )?)
+        };
+        let tx = VersionedTransaction {
+            signatures: vec![
+                Signature::default();
+                message.header().num_required_signatures as usize
+            ],
+            message,
+        };
+        let result = self
+            .rpc_client
+            .simulate_transaction_with_config(
+                &tx,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(CommitmentConfig::processed()),
+                    ..Default::default()
+                },
+            )
+            .await?
+            .value;
+        if let Some(err) = result.err {
+            return Err(format!(
+                "Simulation failed: {} {:?}",
+                err,
+                result.logs.unwrap_or_default()
+            )
+            .into());
+        }
+        let consumed = result
+            .units_consumed
+            .ok_or("Simulation did not report the units consumed")?;
+        let units = (consumed * (10_000 + self.margin_bps) / 10_000)
+            .min(MAX_COMPUTE_UNITS as u64) as u32;
+        debug!("Simulated {} units, limit {}", consumed, units);
+        let mut cached = self.units.write().await;
+        let (max_units, simulated_at) =
+            cached.entry(shape).or_insert((0, Instant::now()));
+        *max_units = (*max_units).max(units);
+        *simulated_at = Instant::now();
+        Ok(*max_units)
+    }
+
+    /// price is the fee_percentile of the recent fees paid to write the
+    /// accounts, capped at max_price
+    pub async fn price(
+        &self,
+        accounts: &[Pubkey],
+    ) -> Result<u64, Box<dyn Error>> {
+        let mut accounts = accounts.to_vec();
+        accounts.sort();
+        accounts.dedup();
+        accounts.truncate(MAX_FEE_ACCOUNTS);
+        if let Some((price, fetched_at)) =
+            self.prices.read().await.get(&accounts)
+        {
+            if fetched_at.elapsed() < self.price_ttl {
+                return Ok(*price);
+            }
+        }
+
+        let mut fees = self
+            .rpc_client
+            .get_recent_prioritization_fees(&accounts)
+            .await?
+            .into_iter()
+            .map(|fee| fee.prioritization_fee)
+            .collect::<Vec<_>>();
+        fees.sort();
+        let price = match fees.len() {
+            0 => 0,
+            len => {
+                let index = (self.fee_percentile / 100. * (len - 1) as f64)
+                    .round() as usize;
+                fees[index].min(self.max_price)
+            }
+        };
+        self.prices
+            .write()
+            .await
+            .insert(accounts, (price, Instant::now()));
+        Ok(price)
+    }
+
+    /// estimate is the budget for the ixs, the payer is left out of the
+    /// fee accounts since nobody else writes it, the tip account since it
+    /// is picked at random
+    pub async fn estimate(
+        &self,
+        payer: &Pubkey,
+        ixs: &[Instruction],
+        lookup_tables: &[AddressLookupTableAccount],
+    ) -> Result<ComputeBudget, Box<dyn Error>> {
+        let units = self.units(payer, ixs, lookup_tables).await?;
+        let written = ixs
+            .iter()
+            .flat_map(|ix| ix.accounts.iter())
+            .filter(|meta| {
+                meta.is_writable
+                    && meta.pubkey != *payer
+                    && !is_jito_tip_account(&meta.pubkey)
+            })
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        let price = self.price(&written).await?;
+        self.shape_prices.write().await.insert(tx_shape(ixs), price);
+        Ok(ComputeBudget { price, units })
+    }
+
+    /// cached is the budget of earlier estimates of the same shape, without
+    /// any rpc call, none until one was estimated
+    pub async fn cached(&self, ixs: &[Instruction]) -> Option<ComputeBudget> {
+        let shape = tx_shape(ixs);
+        let (units, _) = *self.units.read().await.get(&shape)?;
+        let price = *self.shape_prices.read().await.get(&shape)?;
+        Some(ComputeBudget { price, units })
+    }
+
+    /// refresh estimates the ixs in the background for cached, at most once
+    /// per price_ttl for a shape, failed estimates included
+    pub async fn refresh(
+        self: &Arc<Self>,
+        payer: Pubkey,
+        ixs: Vec<Instruction>,
+        lookup_tables: Vec<AddressLookupTableAccount>,
+    ) {
+        let shape = tx_shape(&ixs);
+        {
+            let mut refreshed = self.refreshed.write().await;
+            if refreshed
+                .get(&shape)
+                .is_some_and(|at| at.elapsed() < self.price_ttl)
+            {
+                return;
+            }
+            refreshed.insert(shape, Instant::now());
+        }
+        let estimator = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) =
+                estimator.estimate(&payer, &ixs, &lookup_tables).await
+            {
+                warn!("Refreshing the compute budget failed: {}", e);
+            }
+        });
+    }
+}
+
+/// make_compute_estimator simulates against RPC_URL
+pub fn make_compute_estimator() -> Arc<ComputeEstimator> {
+    Arc::new(ComputeEstimator::new(Arc::new(RpcClient::new(
+        crate::util::env("RPC_URL"),
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rpc::{MockRpcServer, RpcFixtures};
+    use solana_sdk::instruction::AccountMeta;
+    use solana_sdk::system_instruction::transfer;
+    use std::str::FromStr;
+
+    fn pump_ix(amount: u64, bonding_curve: Pubkey) -> Instruction {
+        let mut data = crate::constants::PUMP_BUY_METHOD.to_vec();
+        data.extend(amount.to_le_bytes());
+        Instruction::new_with_bytes(
+            Pubkey::from_str(PUMP_FUN_PROGRAM).unwrap(),
+            &data,
+            vec![AccountMeta::new(bonding_curve, false)],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_estimate_caches_units_per_shape() {
+        let server = MockRpcServer::start(RpcFixtures {
+            slot: 100,
+            units_consumed: 50_000,
+            prioritization_fees: vec![0, 10, 1_000, 100, 5_000_000],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let estimator = ComputeEstimator::new(Arc::new(RpcClient::new(
+            server.rpc_url.clone(),
+        )));
+        let payer = Pubkey::new_unique();
+        let bonding_curve = Pubkey::new_unique();
+
+        let budget = estimator
+            .estimate(
+                &payer,
+                &[
+                    pump_ix(1, bonding_curve),
+                    transfer(&payer, &bonding_curve, 1),
+                ],
+                &[],
+            )
+            .await
+            .unwrap();
+        // p75 of 0, 10, 100, 1000, 5M
+        assert_eq!(
+            budget,
+            ComputeBudget {
+                price: 1_000,
+                units: 60_000
+            }
+        );
+        let simulated = server.simulated_transactions().await;
+        assert_eq!(simulated.len(), 1);
+        assert_eq!(
+            simulated[0].message.instructions()[0].data,
+            ComputeBudgetInstruction::set_compute_unit_limit(
+                MAX_COMPUTE_UNITS
+            )
+            .data
+        );
+
+        // other amounts and a budget ix are the same shape
+        let mut ixs =
+            vec![ComputeBudgetInstruction::set_compute_unit_price(1)];
+        ixs.extend([
+            pump_ix(2, bonding_curve),
+            transfer(&payer, &bonding_curve, 2),
+        ]);
+        assert_eq!(estimator.units(&payer, &ixs, &[]).await.unwrap(), 60_000);
+        assert_eq!(server.simulated_transactions().await.len(), 1);
+        // a sell is not
+        let mut sell = pump_ix(1, bonding_curve);
+        sell.data[..8].copy_from_slice(&crate::constants::PUMP_SELL_METHOD);
+        estimator.units(&payer, &[sell], &[]).await.unwrap();
+        assert_eq!(server.simulated_transactions().await.len(), 2);
+
+        // the tip account is not part of the price key
+        let tip_account = crate::util::get_jito_tip_pubkey();
+        estimator
+            .estimate(&payer, &[transfer(&payer, &tip_account, 1)], &[])
+            .await
+            .unwrap();
+        assert!(estimator
+            .prices
+            .read()
+            .await
+            .keys()
+            .all(|accounts| !accounts.contains(&tip_account)));
+
+        // prices are capped
+        let estimator = estimator.with_fee_percentile(100.);
+        assert_eq!(
+            estimator.price(&[Pubkey::new_unique()]).await.unwrap(),
+            DEFAULT_MAX_PRICE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_units_keep_the_max() {
+        let server = MockRpcServer::start(RpcFixtures {
+            slot: 100,
+            units_consumed: 50_000,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let estimator = ComputeEstimator::new(Arc::new(RpcClient::new(
+            server.rpc_url.clone(),
+        )))
+        .with_units_ttl(Duration::ZERO);
+        let payer = Pubkey::new_unique();
+        let ixs = [pump_ix(1, Pubkey::new_unique())];
+
+        assert_eq!(estimator.units(&payer, &ixs, &[]).await.unwrap(), 60_000);
+        // the ata exists now, less is consumed
+        server.set_units_consumed(40_000).await;
+        assert_eq!(estimator.units(&payer, &ixs, &[]).await.unwrap(), 60_000);
+        server.set_units_consumed(100_000).await;
+        assert_eq!(
+            estimator.units(&payer, &ixs, &[]).await.unwrap(),
+            120_000
+        );
+        assert_eq!(server.simulated_transactions().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_fills_the_cache() {
+        let server = MockRpcServer::start(RpcFixtures {
+            slot: 100,
+            units_consumed: 50_000,
+            prioritization_fees: vec![1_000],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let estimator = Arc::new(ComputeEstimator::new(Arc::new(
+            RpcClient::new(server.rpc_url.clone()),
+        )));
+        let payer = Pubkey::new_unique();
+        let ixs = vec![pump_ix(1, Pubkey::new_unique())];
+
+        assert_eq!(estimator.cached(&ixs).await, None);
+        estimator.refresh(payer, ixs.clone(), vec![]).await;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while estimator.cached(&ixs).await.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("refreshed");
+        // another mint, same shape
+        let other = vec![pump_ix(2, Pubkey::new_unique())];
+        assert_eq!(
+            estimator.cached(&other).await,
+            Some(ComputeBudget {
+                price: 1_000,
+                units: 60_000
+            })
+        );
+        // refreshed within the price ttl already
+        estimator.refresh(payer, other, vec![]).await;
+        tokio::task::yield_now().await;
+        assert_eq!(server.simulated_transactions().await.len(), 1);
+    }
+}
@@ -13,6 +13,16 @@ pub const PUMP_PORTAL_WS_HOST: &str = "pumpportal.fun";
 // Addresses
 pub const JITO_TIP_PUBKEY: &str =
     "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY";
+pub const JITO_TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+];
 pub const BLOXROUTE_ADDRESS: &str =
     "HWEoBxYs7ssKuudEjzjmpfJVX7Dvi7wescFsVx2L5yoY";
 pub const PUMP_GLOBAL_ADDRESS: &str =
@@ -23,6 +33,8 @@ pub const PUMP_FUN_PROGRAM: &str =
     "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
 pub const PUMP_FUN_MINT_AUTHORITY: &str =
     "TSLvdd1pWpHVjahSpsvCXUbgwsL3JAcvokwaKt1eokM";
+pub const PUMP_FEE_PROGRAM: &str =
+    "pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ";
 pub const EVENT_AUTHORITY: &str =
     "Ce6TQqeHC9p8KetsN6JsjHK7UTZk7nasjjnr7XxXp9F1";
 pub const PUMP_BUY_METHOD: [u8; 8] =
@@ -30,10 +42,17 @@ pub const PUMP_BUY_METHOD: [u8; 8] =
 pub const PUMP_SELL_METHOD: [u8; 8] =
     [0x33, 0xe6, 0x85, 0xa4, 0x01, 0x7f, 0x83, 0xad];
 pub const PUMP_CREATE_METHOD: [u8; 8] =
-    [0x18, 0x1e, 0xc8, 0x28, 0x05, 0x00, 0x00, 0x00];
+    [0x18, 0x1e, 0xc8, 0x28, 0x05, 0x1c, 0x07, 0x77];
+pub const PUMP_INITIALIZE_METHOD: [u8; 8] =
+    [0xaf, 0xaf, 0x6d, 0x1f, 0x0d, 0x98, 0x9b, 0xed];
+pub const PUMP_SET_PARAMS_METHOD: [u8; 8] =
+    [0x1b, 0xea, 0xb2, 0x34, 0x93, 0x02, 0xbb, 0x8d];
+pub const PUMP_WITHDRAW_METHOD: [u8; 8] =
+    [0xb7, 0x12, 0x46, 0x9c, 0x94, 0x6d, 0xa1, 0x22];
 pub const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
 pub const RENT_PROGRAM: &str = "SysvarRent111111111111111111111111111111111";
 pub const ASSOCIATED_TOKEN_PROGRAM: &str =
     "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
 pub const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
+pub const MEMO_PROGRAM: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
 pub const WSOL: &str = "So11111111111111111111111111111111111111112";
@@ -0,0 +1,678 @@
+use std::error::Error;
+use std::str::FromStr;
+
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction,
+    UiInstruction, UiMessage, UiParsedInstruction,
+};
+
+use crate::constants::{
+    ASSOCIATED_TOKEN_PROGRAM, PUMP_BUY_METHOD, PUMP_CREATE_METHOD,
+    PUMP_FUN_PROGRAM, PUMP_INITIALIZE_METHOD, PUMP_SELL_METHOD,
+    PUMP_SET_PARAMS_METHOD, PUMP_WITHDRAW_METHOD, RENT_PROGRAM,
+};
+use crate::events::{get_account_keys, BorshReader};
+use crate::pump::PumpAccounts;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateAccounts {
+    pub mint: Pubkey,
+    pub bonding_curve: Pubkey,
+    pub associated_bonding_curve: Pubkey,
+    pub metadata: Pubkey,
+    pub user: Pubkey,
+}
+
+impl CreateAccounts {
+    pub fn pump_accounts(&self) -> PumpAccounts {
+        PumpAccounts {
+            mint: self.mint,
+            bonding_curve: self.bonding_curve,
+            associated_bonding_curve: self.associated_bonding_curve,
+            dev: self.user,
+            metadata: self.metadata,
+        }
+    }
+}
+
+/// TradeAccounts are shared by buys and sells, creator_vault is None for the
+/// legacy layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeAccounts {
+    pub fee_recipient: Pubkey,
+    pub mint: Pubkey,
+    pub bonding_curve: Pubkey,
+    pub associated_bonding_curve: Pubkey,
+    pub associated_user: Pubkey,
+    pub user: Pubkey,
+    pub creator_vault: Option<Pubkey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetParams {
+    pub fee_recipient: Pubkey,
+    pub initial_virtual_token_reserves: u64,
+    pub initial_virtual_sol_reserves: u64,
+    pub initial_real_token_reserves: u64,
+    pub token_total_supply: u64,
+    pub fee_basis_points: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PumpInstruction {
+    Create {
+        name: String,
+        symbol: String,
+        uri: String,
+        /// only passed as an arg by newer program versions
+        creator: Option<Pubkey>,
+        accounts: CreateAccounts,
+    },
+    Buy {
+        amount: u64,
+        max_sol_cost: u64,
+        accounts: TradeAccounts,
+    },
+    Sell {
+        amount: u64,
+        min_sol_output: u64,
+        accounts: TradeAccounts,
+    },
+    Initialize {
+        global: Pubkey,
+        user: Pubkey,
+    },
+    SetParams {
+        params: SetParams,
+        global: Pubkey,
+        user: Pubkey,
+    },
+    Withdraw {
+        mint: Pubkey,
+        bonding_curve: Pubkey,
+        user: Pubkey,
+    },
+    /// pump ix this decoder does not know about yet
+    Unknown {
+        discriminator: [u8; 8],
+    },
+}
+
+fn account(
+    accounts: &[Pubkey],
+    index: usize,
+) -> Result<Pubkey, Box<dyn Error>> {
+    accounts.get(index).copied().ok_or_else(|| {
+        format!(
+            "Missing account #{} (got {} accounts)",
+            index + 1,
+            accounts.len()
+        )
+        .into()
+    })
+}
+
+/// trade accounts, vault_index points at the account that is the creator
+/// vault in newer layouts and legacy_account in the old one
+fn trade_accounts(
+    accounts: &[Pubkey],
+    vault_index: usize,
+    legacy_account: &str,
+) -> Result<TradeAccounts, Box<dyn Error>> {
+    let vault = account(accounts, vault_index)?;
+    Ok(TradeAccounts {
+        fee_recipient: account(accounts, 1)?,
+        mint: account(accounts, 2)?,
+        bonding_curve: account(accounts, 3)?,
+        associated_bonding_curve: account(accounts, 4)?,
+        associated_user: account(accounts, 5)?,
+        user: account(accounts, 6)?,
+        creator_vault: (vault != Pubkey::from_str(legacy_account)?)
+            .then_some(vault),
+    })
+}
+
+/// decode_instruction decodes the data of a pump ix with its accounts
+/// resolved to pubkeys, in the order of the ix
+pub fn decode_instruction(
+    data: &[u8],
+    accounts: &[Pubkey],
+) -> Result<PumpInstruction, Box<dyn Error>> {
+    if data.len() < 8 {
+        return Err(format!("Instruction too short: {}", data.len()).into());
+    }
+    let discriminator: [u8; 8] = data[..8].try_into()?;
+    let mut reader = BorshReader::new(&data[8..]);
+
+    let ix = match discriminator {
+        PUMP_CREATE_METHOD => PumpInstruction::Create {
+            name: reader.string()?,
+            symbol: reader.string()?,
+            uri: reader.string()?,
+            creator: if reader.has_remaining(32) {
+                Some(reader.pubkey()?)
+            } else {
+                None
+            },
+            accounts: CreateAccounts {
+                mint: account(accounts, 0)?,
+                bonding_curve: account(accounts, 2)?,
+                associated_bonding_curve: account(accounts, 3)?,
+                metadata: account(accounts, 6)?,
+                user: account(accounts, 7)?,
+            },
+        },
+        PUMP_BUY_METHOD => PumpInstruction::Buy {
+            amount: reader.u64()?,
+            max_sol_cost: reader.u64()?,
+            accounts: trade_accounts(accounts, 9, RENT_PROGRAM)?,
+        },
+        PUMP_SELL_METHOD => PumpInstruction::Sell {
+            amount: reader.u64()?,
+            min_sol_output: reader.u64()?,
+            accounts: trade_accounts(accounts, 8, ASSOCIATED_TOKEN_PROGRAM)?,
+        },
+        PUMP_INITIALIZE_METHOD => PumpInstruction::Initialize {
+            global: account(accounts, 0)?,
+            user: account(accounts, 1)?,
+        },
+        PUMP_SET_PARAMS_METHOD => PumpInstruction::SetParams {
+            params: SetParams {
+                fee_recipient: reader.pubkey()?,
+                initial_virtual_token_reserves: reader.u64()?,
+                initial_virtual_sol_reserves: reader.u64()?,
+                initial_real_token_reserves: reader.u64()?,
+                token_total_supply: reader.u64()?,
+                fee_basis_points: reader.u64()?,
+            },
+            global: account(accounts, 0)?,
+            user: account(accounts, 1)?,
+        },
+        PUMP_WITHDRAW_METHOD => PumpInstruction::Withdraw {
+            mint: account(accounts, 2)?,
+            bonding_curve: account(accounts, 3)?,
+            user: account(accounts, 6)?,
+        },
+        _ => PumpInstruction::Unknown { discriminator },
+    };
+    Ok(ix)
+}
+
+/// resolve_account_keys returns the static keys followed by the writable and
+/// then the readonly lookup table addresses, the way the runtime loads them
+pub fn resolve_account_keys(
+    message: &VersionedMessage,
+    lookup_tables: &[AddressLookupTableAccount],
+) -> Result<Vec<Pubkey>, Box<dyn Error>> {
+    let mut account_keys = message.static_account_keys().to_vec();
+    let lookups = match message.address_table_lookups() {
+        Some(lookups) if !lookups.is_empty() => lookups,
+        _ => return Ok(account_keys),
+    };
+
+    let mut writable = vec![];
+    let mut readonly = vec![];
+    for lookup in lookups {
+        let table = lookup_tables
+            .iter()
+            .find(|table| table.key == lookup.account_key)
+            .ok_or_else(|| {
+                format!("Lookup table {} not provided", lookup.account_key)
+            })?;
+        let address =
+            |index: &u8| {
+                table.addresses.get(*index as usize).copied().ok_or_else(
+                    || {
+                        format!(
+                            "Index {} out of bounds for lookup table {}",
+                            index, table.key
+                        )
+                    },
+                )
+            };
+        for index in &lookup.writable_indexes {
+            writable.push(address(index)?);
+        }
+        for index in &lookup.readonly_indexes {
+            readonly.push(address(index)?);
+        }
+    }
+    account_keys.extend(writable);
+    account_keys.extend(readonly);
+    Ok(account_keys)
+}
+
+fn decode_message(
+    message: &VersionedMessage,
+    account_keys: &[Pubkey],
+) -> Result<Vec<PumpInstruction>, Box<dyn Error>> {
+    let pump_program = Pubkey::from_str(PUMP_FUN_PROGRAM)?;
+    let mut ixs = vec![];
+    for ix in message.instructions() {
+        if account(account_keys, ix.program_id_index as usize)?
+            != pump_program
+        {
+            continue;
+        }
+        let accounts = ix
+            .accounts
+            .iter()
+            .map(|index| account(account_keys, *index as usize))
+            .collect::<Result<Vec<_>, _>>()?;
+        ixs.push(decode_instruction(&ix.data, &accounts)?);
+    }
+    Ok(ixs)
+}
+
+/// decode_transaction decodes the top-level pump ixs of a legacy or v0
+/// transaction, v0 transactions need the lookup tables they reference, pump
+/// ixs invoked through CPI are not part of the message and are not returned
+pub fn decode_transaction(
+    tx: &VersionedTransaction,
+    lookup_tables: &[AddressLookupTableAccount],
+) -> Result<Vec<PumpInstruction>, Box<dyn Error>> {
+    let account_keys = resolve_account_keys(&tx.message, lookup_tables)?;
+    decode_message(&tx.message, &account_keys)
+}
+
+/// decode_encoded_transaction is decode_transaction for RPC responses, binary
+/// and json encodings alike, the lookup table addresses come from the meta
+pub fn decode_encoded_transaction(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<Vec<PumpInstruction>, Box<dyn Error>> {
+    let account_keys = get_account_keys(tx)
+        .iter()
+        .map(|key| Pubkey::from_str(key))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let ui_tx = match &tx.transaction.transaction {
+        EncodedTransaction::Json(ui_tx) => ui_tx,
+        encoded => {
+            let versioned_tx = encoded
+                .decode()
+                .ok_or("Failed to decode binary transaction")?;
+            return decode_message(&versioned_tx.message, &account_keys);
+        }
+    };
+
+    let pump_program = Pubkey::from_str(PUMP_FUN_PROGRAM)?;
+    let mut ixs = vec![];
+    match &ui_tx.message {
+        UiMessage::Raw(message) => {
+            for ix in &message.instructions {
+                if account(&account_keys, ix.program_id_index as usize)?
+                    != pump_program
+                {
+                    continue;
+                }
+                let accounts = ix
+                    .accounts
+                    .iter()
+                    .map(|index| account(&account_keys, *index as usize))
+                    .collect::<Result<Vec<_>, _>>()?;
+                ixs.push(decode_instruction(
+                    &bs58::decode(&ix.data).into_vec()?,
+                    &accounts,
+                )?);
+            }
+        }
+        UiMessage::Parsed(message) => {
+            for ix in &message.instructions {
+                // the rpc only fully parses programs it knows, pump is
+                // always partially decoded
+                let UiInstruction::Parsed(
+                    UiParsedInstruction::PartiallyDecoded(ix),
+                ) = ix
+                else {
+                    continue;
+                };
+                if ix.program_id != PUMP_FUN_PROGRAM {
+                    continue;
+                }
+                let accounts = ix
+                    .accounts
+                    .iter()
+                    .map(|key| Pubkey::from_str(key))
+                    .collect::<Result<Vec<_>, _>>()?;
+                ixs.push(decode_instruction(
+                    &bs58::decode(&ix.data).into_vec()?,
+                    &accounts,
+                )?);
+            }
+        }
+    }
+    Ok(ixs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global::PumpGlobal;
+    use crate::launcher::_make_create_token_ix;
+    use crate::pump::{
+        get_creator_vault, make_pump_sell_ix, make_pump_swap_ix,
+        mint_to_pump_accounts, PumpIxVersion,
+    };
+    use crate::util::make_compute_budget_ixs;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::v0;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::transaction::Transaction;
+
+    fn make_create_and_buy_tx(
+        user: &Keypair,
+        mint: &Keypair,
+    ) -> VersionedTransaction {
+        let mut pump_accounts = mint_to_pump_accounts(&mint.pubkey());
+        pump_accounts.dev = user.pubkey();
+        let ata = spl_associated_token_account::get_associated_token_address(
+            &user.pubkey(),
+            &mint.pubkey(),
+        );
+        let mut ixs = make_compute_budget_ixs(1_000, 250_000);
+        ixs.push(_make_create_token_ix(
+            "SCAMMER".to_string(),
+            "SAHIL".to_string(),
+            "https://ipfs.io/ipfs/Qme6".to_string(),
+            mint.pubkey(),
+            user.pubkey(),
+        ));
+        ixs.push(
+            make_pump_swap_ix(
+                user.pubkey(),
+                pump_accounts,
+                PumpGlobal::default().fee_recipient,
+                34_612_903_225_806,
+                1_010_000_000,
+                ata,
+                PumpIxVersion::FeeConfig,
+            )
+            .unwrap(),
+        );
+        VersionedTransaction::from(Transaction::new_signed_with_payer(
+            &ixs,
+            Some(&user.pubkey()),
+            &[user, mint],
+            Hash::default(),
+        ))
+    }
+
+    #[test]
+    fn test_decode_legacy_create_and_buy() {
+        let user = Keypair::new();
+        let mint = Keypair::new();
+        let tx = make_create_and_buy_tx(&user, &mint);
+        let ixs = decode_transaction(&tx, &[]).expect("decode");
+        assert_eq!(ixs.len(), 2);
+
+        let PumpInstruction::Create {
+            name,
+            symbol,
+            uri,
+            creator,
+            accounts,
+        } = &ixs[0]
+        else {
+            panic!("expected create, got {:?}", ixs[0]);
+        };
+        assert_eq!(name, "SCAMMER");
+        assert_eq!(symbol, "SAHIL");
+        assert_eq!(uri, "https://ipfs.io/ipfs/Qme6");
+        assert_eq!(*creator, None);
+        assert_eq!(accounts.user, user.pubkey());
+        let pump_accounts = accounts.pump_accounts();
+        let expected = mint_to_pump_accounts(&mint.pubkey());
+        assert_eq!(pump_accounts.mint, mint.pubkey());
+        assert_eq!(pump_accounts.bonding_curve, expected.bonding_curve);
+        assert_eq!(
+            pump_accounts.associated_bonding_curve,
+            expected.associated_bonding_curve
+        );
+
+        assert_eq!(
+            ixs[1],
+            PumpInstruction::Buy {
+                amount: 34_612_903_225_806,
+                max_sol_cost: 1_010_000_000,
+                accounts: TradeAccounts {
+                    fee_recipient: Pubkey::from_str(
+                        crate::constants::PUMP_FEE_ADDRESS
+                    )
+                    .unwrap(),
+                    mint: mint.pubkey(),
+                    bonding_curve: expected.bonding_curve,
+                    associated_bonding_curve: expected
+                        .associated_bonding_curve,
+                    associated_user:
+                        spl_associated_token_account::get_associated_token_address(
+                            &user.pubkey(),
+                            &mint.pubkey(),
+                        ),
+                    user: user.pubkey(),
+                    creator_vault: Some(get_creator_vault(&user.pubkey())),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_v0_sell_with_lookup_table() {
+        let user = Keypair::new();
+        let mut pump_accounts = mint_to_pump_accounts(&Pubkey::new_unique());
+        pump_accounts.dev = Pubkey::new_unique();
+        let ata = Pubkey::new_unique();
+        let sell_ix = make_pump_sell_ix(
+            user.pubkey(),
+            pump_accounts,
+            PumpGlobal::default().fee_recipient,
+            1_000_000,
+            420_000,
+            ata,
+            PumpIxVersion::Legacy,
+        )
+        .unwrap();
+
+        // everything but the signer goes through the table
+        let lookup_table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: sell_ix
+                .accounts
+                .iter()
+                .map(|meta| meta.pubkey)
+                .filter(|pubkey| *pubkey != user.pubkey())
+                .collect(),
+        };
+        let message = v0::Message::try_compile(
+            &user.pubkey(),
+            &[sell_ix],
+            std::slice::from_ref(&lookup_table),
+            Hash::default(),
+        )
+        .unwrap();
+        assert!(!message.address_table_lookups.is_empty());
+        let tx = VersionedTransaction::try_new(
+            VersionedMessage::V0(message),
+            &[&user],
+        )
+        .unwrap();
+
+        assert!(decode_transaction(&tx, &[]).is_err());
+        let ixs = decode_transaction(&tx, &[lookup_table]).expect("decode");
+        assert_eq!(ixs.len(), 1);
+        let PumpInstruction::Sell {
+            amount,
+            min_sol_output,
+            accounts,
+        } = &ixs[0]
+        else {
+            panic!("expected sell, got {:?}", ixs[0]);
+        };
+        assert_eq!(*amount, 1_000_000);
+        assert_eq!(*min_sol_output, 420_000);
+        assert_eq!(accounts.mint, pump_accounts.mint);
+        assert_eq!(accounts.bonding_curve, pump_accounts.bonding_curve);
+        assert_eq!(accounts.associated_user, ata);
+        assert_eq!(accounts.user, user.pubkey());
+        assert_eq!(accounts.creator_vault, None);
+    }
+
+    #[test]
+    fn test_decode_admin_instructions() {
+        let global = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let fee_recipient = Pubkey::new_unique();
+
+        let mut data = PUMP_SET_PARAMS_METHOD.to_vec();
+        data.extend_from_slice(fee_recipient.as_ref());
+        for value in [
+            1_073_000_000_000_000u64,
+            30_000_000_000,
+            793_100_000_000_000,
+            1_000_000_000_000_000,
+            100,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(
+            decode_instruction(&data, &[global, authority]).unwrap(),
+            PumpInstruction::SetParams {
+                params: SetParams {
+                    fee_recipient,
+                    initial_virtual_token_reserves: 1_073_000_000_000_000,
+                    initial_virtual_sol_reserves: 30_000_000_000,
+                    initial_real_token_reserves: 793_100_000_000_000,
+                    token_total_supply: 1_000_000_000_000_000,
+                    fee_basis_points: 100,
+                },
+                global,
+                user: authority,
+            }
+        );
+        assert_eq!(
+            decode_instruction(&PUMP_INITIALIZE_METHOD, &[global, authority])
+                .unwrap(),
+            PumpInstruction::Initialize {
+                global,
+                user: authority
+            }
+        );
+        assert_eq!(
+            decode_instruction(&[1; 8], &[]).unwrap(),
+            PumpInstruction::Unknown {
+                discriminator: [1; 8]
+            }
+        );
+        // truncated args and missing accounts are errors
+        assert!(
+            decode_instruction(&data[..40], &[global, authority]).is_err()
+        );
+        assert!(decode_instruction(&data, &[global]).is_err());
+    }
+
+    #[test]
+    fn test_decode_encoded_transaction() {
+        let user = Keypair::new();
+        let mint = Keypair::new();
+        let tx = make_create_and_buy_tx(&user, &mint);
+        let expected = decode_transaction(&tx, &[]).unwrap();
+
+        // base64, as returned with UiTransactionEncoding::Base64
+        let encoded: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_value(serde_json::json!({
+                "slot": 280_000_000,
+                "transaction": [
+                    base64::Engine::encode(
+                        &base64::engine::general_purpose::STANDARD,
+                        bincode::serialize(&tx).unwrap(),
+                    ),
+                    "base64"
+                ],
+                "meta": null,
+                "blockTime": null,
+            }))
+            .unwrap();
+        assert_eq!(decode_encoded_transaction(&encoded).unwrap(), expected);
+
+        // jsonParsed, pump ixs come back partially decoded
+        let keys = tx.message.static_account_keys();
+        let instructions = tx
+            .message
+            .instructions()
+            .iter()
+            .map(|ix| {
+                serde_json::json!({
+                    "programId": keys[ix.program_id_index as usize].to_string(),
+                    "accounts": ix
+                        .accounts
+                        .iter()
+                        .map(|index| keys[*index as usize].to_string())
+                        .collect::<Vec<_>>(),
+                    "data": bs58::encode(&ix.data).into_string(),
+                    "stackHeight": null,
+                })
+            })
+            .collect::<Vec<_>>();
+        let encoded: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_value(serde_json::json!({
+                "slot": 280_000_000,
+                "transaction": {
+                    "signatures": [tx.signatures[0].to_string()],
+                    "message": {
+                        "accountKeys": keys
+                            .iter()
+                            .map(|key| serde_json::json!({
+                                "pubkey": key.to_string(),
+                                "writable": false,
+                                "signer": false,
+                                "source": "transaction",
+                            }))
+                            .collect::<Vec<_>>(),
+                        "recentBlockhash": Hash::default().to_string(),
+                        "instructions": instructions,
+                    },
+                },
+                "meta": null,
+                "blockTime": null,
+            }))
+            .unwrap();
+        assert_eq!(decode_encoded_transaction(&encoded).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_decode_pump_fun_tx() {
+        let tx: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_str(include_str!(
+                "../tests/fixtures/pump_fun_tx.json"
+            ))
+            .expect("parse tx");
+        let ixs = decode_encoded_transaction(&tx).expect("decode");
+        let accounts = ixs
+            .iter()
+            .find_map(|ix| match ix {
+                PumpInstruction::Create { accounts, .. } => Some(accounts),
+                _ => None,
+            })
+            .expect("create ix");
+        assert_eq!(
+            accounts.mint.to_string(),
+            "6kPvKNrLqg23mApAvHzMKWohhVdSrA54HvrpYud8pump"
+        );
+        assert_eq!(
+            accounts.bonding_curve.to_string(),
+            "6TGz5VAFF6UpSmTSk9327utugSWJCyVeVVFXDtZnMtNp"
+        );
+        assert_eq!(
+            accounts.associated_bonding_curve.to_string(),
+            "4VwNGUif2ubbPjx4YNHmxEH7L4Yt2QFeo8uVTrVC3F68"
+        );
+        assert_eq!(
+            accounts.user.to_string(),
+            "2wgo94ZaiUNUkFBSKNaKsUgEANgSdex7gRpFKR39DPzw"
+        );
+    }
+}
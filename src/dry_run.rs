@@ -0,0 +1,241 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use log::{info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::SerializableTransaction;
+use solana_sdk::hash::hashv;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+
+use crate::sender::{SendResult, Transport, TxSender};
+
+/// set once at startup, None sends for real
+static DRY_RUN: RwLock<Option<DryRun>> = RwLock::new(None);
+
+/// DryRun signs and serializes the txs like a live send would, then logs
+/// them instead of sending
+#[derive(Debug, Clone, Default)]
+pub struct DryRun {
+    /// the txs are appended there as json lines on top of the logs
+    pub path: Option<PathBuf>,
+}
+
+impl DryRun {
+    /// record checks the txs would be accepted on the wire and returns a
+    /// made up bundle id, the same txs always get the same one
+    pub fn record(
+        &self,
+        via: &str,
+        txs: &[VersionedTransaction],
+    ) -> Result<String, Box<dyn Error>> {
+        let mut encoded = vec![];
+        let mut signatures = vec![];
+        for tx in txs {
+            let signature = *tx
+                .signatures
+                .first()
+                .ok_or("Transaction has no signatures")?;
+            if tx.verify_with_results().iter().any(|ok| !ok) {
+                return Err(format!(
+                    "Transaction {} is not signed",
+                    signature
+                )
+                .into());
+            }
+            let wire_transaction = bincode::serialize(tx)?;
+            if wire_transaction.len() > PACKET_DATA_SIZE {
+                return Err(format!(
+                    "Transaction {} is {} bytes, max {}",
+                    signature,
+                    wire_transaction.len(),
+                    PACKET_DATA_SIZE
+                )
+                .into());
+            }
+            encoded.push(BASE64.encode(&wire_transaction));
+            signatures.push(signature);
+        }
+        let bundle_id = dry_run_bundle_id(&signatures);
+        info!("Dry run through {}: {} {:?}", via, bundle_id, signatures);
+
+        if let Some(path) = &self.path {
+            let line = serde_json::json!({
+                "timestamp": chrono::Utc::now().timestamp_millis(),
+                "via": via,
+                "bundle_id": bundle_id,
+                "signatures": signatures
+                    .iter()
+                    .map(|signature| signature.to_string())
+                    .collect::<Vec<_>>(),
+                "transactions": encoded,
+            });
+            let mut file =
+                OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)?;
+        }
+        Ok(bundle_id)
+    }
+}
+
+fn dry_run_bundle_id(signatures: &[Signature]) -> String {
+    let hash = hashv(
+        &signatures
+            .iter()
+            .map(|signature| signature.as_ref())
+            .collect::<Vec<_>>(),
+    );
+    format!("dry-run-{}", hash)
+}
+
+pub fn set_dry_run(dry_run: Option<DryRun>) {
+    *DRY_RUN.write().expect("dry run") = dry_run;
+}
+
+/// get_dry_run is None unless init_dry_run turned it on
+pub fn get_dry_run() -> Option<DryRun> {
+    DRY_RUN.read().expect("dry run").clone()
+}
+
+/// init_dry_run turns the dry run on with --dry-run, DRY_RUN=1 or the
+/// dry-run feature, the file is --dry-run-file or DRY_RUN_FILE
+pub fn init_dry_run(enabled: bool, path: Option<PathBuf>) {
+    let from_env = std::env::var("DRY_RUN")
+        .map(|dry_run| matches!(dry_run.as_str(), "1" | "true"))
+        .unwrap_or(false);
+    if !(enabled || from_env || cfg!(feature = "dry-run")) {
+        return;
+    }
+    let path =
+        path.or_else(|| std::env::var("DRY_RUN_FILE").ok().map(Into::into));
+    warn!(
+        "Dry run, nothing is sent{}",
+        path.as_ref()
+            .map(|path| format!(", txs go to {}", path.display()))
+            .unwrap_or_default()
+    );
+    set_dry_run(Some(DryRun { path }));
+}
+
+/// send_and_confirm is send_and_confirm_transaction unless dry running,
+/// then the signature comes back unconfirmed
+pub async fn send_and_confirm<T>(
+    rpc_client: &RpcClient,
+    tx: &T,
+) -> Result<Signature, Box<dyn Error>>
+where
+    T: SerializableTransaction + Clone + Into<VersionedTransaction>,
+{
+    if let Some(dry_run) = get_dry_run() {
+        dry_run.record("rpc", &[tx.clone().into()])?;
+        return Ok(*tx.get_signature());
+    }
+    Ok(rpc_client.send_and_confirm_transaction(tx).await?)
+}
+
+/// DryRunSender stands in for the sender of the transport, the results
+/// look like its own with no latency
+pub struct DryRunSender {
+    transport: Transport,
+    dry_run: DryRun,
+}
+
+impl DryRunSender {
+    pub fn new(transport: Transport, dry_run: DryRun) -> Self {
+        Self { transport, dry_run }
+    }
+}
+
+#[async_trait]
+impl TxSender for DryRunSender {
+    fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    fn name(&self) -> String {
+        format!("dry-run {}", self.transport)
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>> {
+        let bundle_id = self
+            .dry_run
+            .record(&self.transport.to_string(), std::slice::from_ref(tx))?;
+        Ok(SendResult {
+            signature: tx.signatures[0],
+            bundle_id: match self.transport {
+                Transport::Rpc | Transport::Tpu => None,
+                _ => Some(bundle_id),
+            },
+            transport: self.transport,
+            latency: Duration::ZERO,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_jito::make_tx;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Keypair;
+
+    #[tokio::test]
+    async fn test_dry_run_sender() {
+        let path = std::env::temp_dir()
+            .join(format!("dry-run-{}.jsonl", Pubkey::new_unique()));
+        let dry_run = DryRun {
+            path: Some(path.clone()),
+        };
+        let payer = Keypair::new();
+        let txs = [make_tx(&payer, 1), make_tx(&payer, 2)];
+
+        let jito = DryRunSender::new(Transport::JitoHttp, dry_run.clone());
+        let res = jito.send_transaction(&txs[0]).await.unwrap();
+        assert_eq!(res.signature, txs[0].signatures[0]);
+        assert_eq!(
+            res.bundle_id,
+            Some(dry_run_bundle_id(&[txs[0].signatures[0]]))
+        );
+        let res = DryRunSender::new(Transport::Tpu, dry_run.clone())
+            .send_transaction(&txs[1])
+            .await
+            .unwrap();
+        assert_eq!(res.bundle_id, None);
+        let bundle_id = dry_run.record("jito-grpc", &txs).unwrap();
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect::<Vec<serde_json::Value>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["via"], "tpu");
+        assert_eq!(lines[2]["bundle_id"], bundle_id);
+        // the file has the wire txs, ready to replay
+        let wire_transaction = BASE64
+            .decode(lines[2]["transactions"][1].as_str().unwrap())
+            .unwrap();
+        assert_eq!(
+            bincode::deserialize::<VersionedTransaction>(&wire_transaction)
+                .unwrap(),
+            txs[1]
+        );
+
+        // a tx the network would drop is an error here too
+        let mut unsigned = make_tx(&payer, 3);
+        unsigned.signatures[0] = Signature::default();
+        let err = jito.send_transaction(&unsigned).await.unwrap_err();
+        assert!(err.to_string().contains("is not signed"));
+    }
+}
@@ -0,0 +1,859 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::SystemInstruction;
+
+use crate::constants::PUMP_FUN_PROGRAM;
+use crate::decoder::{decode_instruction, PumpInstruction, TradeAccounts};
+use crate::events::{CompleteEvent, CreateEvent, PumpEvent, TradeEvent};
+use crate::global::PumpGlobal;
+use crate::pump::{
+    get_buy_cost, get_creator_vault, get_sol_amount, mint_to_pump_accounts,
+    BondingCurveLayout, BondingCurveVersion, BONDING_CURVE_DISCRIMINATOR,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+/// PumpEmulator runs pump create/buy/sell ixs against an in-memory account
+/// store, the curve math, slippage checks, fees and completion follow the
+/// program, rent and the token program itself are not emulated
+///
+/// besides pump it handles compute budget ixs (ignored), ATA creation and
+/// system transfers, which is what the builders in this crate put in a tx
+#[derive(Debug, Clone, Default)]
+pub struct PumpEmulator {
+    pub global: PumpGlobal,
+    /// unix timestamp put in the emitted events
+    pub timestamp: i64,
+    lamports: HashMap<Pubkey, u64>,
+    token_accounts: HashMap<Pubkey, TokenAccount>,
+    /// keyed by mint
+    bonding_curves: HashMap<Pubkey, BondingCurveLayout>,
+}
+
+impl PumpEmulator {
+    pub fn new(global: PumpGlobal) -> Self {
+        Self {
+            global,
+            ..Default::default()
+        }
+    }
+
+    pub fn airdrop(&mut self, pubkey: &Pubkey, lamports: u64) {
+        *self.lamports.entry(*pubkey).or_default() += lamports;
+    }
+
+    pub fn lamports(&self, pubkey: &Pubkey) -> u64 {
+        self.lamports.get(pubkey).copied().unwrap_or_default()
+    }
+
+    pub fn token_account(&self, address: &Pubkey) -> Option<&TokenAccount> {
+        self.token_accounts.get(address)
+    }
+
+    /// token_balance of the owner's ATA, 0 if it does not exist
+    pub fn token_balance(&self, owner: &Pubkey, mint: &Pubkey) -> u64 {
+        self.token_account(
+            &spl_associated_token_account::get_associated_token_address(
+                owner, mint,
+            ),
+        )
+        .map(|account| account.amount)
+        .unwrap_or_default()
+    }
+
+    pub fn bonding_curve(
+        &self,
+        mint: &Pubkey,
+    ) -> Option<&BondingCurveLayout> {
+        self.bonding_curves.get(mint)
+    }
+
+    /// process_instructions executes the ixs as a single tx, either all of
+    /// them apply or the state is left untouched
+    pub fn process_instructions(
+        &mut self,
+        ixs: &[Instruction],
+    ) -> Result<Vec<PumpEvent>, Box<dyn Error>> {
+        let mut state = self.clone();
+        let mut events = vec![];
+        for (index, ix) in ixs.iter().enumerate() {
+            let ix_events = state
+                .process_instruction(ix)
+                .map_err(|e| format!("Instruction #{}: {}", index + 1, e))?;
+            events.extend(ix_events);
+        }
+        *self = state;
+        Ok(events)
+    }
+
+    /// process_instruction executes a single ix, the state is not rolled back
+    /// on error, use process_instructions for that
+    pub fn process_instruction(
+        &mut self,
+        ix: &Instruction,
+    ) -> Result<Vec<PumpEvent>, Box<dyn Error>> {
+        if ix.program_id == solana_sdk::compute_budget::id() {
+            return Ok(vec![]);
+        }
+        if ix.program_id == spl_associated_token_account::id() {
+            self.create_ata(ix)?;
+            return Ok(vec![]);
+        }
+        if ix.program_id == solana_sdk::system_program::id() {
+            self.system_transfer(ix)?;
+            return Ok(vec![]);
+        }
+        if ix.program_id != Pubkey::from_str(PUMP_FUN_PROGRAM)? {
+            return Err(
+                format!("Unsupported program {}", ix.program_id).into()
+            );
+        }
+
+        let accounts = ix
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        match decode_instruction(&ix.data, &accounts)? {
+            PumpInstruction::Create {
+                name,
+                symbol,
+                uri,
+                creator,
+                accounts,
+            } => {
+                require_signer(ix, &accounts.user)?;
+                require_signer(ix, &accounts.mint)?;
+                self.create(
+                    name,
+                    symbol,
+                    uri,
+                    &accounts.mint,
+                    &accounts.bonding_curve,
+                    &accounts.user,
+                    creator.unwrap_or(accounts.user),
+                )
+            }
+            PumpInstruction::Buy {
+                amount,
+                max_sol_cost,
+                accounts,
+            } => {
+                require_signer(ix, &accounts.user)?;
+                self.buy(amount, max_sol_cost, &accounts)
+            }
+            PumpInstruction::Sell {
+                amount,
+                min_sol_output,
+                accounts,
+            } => {
+                require_signer(ix, &accounts.user)?;
+                self.sell(amount, min_sol_output, &accounts)
+            }
+            PumpInstruction::SetParams { params, user, .. } => {
+                require_signer(ix, &user)?;
+                if user != self.global.authority {
+                    return Err("NotAuthorized".into());
+                }
+                self.global = PumpGlobal {
+                    fee_recipient: params.fee_recipient,
+                    initial_virtual_token_reserves: params
+                        .initial_virtual_token_reserves,
+                    initial_virtual_sol_reserves: params
+                        .initial_virtual_sol_reserves,
+                    initial_real_token_reserves: params
+                        .initial_real_token_reserves,
+                    token_total_supply: params.token_total_supply,
+                    fee_basis_points: params.fee_basis_points,
+                    ..self.global.clone()
+                };
+                Ok(vec![])
+            }
+            ix => {
+                Err(format!("Unsupported pump instruction {:?}", ix).into())
+            }
+        }
+    }
+
+    fn create_ata(&mut self, ix: &Instruction) -> Result<(), Box<dyn Error>> {
+        let [_funder, ata, wallet, mint, ..] = ix.accounts.as_slice() else {
+            return Err("Not enough accounts for ATA creation".into());
+        };
+        let idempotent = match ix.data.as_slice() {
+            [] | [0] => false,
+            [1] => true,
+            data => {
+                return Err(format!("Unsupported ATA ix {:?}", data).into())
+            }
+        };
+        if ata.pubkey
+            != spl_associated_token_account::get_associated_token_address(
+                &wallet.pubkey,
+                &mint.pubkey,
+            )
+        {
+            return Err(format!("{} is not an ATA", ata.pubkey).into());
+        }
+        if self.token_accounts.contains_key(&ata.pubkey) {
+            if idempotent {
+                return Ok(());
+            }
+            return Err(format!("{} already in use", ata.pubkey).into());
+        }
+        self.token_accounts.insert(
+            ata.pubkey,
+            TokenAccount {
+                mint: mint.pubkey,
+                owner: wallet.pubkey,
+                amount: 0,
+            },
+        );
+        Ok(())
+    }
+
+    fn system_transfer(
+        &mut self,
+        ix: &Instruction,
+    ) -> Result<(), Box<dyn Error>> {
+        let SystemInstruction::Transfer { lamports } =
+            bincode::deserialize(&ix.data)?
+        else {
+            return Err("Unsupported system instruction".into());
+        };
+        let [from, to, ..] = ix.accounts.as_slice() else {
+            return Err("Not enough accounts for transfer".into());
+        };
+        require_signer(ix, &from.pubkey)?;
+        self.debit(&from.pubkey, lamports)?;
+        self.airdrop(&to.pubkey, lamports);
+        Ok(())
+    }
+
+    fn debit(
+        &mut self,
+        pubkey: &Pubkey,
+        lamports: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        let balance = self.lamports.entry(*pubkey).or_default();
+        *balance = balance.checked_sub(lamports).ok_or_else(|| {
+            format!(
+                "Insufficient lamports in {}: {} < {}",
+                pubkey, balance, lamports
+            )
+        })?;
+        Ok(())
+    }
+
+    fn move_tokens(
+        &mut self,
+        from: &Pubkey,
+        to: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        for address in [from, to] {
+            match self.token_accounts.get(address) {
+                Some(account) if account.mint == *mint => {}
+                Some(_) => {
+                    return Err(
+                        format!("{} is for another mint", address).into()
+                    )
+                }
+                None => {
+                    return Err(
+                        format!("AccountNotInitialized: {}", address).into()
+                    )
+                }
+            }
+        }
+        let from_account = self.token_accounts.get_mut(from).unwrap();
+        from_account.amount =
+            from_account.amount.checked_sub(amount).ok_or_else(|| {
+                format!(
+                    "Insufficient tokens in {}: {} < {}",
+                    from, from_account.amount, amount
+                )
+            })?;
+        self.token_accounts.get_mut(to).unwrap().amount += amount;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        &mut self,
+        name: String,
+        symbol: String,
+        uri: String,
+        mint: &Pubkey,
+        bonding_curve: &Pubkey,
+        user: &Pubkey,
+        creator: Pubkey,
+    ) -> Result<Vec<PumpEvent>, Box<dyn Error>> {
+        let pump_accounts = mint_to_pump_accounts(mint);
+        if *bonding_curve != pump_accounts.bonding_curve {
+            return Err(format!("Invalid bonding curve for {}", mint).into());
+        }
+        if self.bonding_curves.contains_key(mint) {
+            return Err(format!("Mint {} already in use", mint).into());
+        }
+
+        let curve = BondingCurveLayout {
+            discriminator: BONDING_CURVE_DISCRIMINATOR,
+            virtual_token_reserves: self
+                .global
+                .initial_virtual_token_reserves,
+            virtual_sol_reserves: self.global.initial_virtual_sol_reserves,
+            real_token_reserves: self.global.initial_real_token_reserves,
+            real_sol_reserves: 0,
+            token_total_supply: self.global.token_total_supply,
+            complete: false,
+            creator: Some(creator),
+            version: BondingCurveVersion::V2,
+        };
+        // the whole supply is minted to the curve
+        self.token_accounts.insert(
+            pump_accounts.associated_bonding_curve,
+            TokenAccount {
+                mint: *mint,
+                owner: *bonding_curve,
+                amount: curve.token_total_supply,
+            },
+        );
+        let event = CreateEvent {
+            name,
+            symbol,
+            uri,
+            mint: *mint,
+            bonding_curve: *bonding_curve,
+            user: *user,
+            creator: Some(creator),
+            timestamp: Some(self.timestamp),
+            virtual_token_reserves: Some(curve.virtual_token_reserves),
+            virtual_sol_reserves: Some(curve.virtual_sol_reserves),
+            real_token_reserves: Some(curve.real_token_reserves),
+            token_total_supply: Some(curve.token_total_supply),
+        };
+        self.bonding_curves.insert(*mint, curve);
+        Ok(vec![PumpEvent::Create(event)])
+    }
+
+    /// curve_for_trade checks the trade accounts against the curve of the
+    /// mint and returns a copy of it to be modified
+    fn curve_for_trade(
+        &self,
+        accounts: &TradeAccounts,
+    ) -> Result<BondingCurveLayout, Box<dyn Error>> {
+        let curve = self
+            .bonding_curves
+            .get(&accounts.mint)
+            .ok_or_else(|| {
+                format!(
+                    "AccountNotInitialized: no curve for {}",
+                    accounts.mint
+                )
+            })?
+            .clone();
+        let pump_accounts = mint_to_pump_accounts(&accounts.mint);
+        if accounts.bonding_curve != pump_accounts.bonding_curve
+            || accounts.associated_bonding_curve
+                != pump_accounts.associated_bonding_curve
+        {
+            return Err(format!(
+                "Invalid curve accounts for {}",
+                accounts.mint
+            )
+            .into());
+        }
+        if accounts.fee_recipient != self.global.fee_recipient {
+            return Err(format!(
+                "NotAuthorized: fee recipient {}",
+                accounts.fee_recipient
+            )
+            .into());
+        }
+        if let (Some(vault), Some(creator)) =
+            (accounts.creator_vault, curve.creator)
+        {
+            if vault != get_creator_vault(&creator) {
+                return Err(format!("Invalid creator vault {}", vault).into());
+            }
+        }
+        if curve.complete {
+            return Err("BondingCurveComplete".into());
+        }
+        Ok(curve)
+    }
+
+    fn trade_event(
+        &self,
+        curve: &BondingCurveLayout,
+        accounts: &TradeAccounts,
+        sol_amount: u64,
+        token_amount: u64,
+        is_buy: bool,
+    ) -> PumpEvent {
+        PumpEvent::Trade(TradeEvent {
+            mint: accounts.mint,
+            sol_amount,
+            token_amount,
+            is_buy,
+            user: accounts.user,
+            timestamp: self.timestamp,
+            virtual_sol_reserves: curve.virtual_sol_reserves,
+            virtual_token_reserves: curve.virtual_token_reserves,
+            real_sol_reserves: Some(curve.real_sol_reserves),
+            real_token_reserves: Some(curve.real_token_reserves),
+        })
+    }
+
+    fn buy(
+        &mut self,
+        amount: u64,
+        max_sol_cost: u64,
+        accounts: &TradeAccounts,
+    ) -> Result<Vec<PumpEvent>, Box<dyn Error>> {
+        let mut curve = self.curve_for_trade(accounts)?;
+        // the last buy gets whatever is left on the curve
+        let token_amount = amount.min(curve.real_token_reserves);
+        if token_amount == 0 {
+            return Err("Buy of 0 tokens".into());
+        }
+        let sol_cost = get_buy_cost(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            token_amount,
+            0,
+        )?;
+        let fee = self.global.fee(sol_cost);
+        if sol_cost.saturating_add(fee) > max_sol_cost {
+            return Err(format!(
+                "TooMuchSolRequired: {} + {} fee > {}",
+                sol_cost, fee, max_sol_cost
+            )
+            .into());
+        }
+
+        self.debit(&accounts.user, sol_cost + fee)?;
+        self.airdrop(&accounts.bonding_curve, sol_cost);
+        self.airdrop(&accounts.fee_recipient, fee);
+        self.move_tokens(
+            &accounts.associated_bonding_curve,
+            &accounts.associated_user,
+            &accounts.mint,
+            token_amount,
+        )?;
+        curve.apply_buy(sol_cost, token_amount)?;
+
+        let mut events = vec![self.trade_event(
+            &curve,
+            accounts,
+            sol_cost,
+            token_amount,
+            true,
+        )];
+        if curve.real_token_reserves == 0 {
+            curve.complete = true;
+            events.push(PumpEvent::Complete(CompleteEvent {
+                user: accounts.user,
+                mint: accounts.mint,
+                bonding_curve: accounts.bonding_curve,
+                timestamp: self.timestamp,
+            }));
+        }
+        self.bonding_curves.insert(accounts.mint, curve);
+        Ok(events)
+    }
+
+    fn sell(
+        &mut self,
+        amount: u64,
+        min_sol_output: u64,
+        accounts: &TradeAccounts,
+    ) -> Result<Vec<PumpEvent>, Box<dyn Error>> {
+        let mut curve = self.curve_for_trade(accounts)?;
+        if amount == 0 {
+            return Err("Sell of 0 tokens".into());
+        }
+        let sol_output = get_sol_amount(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            amount,
+            0,
+        )?;
+        let fee = self.global.fee(sol_output);
+        if sol_output - fee < min_sol_output {
+            return Err(format!(
+                "TooLittleSolReceived: {} - {} fee < {}",
+                sol_output, fee, min_sol_output
+            )
+            .into());
+        }
+
+        self.move_tokens(
+            &accounts.associated_user,
+            &accounts.associated_bonding_curve,
+            &accounts.mint,
+            amount,
+        )?;
+        curve.apply_sell(sol_output, amount)?;
+        self.debit(&accounts.bonding_curve, sol_output)?;
+        self.airdrop(&accounts.user, sol_output - fee);
+        self.airdrop(&accounts.fee_recipient, fee);
+
+        let events = vec![
+            self.trade_event(&curve, accounts, sol_output, amount, false)
+        ];
+        self.bonding_curves.insert(accounts.mint, curve);
+        Ok(events)
+    }
+}
+
+fn require_signer(
+    ix: &Instruction,
+    pubkey: &Pubkey,
+) -> Result<(), Box<dyn Error>> {
+    if ix
+        .accounts
+        .iter()
+        .any(|meta| meta.pubkey == *pubkey && meta.is_signer)
+    {
+        Ok(())
+    } else {
+        Err(format!("Missing signature for {}", pubkey).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launcher::_make_create_token_ix;
+    use crate::pump::{
+        get_min_sol_output, get_token_amount, make_pump_sell_ix,
+        make_pump_swap_ix, PumpAccounts, PumpIxVersion,
+    };
+    use crate::util::make_compute_budget_ixs;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use spl_associated_token_account::get_associated_token_address;
+    use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+
+    const SOL: u64 = 1_000_000_000;
+
+    struct Setup {
+        emulator: PumpEmulator,
+        dev: Keypair,
+        pump_accounts: PumpAccounts,
+    }
+
+    fn make_buy_ixs(
+        user: &Pubkey,
+        pump_accounts: PumpAccounts,
+        token_amount: u64,
+        max_sol_cost: u64,
+    ) -> Vec<Instruction> {
+        let ata = get_associated_token_address(user, &pump_accounts.mint);
+        let mut ixs = make_compute_budget_ixs(1_000, 100_000);
+        ixs.push(create_associated_token_account_idempotent(
+            user,
+            user,
+            &pump_accounts.mint,
+            &spl_token::id(),
+        ));
+        ixs.push(
+            make_pump_swap_ix(
+                *user,
+                pump_accounts,
+                PumpGlobal::default().fee_recipient,
+                token_amount,
+                max_sol_cost,
+                ata,
+                PumpIxVersion::FeeConfig,
+            )
+            .unwrap(),
+        );
+        ixs
+    }
+
+    fn make_sell_ix(
+        user: &Pubkey,
+        pump_accounts: PumpAccounts,
+        token_amount: u64,
+        min_sol_output: u64,
+    ) -> Instruction {
+        make_pump_sell_ix(
+            *user,
+            pump_accounts,
+            PumpGlobal::default().fee_recipient,
+            token_amount,
+            min_sol_output,
+            get_associated_token_address(user, &pump_accounts.mint),
+            PumpIxVersion::FeeConfig,
+        )
+        .unwrap()
+    }
+
+    fn setup() -> Setup {
+        let mut emulator = PumpEmulator::new(PumpGlobal::default());
+        let dev = Keypair::new();
+        let mint = Keypair::new();
+        emulator.airdrop(&dev.pubkey(), 1_000 * SOL);
+
+        let events = emulator
+            .process_instructions(&[_make_create_token_ix(
+                "SCAMMER".to_string(),
+                "SAHIL".to_string(),
+                "https://ipfs.io/ipfs/Qme6".to_string(),
+                mint.pubkey(),
+                dev.pubkey(),
+            )])
+            .expect("create");
+        let PumpEvent::Create(create) = &events[0] else {
+            panic!("expected create event, got {:?}", events);
+        };
+        assert_eq!(create.creator, Some(dev.pubkey()));
+
+        let mut pump_accounts = mint_to_pump_accounts(&mint.pubkey());
+        pump_accounts.dev = dev.pubkey();
+        Setup {
+            emulator,
+            dev,
+            pump_accounts,
+        }
+    }
+
+    #[test]
+    fn test_create() {
+        let Setup {
+            mut emulator,
+            dev,
+            pump_accounts,
+        } = setup();
+        let global = PumpGlobal::default();
+        let curve = emulator.bonding_curve(&pump_accounts.mint).unwrap();
+        assert_eq!(
+            curve.virtual_sol_reserves,
+            global.initial_virtual_sol_reserves
+        );
+        assert_eq!(
+            curve.real_token_reserves,
+            global.initial_real_token_reserves
+        );
+        assert_eq!(curve.creator, Some(dev.pubkey()));
+        assert!(!curve.complete);
+
+        // the same mint can only be created once
+        assert!(emulator
+            .process_instructions(&[_make_create_token_ix(
+                "SCAMMER".to_string(),
+                "SAHIL".to_string(),
+                "https://ipfs.io/ipfs/Qme6".to_string(),
+                pump_accounts.mint,
+                dev.pubkey(),
+            )])
+            .is_err());
+    }
+
+    #[test]
+    fn test_buy_and_sell() {
+        let Setup {
+            mut emulator,
+            pump_accounts,
+            ..
+        } = setup();
+        let global = emulator.global.clone();
+        let user = Keypair::new();
+        emulator.airdrop(&user.pubkey(), 2 * SOL);
+
+        let token_amount = get_token_amount(
+            global.initial_virtual_sol_reserves,
+            global.initial_virtual_token_reserves,
+            Some(global.initial_real_token_reserves),
+            SOL,
+        )
+        .unwrap();
+        let events = emulator
+            .process_instructions(&make_buy_ixs(
+                &user.pubkey(),
+                pump_accounts,
+                token_amount,
+                global.apply_fee(SOL),
+            ))
+            .expect("buy");
+        let PumpEvent::Trade(trade) = &events[0] else {
+            panic!("expected trade event, got {:?}", events);
+        };
+        assert!(trade.is_buy);
+        assert_eq!(trade.token_amount, token_amount);
+        assert!(trade.sol_amount <= SOL);
+        assert_eq!(
+            emulator.token_balance(&user.pubkey(), &pump_accounts.mint),
+            token_amount
+        );
+        let spent = 2 * SOL - emulator.lamports(&user.pubkey());
+        assert_eq!(spent, global.apply_fee(trade.sol_amount));
+        assert_eq!(
+            emulator.lamports(&global.fee_recipient),
+            global.fee(trade.sol_amount)
+        );
+
+        // sell half with a 5% slippage bound
+        let curve = emulator.bonding_curve(&pump_accounts.mint).unwrap();
+        let min_sol_output = get_min_sol_output(
+            curve,
+            token_amount / 2,
+            500,
+            global.fee_basis_points,
+        )
+        .unwrap();
+        let before = emulator.lamports(&user.pubkey());
+        emulator
+            .process_instructions(&[make_sell_ix(
+                &user.pubkey(),
+                pump_accounts,
+                token_amount / 2,
+                min_sol_output,
+            )])
+            .expect("sell");
+        let received = emulator.lamports(&user.pubkey()) - before;
+        assert!(received >= min_sol_output);
+        assert_eq!(
+            emulator.token_balance(&user.pubkey(), &pump_accounts.mint),
+            token_amount - token_amount / 2
+        );
+
+        // cannot sell more than held
+        assert!(emulator
+            .process_instructions(&[make_sell_ix(
+                &user.pubkey(),
+                pump_accounts,
+                token_amount,
+                0,
+            )])
+            .is_err());
+        // buys need the signature of the user
+        let mut ixs = make_buy_ixs(&user.pubkey(), pump_accounts, 1_000, SOL);
+        ixs.last_mut().unwrap().accounts[6].is_signer = false;
+        assert!(emulator.process_instructions(&ixs).is_err());
+    }
+
+    #[test]
+    fn test_slippage() {
+        let Setup {
+            mut emulator,
+            pump_accounts,
+            ..
+        } = setup();
+        let global = emulator.global.clone();
+        let user = Keypair::new();
+        emulator.airdrop(&user.pubkey(), 2 * SOL);
+
+        let token_amount = get_token_amount(
+            global.initial_virtual_sol_reserves,
+            global.initial_virtual_token_reserves,
+            None,
+            SOL,
+        )
+        .unwrap();
+        // fee not accounted for in max_sol_cost
+        let err = emulator
+            .process_instructions(&make_buy_ixs(
+                &user.pubkey(),
+                pump_accounts,
+                token_amount,
+                SOL,
+            ))
+            .unwrap_err();
+        assert!(err.to_string().contains("TooMuchSolRequired"), "{}", err);
+        // the failed tx left no trace, not even the ATA
+        assert_eq!(emulator.lamports(&user.pubkey()), 2 * SOL);
+        assert!(emulator
+            .token_account(&get_associated_token_address(
+                &user.pubkey(),
+                &pump_accounts.mint
+            ))
+            .is_none());
+
+        emulator
+            .process_instructions(&make_buy_ixs(
+                &user.pubkey(),
+                pump_accounts,
+                token_amount,
+                global.apply_fee(SOL),
+            ))
+            .expect("buy");
+        let curve = emulator.bonding_curve(&pump_accounts.mint).unwrap();
+        let expected = get_sol_amount(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            token_amount,
+            global.fee_basis_points,
+        )
+        .unwrap();
+        let err = emulator
+            .process_instructions(&[make_sell_ix(
+                &user.pubkey(),
+                pump_accounts,
+                token_amount,
+                expected + 1,
+            )])
+            .unwrap_err();
+        assert!(err.to_string().contains("TooLittleSolReceived"), "{}", err);
+        emulator
+            .process_instructions(&[make_sell_ix(
+                &user.pubkey(),
+                pump_accounts,
+                token_amount,
+                expected,
+            )])
+            .expect("sell");
+    }
+
+    #[test]
+    fn test_complete() {
+        let Setup {
+            mut emulator,
+            dev,
+            pump_accounts,
+        } = setup();
+        let global = emulator.global.clone();
+
+        // asking for more than is left buys out the curve
+        let events = emulator
+            .process_instructions(&make_buy_ixs(
+                &dev.pubkey(),
+                pump_accounts,
+                global.initial_virtual_token_reserves - 1,
+                1_000 * SOL,
+            ))
+            .expect("buy out");
+        assert!(matches!(events.last(), Some(PumpEvent::Complete(_))));
+        let curve = emulator.bonding_curve(&pump_accounts.mint).unwrap();
+        assert!(curve.complete);
+        assert_eq!(curve.real_token_reserves, 0);
+        assert_eq!(
+            emulator.token_balance(&dev.pubkey(), &pump_accounts.mint),
+            global.initial_real_token_reserves
+        );
+        assert_eq!(
+            emulator.lamports(&pump_accounts.bonding_curve),
+            curve.real_sol_reserves
+        );
+
+        for ixs in [
+            make_buy_ixs(&dev.pubkey(), pump_accounts, 1_000, SOL),
+            vec![make_sell_ix(&dev.pubkey(), pump_accounts, 1_000, 0)],
+        ] {
+            let err = emulator.process_instructions(&ixs).unwrap_err();
+            assert!(err.to_string().contains("BondingCurveComplete"));
+        }
+    }
+}
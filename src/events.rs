@@ -0,0 +1,582 @@
+use std::error::Error;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction,
+    UiInstruction, UiMessage, UiParsedInstruction,
+};
+
+use crate::constants::PUMP_FUN_PROGRAM;
+use crate::util::{pubkey_to_string, string_to_pubkey};
+
+/// sha256("anchor:event")[..8] reversed, prefix of the emit_cpi! self-CPI
+/// instruction data
+pub const EVENT_IX_TAG: [u8; 8] =
+    [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d];
+/// sha256("event:CreateEvent")[..8]
+pub const CREATE_EVENT_DISCRIMINATOR: [u8; 8] =
+    [0x1b, 0x72, 0xa9, 0x4d, 0xde, 0xeb, 0x63, 0x76];
+/// sha256("event:TradeEvent")[..8]
+pub const TRADE_EVENT_DISCRIMINATOR: [u8; 8] =
+    [0xbd, 0xdb, 0x7f, 0xd3, 0x4e, 0xe6, 0x61, 0xee];
+/// sha256("event:CompleteEvent")[..8]
+pub const COMPLETE_EVENT_DISCRIMINATOR: [u8; 8] =
+    [0x5f, 0x72, 0x61, 0x9c, 0xd4, 0x2e, 0x98, 0x08];
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// CreateEvent is emitted on token creation, the creator, timestamp and
+/// reserves were appended in later program versions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateEvent {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub mint: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub bonding_curve: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub user: Pubkey,
+    pub creator: Option<Pubkey>,
+    pub timestamp: Option<i64>,
+    pub virtual_token_reserves: Option<u64>,
+    pub virtual_sol_reserves: Option<u64>,
+    pub real_token_reserves: Option<u64>,
+    pub token_total_supply: Option<u64>,
+}
+
+/// TradeEvent is emitted on every buy and sell, the reserves are the ones
+/// after the trade, fields appended after virtual_token_reserves are skipped
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeEvent {
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub mint: Pubkey,
+    pub sol_amount: u64,
+    pub token_amount: u64,
+    pub is_buy: bool,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub user: Pubkey,
+    pub timestamp: i64,
+    pub virtual_sol_reserves: u64,
+    pub virtual_token_reserves: u64,
+    pub real_sol_reserves: Option<u64>,
+    pub real_token_reserves: Option<u64>,
+}
+
+/// CompleteEvent is emitted by the trade that fills up the curve
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompleteEvent {
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub user: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub mint: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub bonding_curve: Pubkey,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PumpEvent {
+    Create(CreateEvent),
+    Trade(TradeEvent),
+    Complete(CompleteEvent),
+}
+
+/// BorshReader reads borsh encoded event and instruction fields one by one,
+/// optional reads return None once the data runs out (older versions)
+pub(crate) struct BorshReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> BorshReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub(crate) fn take(
+        &mut self,
+        len: usize,
+    ) -> Result<&'a [u8], Box<dyn Error>> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| {
+                format!(
+                    "Event data too short: need {} bytes at {}, got {}",
+                    len,
+                    self.offset,
+                    self.data.len()
+                )
+            })?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn has_remaining(&self, len: usize) -> bool {
+        self.data.len() - self.offset >= len
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub(crate) fn i64(&mut self) -> Result<i64, Box<dyn Error>> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.take(1)?[0] != 0)
+    }
+
+    pub(crate) fn pubkey(&mut self) -> Result<Pubkey, Box<dyn Error>> {
+        Ok(Pubkey::try_from(self.take(32)?)?)
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into()?) as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    pub(crate) fn opt_u64(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        if self.has_remaining(8) {
+            Ok(Some(self.u64()?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl CreateEvent {
+    pub fn parse(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut reader = BorshReader::new(data);
+        let mut event = Self {
+            name: reader.string()?,
+            symbol: reader.string()?,
+            uri: reader.string()?,
+            mint: reader.pubkey()?,
+            bonding_curve: reader.pubkey()?,
+            user: reader.pubkey()?,
+            ..Default::default()
+        };
+        if reader.has_remaining(32 + 8) {
+            event.creator = Some(reader.pubkey()?);
+            event.timestamp = Some(reader.i64()?);
+            event.virtual_token_reserves = reader.opt_u64()?;
+            event.virtual_sol_reserves = reader.opt_u64()?;
+            event.real_token_reserves = reader.opt_u64()?;
+            event.token_total_supply = reader.opt_u64()?;
+        }
+        Ok(event)
+    }
+}
+
+impl TradeEvent {
+    pub fn parse(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut reader = BorshReader::new(data);
+        Ok(Self {
+            mint: reader.pubkey()?,
+            sol_amount: reader.u64()?,
+            token_amount: reader.u64()?,
+            is_buy: reader.bool()?,
+            user: reader.pubkey()?,
+            timestamp: reader.i64()?,
+            virtual_sol_reserves: reader.u64()?,
+            virtual_token_reserves: reader.u64()?,
+            real_sol_reserves: reader.opt_u64()?,
+            real_token_reserves: reader.opt_u64()?,
+        })
+    }
+}
+
+impl CompleteEvent {
+    pub fn parse(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut reader = BorshReader::new(data);
+        Ok(Self {
+            user: reader.pubkey()?,
+            mint: reader.pubkey()?,
+            bonding_curve: reader.pubkey()?,
+            timestamp: reader.i64()?,
+        })
+    }
+}
+
+impl PumpEvent {
+    /// decode takes the event discriminator followed by the event data,
+    /// events other than Create, Trade and Complete yield Ok(None)
+    pub fn decode(data: &[u8]) -> Result<Option<Self>, Box<dyn Error>> {
+        if data.len() < 8 {
+            return Err(format!("Event too short: {}", data.len()).into());
+        }
+        let (discriminator, data) = data.split_at(8);
+        let event = match discriminator {
+            d if d == CREATE_EVENT_DISCRIMINATOR => {
+                Self::Create(CreateEvent::parse(data)?)
+            }
+            d if d == TRADE_EVENT_DISCRIMINATOR => {
+                Self::Trade(TradeEvent::parse(data)?)
+            }
+            d if d == COMPLETE_EVENT_DISCRIMINATOR => {
+                Self::Complete(CompleteEvent::parse(data)?)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+
+    /// from_log decodes a "Program data: <base64>" log line
+    pub fn from_log(log: &str) -> Result<Option<Self>, Box<dyn Error>> {
+        match log.strip_prefix(PROGRAM_DATA_PREFIX) {
+            Some(encoded) => Self::decode(&BASE64.decode(encoded.trim())?),
+            None => Ok(None),
+        }
+    }
+
+    /// from_cpi_data decodes the data of an emit_cpi! self-CPI instruction
+    pub fn from_cpi_data(
+        data: &[u8],
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        match data.strip_prefix(EVENT_IX_TAG.as_slice()) {
+            Some(event) => Self::decode(event),
+            None => Ok(None),
+        }
+    }
+
+    pub fn mint(&self) -> Pubkey {
+        match self {
+            Self::Create(event) => event.mint,
+            Self::Trade(event) => event.mint,
+            Self::Complete(event) => event.mint,
+        }
+    }
+}
+
+/// parse_events_from_logs decodes the "Program data:" lines emitted while
+/// the pump program is executing, data logged by other programs is skipped
+pub fn parse_events_from_logs(logs: &[String]) -> Vec<PumpEvent> {
+    let mut events = vec![];
+    let mut invoke_stack: Vec<&str> = vec![];
+    for log in logs {
+        if let Some(rest) = log.strip_prefix("Program ") {
+            let mut parts = rest.split_whitespace();
+            let program = parts.next().unwrap_or_default();
+            match parts.next() {
+                Some("invoke") => {
+                    invoke_stack.push(program);
+                    continue;
+                }
+                Some("success") | Some("failed:") => {
+                    invoke_stack.pop();
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        if invoke_stack.last() != Some(&PUMP_FUN_PROGRAM) {
+            continue;
+        }
+        match PumpEvent::from_log(log) {
+            Ok(Some(event)) => events.push(event),
+            Ok(None) => {}
+            Err(e) => {
+                debug!("Failed to decode pump event from {}: {}", log, e)
+            }
+        }
+    }
+    events
+}
+
+/// parse_events_from_tx prefers the self-CPI events, which survive log
+/// truncation, and falls back to the logs for emit! program versions
+pub fn parse_events_from_tx(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Vec<PumpEvent> {
+    let meta = match &tx.transaction.meta {
+        Some(meta) => meta,
+        None => return vec![],
+    };
+
+    let mut events = vec![];
+    if let OptionSerializer::Some(inner_instructions) =
+        &meta.inner_instructions
+    {
+        let account_keys = get_account_keys(tx);
+        for ix in inner_instructions.iter().flat_map(|ix| &ix.instructions) {
+            let (program_id, data) = match ix {
+                UiInstruction::Compiled(ix) => (
+                    account_keys
+                        .get(ix.program_id_index as usize)
+                        .map(String::as_str),
+                    &ix.data,
+                ),
+                UiInstruction::Parsed(
+                    UiParsedInstruction::PartiallyDecoded(ix),
+                ) => (Some(ix.program_id.as_str()), &ix.data),
+                UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => {
+                    continue
+                }
+            };
+            if program_id != Some(PUMP_FUN_PROGRAM) {
+                continue;
+            }
+            let data = match bs58::decode(data).into_vec() {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            match PumpEvent::from_cpi_data(&data) {
+                Ok(Some(event)) => events.push(event),
+                Ok(None) => {}
+                Err(e) => debug!("Failed to decode pump cpi event: {}", e),
+            }
+        }
+    }
+    if !events.is_empty() {
+        return events;
+    }
+
+    if let OptionSerializer::Some(logs) = &meta.log_messages {
+        return parse_events_from_logs(logs);
+    }
+    vec![]
+}
+
+/// static keys followed by the lookup table loaded ones, as indexed by
+/// compiled instructions
+pub(crate) fn get_account_keys(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Vec<String> {
+    let mut account_keys = match &tx.transaction.transaction {
+        EncodedTransaction::Json(tx) => match &tx.message {
+            UiMessage::Raw(message) => message.account_keys.clone(),
+            UiMessage::Parsed(message) => message
+                .account_keys
+                .iter()
+                .map(|key| key.pubkey.clone())
+                .collect(),
+        },
+        encoded => match encoded.decode() {
+            Some(tx) => tx
+                .message
+                .static_account_keys()
+                .iter()
+                .map(Pubkey::to_string)
+                .collect(),
+            None => vec![],
+        },
+    };
+    // parsed messages already list the loaded addresses
+    let is_parsed = matches!(
+        &tx.transaction.transaction,
+        EncodedTransaction::Json(ui_tx)
+            if matches!(ui_tx.message, UiMessage::Parsed(_))
+    );
+    if let Some(meta) = &tx.transaction.meta {
+        if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+            if !is_parsed {
+                account_keys.extend(loaded.writable.iter().cloned());
+                account_keys.extend(loaded.readonly.iter().cloned());
+            }
+        }
+    }
+    account_keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn encode_trade(is_buy: bool, trailing: &[u8]) -> Vec<u8> {
+        let mut data = TRADE_EVENT_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(
+            Pubkey::from_str("5KEDcNGebCcLptWzknqVmPRNLHfiHA9Mm2djVE26pump")
+                .unwrap()
+                .as_ref(),
+        );
+        data.extend_from_slice(&1_000_000_000u64.to_le_bytes());
+        data.extend_from_slice(&34_612_903_225_806u64.to_le_bytes());
+        data.push(is_buy as u8);
+        data.extend_from_slice(
+            Pubkey::from_str("Gizxxed4uXCzL7Q8DyALDVoEEDfMkSV7XyUNrPDnPJ9J")
+                .unwrap()
+                .as_ref(),
+        );
+        data.extend_from_slice(&1_722_000_000i64.to_le_bytes());
+        data.extend_from_slice(&31_000_000_000u64.to_le_bytes());
+        data.extend_from_slice(&1_038_387_096_774_194u64.to_le_bytes());
+        data.extend_from_slice(trailing);
+        data
+    }
+
+    fn encode_create(extended: bool) -> Vec<u8> {
+        let mut data = CREATE_EVENT_DISCRIMINATOR.to_vec();
+        for s in ["SCAMMER", "SAHIL", "https://ipfs.io/ipfs/Qme6"] {
+            data.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            data.extend_from_slice(s.as_bytes());
+        }
+        for _ in 0..3 {
+            data.extend_from_slice(Pubkey::new_unique().as_ref());
+        }
+        if extended {
+            data.extend_from_slice(Pubkey::new_unique().as_ref());
+            data.extend_from_slice(&1_722_000_000i64.to_le_bytes());
+            for value in [
+                1_073_000_000_000_000u64,
+                30_000_000_000,
+                793_100_000_000_000,
+                1_000_000_000_000_000,
+            ] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_decode_trade_event() {
+        let event = PumpEvent::decode(&encode_trade(true, &[]))
+            .unwrap()
+            .unwrap();
+        let PumpEvent::Trade(trade) = event else {
+            panic!("expected trade event, got {:?}", event);
+        };
+        assert!(trade.is_buy);
+        assert_eq!(trade.sol_amount, 1_000_000_000);
+        assert_eq!(trade.token_amount, 34_612_903_225_806);
+        assert_eq!(trade.timestamp, 1_722_000_000);
+        assert_eq!(trade.virtual_sol_reserves, 31_000_000_000);
+        assert_eq!(trade.real_sol_reserves, None);
+        assert_eq!(
+            trade.user.to_string(),
+            "Gizxxed4uXCzL7Q8DyALDVoEEDfMkSV7XyUNrPDnPJ9J"
+        );
+
+        // newer versions append real reserves, fees and the creator
+        let mut trailing = 1_000_000_000u64.to_le_bytes().to_vec();
+        trailing.extend_from_slice(&758_487_096_774_194u64.to_le_bytes());
+        trailing.extend_from_slice(&[7; 64]);
+        let event = PumpEvent::decode(&encode_trade(false, &trailing))
+            .unwrap()
+            .unwrap();
+        let PumpEvent::Trade(trade) = event else {
+            panic!("expected trade event, got {:?}", event);
+        };
+        assert!(!trade.is_buy);
+        assert_eq!(trade.real_sol_reserves, Some(1_000_000_000));
+        assert_eq!(trade.real_token_reserves, Some(758_487_096_774_194));
+    }
+
+    #[test]
+    fn test_decode_create_event() {
+        let PumpEvent::Create(create) =
+            PumpEvent::decode(&encode_create(false)).unwrap().unwrap()
+        else {
+            panic!("expected create event");
+        };
+        assert_eq!(create.symbol, "SAHIL");
+        assert_eq!(create.creator, None);
+
+        let PumpEvent::Create(create) =
+            PumpEvent::decode(&encode_create(true)).unwrap().unwrap()
+        else {
+            panic!("expected create event");
+        };
+        assert!(create.creator.is_some());
+        assert_eq!(create.timestamp, Some(1_722_000_000));
+        assert_eq!(create.virtual_sol_reserves, Some(30_000_000_000));
+        assert_eq!(create.token_total_supply, Some(1_000_000_000_000_000));
+    }
+
+    #[test]
+    fn test_decode_complete_and_unknown_events() {
+        let mut data = COMPLETE_EVENT_DISCRIMINATOR.to_vec();
+        for _ in 0..3 {
+            data.extend_from_slice(Pubkey::new_unique().as_ref());
+        }
+        data.extend_from_slice(&1_722_000_000i64.to_le_bytes());
+        assert!(matches!(
+            PumpEvent::decode(&data).unwrap(),
+            Some(PumpEvent::Complete(CompleteEvent {
+                timestamp: 1_722_000_000,
+                ..
+            }))
+        ));
+
+        // SetParamsEvent and friends are skipped
+        let set_params = [0xdf, 0xc3, 0x9f, 0xf6, 0x3e, 0x30, 0x8f, 0x83];
+        assert!(PumpEvent::decode(&set_params).unwrap().is_none());
+        // truncated events are errors
+        assert!(PumpEvent::decode(&encode_trade(true, &[])[..60]).is_err());
+    }
+
+    #[test]
+    fn test_parse_events_from_logs() {
+        let trade = BASE64.encode(encode_trade(true, &[]));
+        let logs = [
+            "Program ComputeBudget111111111111111111111111111111 invoke [1]"
+                .to_string(),
+            "Program ComputeBudget111111111111111111111111111111 success"
+                .to_string(),
+            format!("Program {} invoke [1]", PUMP_FUN_PROGRAM),
+            "Program log: Instruction: Buy".to_string(),
+            "Program 11111111111111111111111111111111 invoke [2]".to_string(),
+            // not pump, ignored even though it decodes
+            format!("Program data: {}", trade),
+            "Program 11111111111111111111111111111111 success".to_string(),
+            format!("Program data: {}", trade),
+            format!(
+                "Program {} consumed 32000 of 200000 compute units",
+                PUMP_FUN_PROGRAM
+            ),
+            format!("Program {} success", PUMP_FUN_PROGRAM),
+        ];
+        let events = parse_events_from_logs(&logs);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], PumpEvent::Trade(t) if t.is_buy));
+    }
+
+    #[test]
+    fn test_from_cpi_data() {
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend(encode_trade(false, &[]));
+        assert!(matches!(
+            PumpEvent::from_cpi_data(&data).unwrap(),
+            Some(PumpEvent::Trade(TradeEvent { is_buy: false, .. }))
+        ));
+        // a regular buy ix is not an event
+        assert!(PumpEvent::from_cpi_data(&[0x66, 0x06, 0x3d, 0x12])
+            .unwrap()
+            .is_none());
+    }
+}
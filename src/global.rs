@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+
+use crate::constants::{PUMP_FEE_ADDRESS, PUMP_GLOBAL_ADDRESS};
+use crate::pump::{calculate_fee, DEFAULT_FEE_BASIS_POINTS};
+
+/// sha256("account:Global")[..8]
+pub const PUMP_GLOBAL_DISCRIMINATOR: [u8; 8] =
+    [167, 232, 232, 177, 200, 108, 114, 127];
+
+/// the params rarely change, no need to hammer the rpc for them
+pub const PUMP_GLOBAL_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// PumpGlobal is the program-wide config stored under PUMP_GLOBAL_ADDRESS,
+/// fields that the program appended after fee_basis_points are ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PumpGlobal {
+    pub initialized: bool,
+    pub authority: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub initial_virtual_token_reserves: u64,
+    pub initial_virtual_sol_reserves: u64,
+    pub initial_real_token_reserves: u64,
+    pub token_total_supply: u64,
+    pub fee_basis_points: u64,
+}
+
+impl Default for PumpGlobal {
+    /// mainnet values as of mid-2024, only to be used before the first fetch
+    /// completes
+    fn default() -> Self {
+        Self {
+            initialized: true,
+            authority: Pubkey::default(),
+            fee_recipient: Pubkey::from_str(PUMP_FEE_ADDRESS)
+                .expect("parse fee address"),
+            initial_virtual_token_reserves: 1_073_000_000_000_000,
+            initial_virtual_sol_reserves: 30_000_000_000,
+            initial_real_token_reserves: 793_100_000_000_000,
+            token_total_supply: 1_000_000_000_000_000,
+            fee_basis_points: DEFAULT_FEE_BASIS_POINTS,
+        }
+    }
+}
+
+impl PumpGlobal {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 8 + 8 + 8 + 8 + 8;
+
+    pub fn parse(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if data.len() < Self::LEN {
+            return Err(format!("Invalid data length: {}", data.len()).into());
+        }
+        if data[..8] != PUMP_GLOBAL_DISCRIMINATOR {
+            return Err("Invalid discriminator, not a pump global".into());
+        }
+
+        let u64_at = |offset: usize| -> Result<u64, Box<dyn Error>> {
+            Ok(u64::from_le_bytes(data[offset..offset + 8].try_into()?))
+        };
+
+        Ok(Self {
+            initialized: data[8] != 0,
+            authority: Pubkey::try_from(&data[9..41])?,
+            fee_recipient: Pubkey::try_from(&data[41..73])?,
+            initial_virtual_token_reserves: u64_at(73)?,
+            initial_virtual_sol_reserves: u64_at(81)?,
+            initial_real_token_reserves: u64_at(89)?,
+            token_total_supply: u64_at(97)?,
+            fee_basis_points: u64_at(105)?,
+        })
+    }
+
+    /// fee the program charges on top of (buy) or out of (sell) lamports
+    pub fn fee(&self, lamports: u64) -> u64 {
+        calculate_fee(lamports, self.fee_basis_points).unwrap_or(u64::MAX)
+    }
+
+    /// apply_fee puts the pump fee on the lamports in
+    /// e.g. 1 sol -> 1.01 sol with 100 bps
+    pub fn apply_fee(&self, lamports: u64) -> u64 {
+        lamports.saturating_add(self.fee(lamports))
+    }
+}
+
+pub async fn get_pump_global(
+    rpc_client: &RpcClient,
+) -> Result<PumpGlobal, Box<dyn Error>> {
+    let account = rpc_client
+        .get_account_with_commitment(
+            &Pubkey::from_str(PUMP_GLOBAL_ADDRESS)?,
+            CommitmentConfig::confirmed(),
+        )
+        .await?
+        .value
+        .ok_or("Pump global account not found")?;
+
+    let global = PumpGlobal::parse(&account.data)?;
+    debug!("Parsed PumpGlobal: {:?}", global);
+    Ok(global)
+}
+
+/// update_pump_global keeps pump_global fresh, keeps the last known value if
+/// a refresh fails
+pub fn update_pump_global(
+    rpc_client: Arc<RpcClient>,
+    pump_global: Arc<RwLock<PumpGlobal>>,
+    refresh_interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(refresh_interval);
+        loop {
+            interval.tick().await;
+            let new_global = match get_pump_global(&rpc_client).await {
+                Ok(new_global) => new_global,
+                Err(e) => {
+                    error!("Failed to get pump global: {}", e);
+                    continue;
+                }
+            };
+            let mut global = pump_global.write().await;
+            if *global != new_global {
+                warn!("Pump global changed: {:?}", new_global);
+            }
+            *global = new_global;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_global_data(fee_basis_points: u64, trailing: usize) -> Vec<u8> {
+        let authority = Pubkey::new_unique();
+        let fee_recipient = Pubkey::from_str(PUMP_FEE_ADDRESS).unwrap();
+        let mut data = PUMP_GLOBAL_DISCRIMINATOR.to_vec();
+        data.push(1);
+        data.extend_from_slice(authority.as_ref());
+        data.extend_from_slice(fee_recipient.as_ref());
+        for value in [
+            1_073_000_000_000_000u64,
+            30_000_000_000,
+            793_100_000_000_000,
+            1_000_000_000_000_000,
+            fee_basis_points,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.resize(data.len() + trailing, 0);
+        data
+    }
+
+    #[test]
+    fn test_parse_pump_global() {
+        let global =
+            PumpGlobal::parse(&make_global_data(100, 0)).expect("parse");
+        assert!(global.initialized);
+        assert_eq!(
+            PumpGlobal {
+                authority: global.authority,
+                ..PumpGlobal::default()
+            },
+            global
+        );
+        assert_eq!(global.apply_fee(1_000_000_000), 1_010_000_000);
+
+        // newer program versions append fields after fee_basis_points
+        let global =
+            PumpGlobal::parse(&make_global_data(95, 64)).expect("parse");
+        assert_eq!(global.fee_basis_points, 95);
+        assert_eq!(global.fee(1_000_000_000), 9_500_000);
+    }
+
+    #[test]
+    fn test_parse_pump_global_invalid() {
+        let mut data = make_global_data(100, 0);
+        assert!(PumpGlobal::parse(&data[..PumpGlobal::LEN - 1]).is_err());
+        data[0] = 0;
+        assert!(PumpGlobal::parse(&data).is_err());
+    }
+}
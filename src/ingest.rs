@@ -0,0 +1,425 @@
+use std::error::Error;
+
+use log::{debug, warn};
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+
+use crate::decoder::{decode_transaction, PumpInstruction};
+use crate::global::PumpGlobal;
+use crate::pump::{get_buy_cost, get_reserves_after_buy};
+use crate::pump_service::CreatePumpTokenEvent;
+
+/// apply_dev_buys folds the buys of mint into the event, the reserves move
+/// the way the program would move them, the fee never enters the curve
+fn apply_dev_buys(
+    event: &mut CreatePumpTokenEvent,
+    ixs: &[PumpInstruction],
+    only_user: Option<&Pubkey>,
+) -> Result<bool, Box<dyn Error>> {
+    let mut bought = false;
+    for ix in ixs {
+        let PumpInstruction::Buy {
+            amount,
+            max_sol_cost,
+            accounts,
+        } = ix
+        else {
+            continue;
+        };
+        if accounts.mint != event.mint
+            || only_user.is_some_and(|user| *user != accounts.user)
+        {
+            continue;
+        }
+        let sol_amount = get_buy_cost(
+            event.virtual_sol_reserves,
+            event.virtual_token_reserves,
+            *amount,
+            0,
+        )?;
+        (event.virtual_sol_reserves, event.virtual_token_reserves) =
+            get_reserves_after_buy(
+                event.virtual_sol_reserves,
+                event.virtual_token_reserves,
+                sol_amount,
+                *amount,
+            )?;
+        // the ix args are whatever the dev signed, do not trust them to add
+        event.dev_bought_amount = event
+            .dev_bought_amount
+            .checked_add(*amount)
+            .ok_or("Overflow in dev bought amount")?;
+        event.dev_max_sol_cost = event
+            .dev_max_sol_cost
+            .checked_add(*max_sol_cost)
+            .ok_or("Overflow in dev max sol cost")?;
+        bought = true;
+    }
+    Ok(bought)
+}
+
+/// create_pump_token_event builds the event from the decoded pump ixs of a
+/// single tx, None if there is no create in there, any buy of the new mint in
+/// the same tx counts as the dev buy
+pub fn create_pump_token_event(
+    sig: String,
+    slot: Slot,
+    ixs: &[PumpInstruction],
+    pump_global: &PumpGlobal,
+) -> Result<Option<CreatePumpTokenEvent>, Box<dyn Error>> {
+    let Some(PumpInstruction::Create {
+        name,
+        symbol,
+        uri,
+        creator,
+        accounts,
+    }) = ixs
+        .iter()
+        .find(|ix| matches!(ix, PumpInstruction::Create { .. }))
+    else {
+        return Ok(None);
+    };
+
+    let mut event = CreatePumpTokenEvent {
+        sig,
+        slot,
+        mint: accounts.mint,
+        bounding_curve: accounts.bonding_curve,
+        associated_bounding_curve: accounts.associated_bonding_curve,
+        name: name.clone(),
+        symbol: symbol.clone(),
+        uri: uri.clone(),
+        dev_bought_amount: 0,
+        dev_max_sol_cost: 0,
+        num_dev_buy_txs: 0,
+        virtual_sol_reserves: pump_global.initial_virtual_sol_reserves,
+        virtual_token_reserves: pump_global.initial_virtual_token_reserves,
+        creator: creator.unwrap_or(accounts.user),
+    };
+    if apply_dev_buys(&mut event, ixs, None)? {
+        event.num_dev_buy_txs = event.num_dev_buy_txs.saturating_add(1);
+    }
+    Ok(Some(event))
+}
+
+/// create_pump_token_event_from_tx is create_pump_token_event for a raw
+/// legacy or v0 transaction
+pub fn create_pump_token_event_from_tx(
+    slot: Slot,
+    tx: &VersionedTransaction,
+    lookup_tables: &[AddressLookupTableAccount],
+    pump_global: &PumpGlobal,
+) -> Result<Option<CreatePumpTokenEvent>, Box<dyn Error>> {
+    let sig = tx
+        .signatures
+        .first()
+        .ok_or("Transaction has no signatures")?
+        .to_string();
+    let ixs = decode_transaction(tx, lookup_tables)?;
+    create_pump_token_event(sig, slot, &ixs, pump_global)
+}
+
+/// create_pump_token_events_from_slot goes through the txs of one slot in
+/// entry order, buys the creator sends in later txs of the same slot are
+/// added to the dev buy, txs that fail to decode are skipped
+pub fn create_pump_token_events_from_slot(
+    slot: Slot,
+    txs: &[VersionedTransaction],
+    lookup_tables: &[AddressLookupTableAccount],
+    pump_global: &PumpGlobal,
+) -> Vec<CreatePumpTokenEvent> {
+    let mut events: Vec<CreatePumpTokenEvent> = vec![];
+    for tx in txs {
+        let Some(sig) = tx.signatures.first() else {
+            continue;
+        };
+        let ixs = match decode_transaction(tx, lookup_tables) {
+            Ok(ixs) if !ixs.is_empty() => ixs,
+            Ok(_) => continue,
+            Err(e) => {
+                debug!("Skipping {}: {}", sig, e);
+                continue;
+            }
+        };
+
+        for event in events.iter_mut() {
+            let creator = event.creator;
+            match apply_dev_buys(event, &ixs, Some(&creator)) {
+                Ok(true) => {
+                    event.num_dev_buy_txs =
+                        event.num_dev_buy_txs.saturating_add(1)
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to apply dev buy {}: {}", sig, e),
+            }
+        }
+
+        match create_pump_token_event(
+            sig.to_string(),
+            slot,
+            &ixs,
+            pump_global,
+        ) {
+            Ok(Some(event)) => events.push(event),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to build create event {}: {}", sig, e),
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launcher::_make_create_token_ix;
+    use crate::pump::{
+        make_pump_swap_ix, mint_to_pump_accounts, PumpIxVersion,
+    };
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::transaction::Transaction;
+
+    fn make_buy_ix(
+        user: &Pubkey,
+        mint: &Pubkey,
+        creator: &Pubkey,
+        token_amount: u64,
+        lamports: u64,
+    ) -> Instruction {
+        let mut pump_accounts = mint_to_pump_accounts(mint);
+        pump_accounts.dev = *creator;
+        make_pump_swap_ix(
+            *user,
+            pump_accounts,
+            PumpGlobal::default().fee_recipient,
+            token_amount,
+            lamports,
+            spl_associated_token_account::get_associated_token_address(
+                user, mint,
+            ),
+            PumpIxVersion::FeeConfig,
+        )
+        .unwrap()
+    }
+
+    fn make_tx(
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> VersionedTransaction {
+        VersionedTransaction::from(Transaction::new_signed_with_payer(
+            ixs,
+            Some(&signers[0].pubkey()),
+            signers,
+            Hash::default(),
+        ))
+    }
+
+    fn make_create_tx(
+        dev: &Keypair,
+        mint: &Keypair,
+        buys: &[(u64, u64)],
+    ) -> VersionedTransaction {
+        let mut ixs = vec![_make_create_token_ix(
+            "SCAMMER".to_string(),
+            "SAHIL".to_string(),
+            "https://ipfs.io/ipfs/Qme6".to_string(),
+            mint.pubkey(),
+            dev.pubkey(),
+        )];
+        for (token_amount, lamports) in buys {
+            ixs.push(make_buy_ix(
+                &dev.pubkey(),
+                &mint.pubkey(),
+                &dev.pubkey(),
+                *token_amount,
+                *lamports,
+            ));
+        }
+        make_tx(&ixs, &[dev, mint])
+    }
+
+    #[test]
+    fn test_create_pump_token_event_from_tx() {
+        let dev = Keypair::new();
+        let mint = Keypair::new();
+        let pump_global = PumpGlobal::default();
+
+        let tx = make_create_tx(&dev, &mint, &[]);
+        let event =
+            create_pump_token_event_from_tx(420, &tx, &[], &pump_global)
+                .unwrap()
+                .expect("create event");
+        let pump_accounts = mint_to_pump_accounts(&mint.pubkey());
+        assert_eq!(event.sig, tx.signatures[0].to_string());
+        assert_eq!(event.slot, 420);
+        assert_eq!(event.mint, mint.pubkey());
+        assert_eq!(event.bounding_curve, pump_accounts.bonding_curve);
+        assert_eq!(
+            event.associated_bounding_curve,
+            pump_accounts.associated_bonding_curve
+        );
+        assert_eq!(event.name, "SCAMMER");
+        assert_eq!(event.creator, dev.pubkey());
+        assert_eq!(event.num_dev_buy_txs, 0);
+        assert_eq!(event.dev_bought_amount, 0);
+        assert_eq!(
+            event.virtual_sol_reserves,
+            pump_global.initial_virtual_sol_reserves
+        );
+        assert_eq!(
+            event.virtual_token_reserves,
+            pump_global.initial_virtual_token_reserves
+        );
+
+        // two buys in the create tx are a single dev buy tx
+        let tx = make_create_tx(
+            &dev,
+            &mint,
+            &[
+                (34_612_903_225_806, 1_010_000_000),
+                (10_000_000_000_000, 500_000_000),
+            ],
+        );
+        let event =
+            create_pump_token_event_from_tx(420, &tx, &[], &pump_global)
+                .unwrap()
+                .expect("create event");
+        assert_eq!(event.num_dev_buy_txs, 1);
+        assert_eq!(event.dev_bought_amount, 44_612_903_225_806);
+        assert_eq!(event.dev_max_sol_cost, 1_510_000_000);
+        assert_eq!(
+            event.virtual_token_reserves,
+            pump_global.initial_virtual_token_reserves - 44_612_903_225_806
+        );
+        // 1 sol in buys 34_612_903_225_806 off fresh reserves
+        let first_cost = get_buy_cost(
+            pump_global.initial_virtual_sol_reserves,
+            pump_global.initial_virtual_token_reserves,
+            34_612_903_225_806,
+            0,
+        )
+        .unwrap();
+        assert!(first_cost.abs_diff(1_000_000_000) <= 1);
+        assert!(
+            event.virtual_sol_reserves
+                > pump_global.initial_virtual_sol_reserves + first_cost
+        );
+
+        // not a create tx
+        let buy_tx = make_tx(
+            &[make_buy_ix(
+                &dev.pubkey(),
+                &mint.pubkey(),
+                &dev.pubkey(),
+                1_000,
+                1_000,
+            )],
+            &[&dev],
+        );
+        assert!(create_pump_token_event_from_tx(
+            420,
+            &buy_tx,
+            &[],
+            &pump_global
+        )
+        .unwrap()
+        .is_none());
+
+        // max sol costs that add up past u64 are an error, not a wrap
+        let tx = make_create_tx(
+            &dev,
+            &mint,
+            &[(1_000, u64::MAX), (1_000, u64::MAX)],
+        );
+        assert!(create_pump_token_event_from_tx(420, &tx, &[], &pump_global)
+            .is_err());
+    }
+
+    #[test]
+    fn test_create_pump_token_events_from_slot() {
+        let dev = Keypair::new();
+        let mint = Keypair::new();
+        let other = Keypair::new();
+        let pump_global = PumpGlobal::default();
+
+        let create_tx = make_create_tx(
+            &dev,
+            &mint,
+            &[(34_612_903_225_806, 1_010_000_000)],
+        );
+        let dev_buy_tx = make_tx(
+            &[make_buy_ix(
+                &dev.pubkey(),
+                &mint.pubkey(),
+                &dev.pubkey(),
+                10_000_000_000_000,
+                500_000_000,
+            )],
+            &[&dev],
+        );
+        let other_buy_tx = make_tx(
+            &[make_buy_ix(
+                &other.pubkey(),
+                &mint.pubkey(),
+                &dev.pubkey(),
+                10_000_000_000_000,
+                500_000_000,
+            )],
+            &[&other],
+        );
+        let unrelated_tx = make_tx(
+            &[solana_sdk::system_instruction::transfer(
+                &other.pubkey(),
+                &dev.pubkey(),
+                1,
+            )],
+            &[&other],
+        );
+
+        // a dev buy before the create does not count
+        let events = create_pump_token_events_from_slot(
+            420,
+            &[
+                dev_buy_tx.clone(),
+                unrelated_tx,
+                create_tx.clone(),
+                other_buy_tx,
+                dev_buy_tx,
+            ],
+            &[],
+            &pump_global,
+        );
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.sig, create_tx.signatures[0].to_string());
+        assert_eq!(event.num_dev_buy_txs, 2);
+        assert_eq!(event.dev_bought_amount, 44_612_903_225_806);
+        assert_eq!(event.dev_max_sol_cost, 1_510_000_000);
+
+        // same reserves as both buys landing in the create tx
+        let single = create_pump_token_event_from_tx(
+            420,
+            &make_create_tx(
+                &dev,
+                &mint,
+                &[
+                    (34_612_903_225_806, 1_010_000_000),
+                    (10_000_000_000_000, 500_000_000),
+                ],
+            ),
+            &[],
+            &pump_global,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(event.virtual_sol_reserves, single.virtual_sol_reserves);
+        assert_eq!(
+            event.virtual_token_reserves,
+            single.virtual_token_reserves
+        );
+    }
+}
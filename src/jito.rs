@@ -1,33 +1,45 @@
 use fastwebsockets::OpCode;
 use futures_util::StreamExt;
-use jito_searcher_client::get_searcher_client;
-use log::debug;
-use serde_json::json;
+use jito_searcher_client::{get_searcher_client, send_bundle_no_wait};
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::EncodableKey;
-use solana_sdk::transaction::Transaction;
-use solana_transaction_status::Encodable;
-use solana_transaction_status::EncodedTransaction;
-use solana_transaction_status::UiTransactionEncoding;
+use solana_sdk::transaction::VersionedTransaction;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 
-use jito_protos::bundle::{bundle_result, BundleResult};
 use jito_protos::searcher::searcher_service_client::SearcherServiceClient;
 use jito_protos::searcher::SubscribeBundleResultsRequest;
 use jito_searcher_client::token_authenticator::ClientInterceptor;
 use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Channel;
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::block_engine::make_regional_jito_sender;
+use crate::bundle_tracker::BundleTracker;
+use crate::dry_run::get_dry_run;
+use crate::jito_client::{
+    InflightBundleStatus, JitoClient, JITO_MAINNET_URL,
+};
+use crate::sender::jito_fanout;
+use crate::tip::TipFloor;
 use crate::util::env;
 use crate::ws::connect_to_jito_tip_websocket;
 
 pub type SearcherClient =
     SearcherServiceClient<InterceptedService<Channel, ClientInterceptor>>;
 
+pub const JITO_BUNDLES_PATH: &str = "/api/v1/bundles";
+pub const JITO_TRANSACTIONS_PATH: &str = "/api/v1/transactions";
+pub const JITO_REGIONS: [&str; 5] =
+    ["amsterdam", "ny", "frankfurt", "tokyo", "slc"];
+
+/// block engine base url of a region, e.g. "ny"
+pub fn block_engine_url(region: &str) -> String {
+    format!("https://{}.mainnet.block-engine.jito.wtf", region)
+}
+
 pub async fn make_searcher_client(
 ) -> Result<SearcherClient, Box<dyn std::error::Error>> {
     let auth_keypair =
@@ -41,6 +53,7 @@ pub async fn make_searcher_client(
 #[timed::timed(duration(printer = "info!"))]
 pub async fn start_bundle_results_listener(
     searcher_client: Arc<Mutex<SearcherClient>>,
+    tracker: Arc<BundleTracker>,
 ) {
     // keep a stream for bundle results
     // TODO hopefully this doesn't deadlock
@@ -54,75 +67,47 @@ pub async fn start_bundle_results_listener(
     // poll for bundle results
     tokio::spawn(async move {
         while let Some(res) = bundle_results_stream.next().await {
-            if let Ok(BundleResult {
-                bundle_id,
-                result: Some(result),
-            }) = res
-            {
-                match result {
-                    bundle_result::Result::Accepted(_) => {
-                        info!("Bundle {} accepted", bundle_id);
-                    }
-                    bundle_result::Result::Rejected(rejection) => {
-                        info!(
-                            "Bundle {} rejected: {:?}",
-                            bundle_id, rejection
-                        );
-                    }
-                    bundle_result::Result::Dropped(_) => {
-                        info!("Bundle {} dropped", bundle_id);
-                    }
-                    bundle_result::Result::Processed(_) => {
-                        info!("Bundle {} processed", bundle_id);
-                    }
-                    bundle_result::Result::Finalized(_) => {
-                        info!("Bundle {} finalized", bundle_id);
-                    }
-                }
+            match res {
+                Ok(result) => tracker.apply_result(result).await,
+                Err(e) => warn!("Bundle results stream error: {}", e),
             }
         }
     });
 }
 
-pub fn subscribe_tips(dynamic_tip: Arc<RwLock<u64>>) -> JoinHandle<()> {
-    tokio::spawn({
-        let dynamic_tip = dynamic_tip.clone();
-        async move {
-            let mut ws = connect_to_jito_tip_websocket()
-                .await
-                .expect("connect to jito ws");
-            while let Ok(frame) = ws.read_frame().await {
-                match frame.opcode {
-                    OpCode::Text => {
-                        if let Ok(payload) =
-                            String::from_utf8(frame.payload.to_vec())
-                        {
-                            let payload_json =
-                                &(serde_json::from_str::<serde_json::Value>(
-                                    &payload,
-                                )
-                                .expect("parse payload"))[0];
-                            let top_75th = payload_json
-                                ["landed_tips_75th_percentile"]
-                                .as_f64()
-                                .unwrap();
-                            let top_95th = payload_json
-                                ["landed_tips_95th_percentile"]
-                                .as_f64()
-                                .unwrap();
-
-                            let mut dynamic_tip = dynamic_tip.write().await;
-                            *dynamic_tip =
-                                ((top_75th * 0.95 + top_95th * 0.05) * 10e9)
-                                    as u64;
-                            info!("Updated tip to {}", dynamic_tip);
+/// subscribe_tips keeps the latest landed tips distribution in tip_floor
+pub fn subscribe_tips(
+    tip_floor: Arc<RwLock<Option<TipFloor>>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ws = connect_to_jito_tip_websocket()
+            .await
+            .expect("connect to jito ws");
+        while let Ok(frame) = ws.read_frame().await {
+            match frame.opcode {
+                OpCode::Text => {
+                    let Ok(payload) =
+                        String::from_utf8(frame.payload.to_vec())
+                    else {
+                        continue;
+                    };
+                    let floor = match TipFloor::parse(&payload) {
+                        Ok(floor) => floor,
+                        Err(e) => {
+                            warn!("Bad tip frame {}: {}", payload, e);
+                            continue;
                         }
-                    }
-                    OpCode::Ping => {
-                        info!("Received ping");
-                    }
-                    _ => {}
+                    };
+                    info!(
+                        "Updated tip floor p50: {} p75: {} p95: {}",
+                        floor.p50, floor.p75, floor.p95
+                    );
+                    *tip_floor.write().await = Some(floor);
+                }
+                OpCode::Ping => {
+                    info!("Received ping");
                 }
+                _ => {}
             }
         }
     })
@@ -130,94 +115,102 @@ pub fn subscribe_tips(dynamic_tip: Arc<RwLock<u64>>) -> JoinHandle<()> {
 
 #[timed::timed(duration(printer = "info!"))]
 pub async fn send_jito_tx(
-    tx: Transaction,
+    tx: impl Into<VersionedTransaction>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-
-    let encoded_tx = match tx.encode(UiTransactionEncoding::Binary) {
-        EncodedTransaction::LegacyBinary(b) => b,
-        _ => return Err("Failed to encode transaction".into()),
-    };
-
-    // amsterdam can be ny, tokio, frankfurt, slc
-    // pick the closest region
-    let res = client
-        .post("https://amsterdam.mainnet.block-engine.jito.wtf/api/v1/transactions")
-        .header("content-type", "application/json")
-        .json(&json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "sendTransaction",
-            "params": [encoded_tx]
-        }))
-        .send()
+    let tx = tx.into();
+    if let Some(dry_run) = get_dry_run() {
+        dry_run.record("jito-http", &[tx])?;
+        return Ok(());
+    }
+    // the closest region(s) by the probed latency
+    let sent = make_regional_jito_sender(jito_fanout())
         .await
-        .expect("send tx");
-
-    let out = res.json::<serde_json::Value>().await?;
+        .send_transaction(&tx)
+        .await?;
+    info!("{} (bundle {:?})", sent.signature, sent.bundle_id);
+    Ok(())
+}
 
-    info!(
-        "{}",
-        out["result"].as_str().unwrap_or(out.to_string().as_str())
-    );
+pub async fn _send_jito_tx(
+    block_engine_url: &str,
+    tx: impl Into<VersionedTransaction>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dry_run) = get_dry_run() {
+        dry_run.record(block_engine_url, &[tx.into()])?;
+        return Ok(());
+    }
+    let sent = JitoClient::new(block_engine_url.to_string())
+        .send_transaction(&tx.into())
+        .await?;
+    info!("{} (bundle {:?})", sent.signature, sent.bundle_id);
     Ok(())
 }
 
 #[timed::timed(duration(printer = "info!"))]
 pub async fn send_out_bundle_to_all_regions(
-    bundle: &[Transaction],
+    bundle: &[VersionedTransaction],
 ) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error>> {
-    let client = Arc::new(RwLock::new(reqwest::Client::new()));
-    let leader_regions = ["amsterdam", "ny", "frankfurt", "tokyo", "slc"];
-    let leader_urls = leader_regions
-        .iter()
-        .map(|region| {
-            format!(
-                "https://{}.mainnet.block-engine.jito.wtf/api/v1/bundles",
-                region
-            )
-        })
-        .collect::<Vec<String>>();
+    _send_out_bundle(
+        &JITO_REGIONS
+            .iter()
+            .map(|region| block_engine_url(region))
+            .collect::<Vec<String>>(),
+        bundle,
+    )
+    .await
+}
 
-    let bundle = bundle
-        .iter()
-        .map(|tx| match tx.encode(UiTransactionEncoding::Binary) {
-            EncodedTransaction::LegacyBinary(b) => b,
-            _ => panic!("impossible"),
-        })
-        .collect::<Vec<_>>();
+pub async fn _send_out_bundle(
+    block_engine_urls: &[String],
+    bundle: &[VersionedTransaction],
+) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error>> {
+    // once for all the regions, nothing to wait on
+    if let Some(dry_run) = get_dry_run() {
+        dry_run.record(&block_engine_urls.join(" "), bundle)?;
+        return Ok(vec![]);
+    }
+    let client = reqwest::Client::new();
+    let bundle = Arc::new(bundle.to_vec());
 
-    Ok(leader_urls
+    Ok(block_engine_urls
         .iter()
-        .map(|leader_url| {
-            let leader_url = leader_url.clone();
+        .map(|url| {
+            let jito_client =
+                JitoClient::with_client(url.clone(), client.clone());
             let bundle = bundle.clone();
-            let client = client.clone();
             tokio::spawn(async move {
-                let client = client.read().await;
-                let res = client
-                    .post(&leader_url)
-                    .header("content-type", "application/json")
-                    .json(&json!({
-                        "jsonrpc": "2.0",
-                        "id": 1,
-                        "method": "sendBundle",
-                        "params": [bundle]
-                    }))
-                    .send()
-                    .await
-                    .expect("send bundle");
-                let status = res.status();
-                debug!("Sent bundle to {}: {:?}", leader_url, res);
-                let body =
-                    res.json::<serde_json::Value>().await.expect("json");
-                info!("Bundle ID: {}, {}", body["result"], status);
-                debug!("response: {:?}", body);
+                match jito_client.send_bundle(&bundle).await {
+                    Ok(bundle_id) => info!(
+                        "Bundle ID: {} ({})",
+                        bundle_id,
+                        jito_client.block_engine_url()
+                    ),
+                    Err(e) => warn!(
+                        "Bundle to {} failed: {}",
+                        jito_client.block_engine_url(),
+                        e
+                    ),
+                }
             })
         })
         .collect::<Vec<_>>())
 }
 
+/// send_bundle sends the bundle through the searcher gRPC and returns its
+/// uuid, a dry run records it instead
+pub async fn send_bundle(
+    bundle: &[VersionedTransaction],
+    searcher_client: &mut SearcherClient,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(dry_run) = get_dry_run() {
+        return dry_run.record("jito-grpc", bundle);
+    }
+    Ok(send_bundle_no_wait(bundle, searcher_client)
+        .await?
+        .into_inner()
+        .uuid)
+}
+
 ///     curl https://mainnet.block-engine.jito.wtf/api/v1/bundles -X POST -H "Content-Type: application/json" -d '
 /// {
 ///   "jsonrpc": "2.0",
@@ -237,26 +230,52 @@ pub async fn send_out_bundle_to_all_regions(
 pub async fn get_bundle_status(
     bundle_id: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    let res = client
-        .post("https://mainnet.block-engine.jito.wtf/api/v1/bundles")
-        .header("content-type", "application/json")
-        .json(&json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "getInflightBundleStatuses",
-            "params": [[bundle_id]]
-        }))
-        .send()
-        .await
-        .expect("send bundle");
+    _get_bundle_status(JITO_MAINNET_URL, bundle_id).await
+}
+
+/// _get_bundle_status prints the inflight status (last 5 minutes) and the
+/// landed status if there is one
+pub async fn _get_bundle_status(
+    block_engine_url: &str,
+    bundle_id: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let jito_client = JitoClient::new(block_engine_url.to_string());
+    let bundle_ids = [bundle_id.clone()];
+    let inflight = jito_client
+        .get_inflight_bundle_statuses(&bundle_ids)
+        .await?
+        .pop();
+    let status = jito_client
+        .get_bundle_statuses(&bundle_ids)
+        .await?
+        .pop()
+        .flatten();
 
-    info!(
-        "{}, {}: {:?}",
-        bundle_id,
-        res.status(),
-        res.json::<serde_json::Value>().await?
-    );
+    println!("bundle:    {}", bundle_id);
+    match inflight {
+        Some(InflightBundleStatus {
+            status,
+            landed_slot: Some(slot),
+            ..
+        }) => println!("inflight:  {:?} in slot {}", status, slot),
+        Some(InflightBundleStatus { status, .. }) => {
+            println!("inflight:  {:?}", status)
+        }
+        None => println!("inflight:  -"),
+    }
+    match status {
+        Some(status) => {
+            println!(
+                "status:    {:?} in slot {}",
+                status.confirmation_status, status.slot
+            );
+            println!("err:       {}", status.err);
+            for signature in status.transactions {
+                println!("tx:        {}", signature);
+            }
+        }
+        None => println!("status:    not landed"),
+    }
 
     Ok(())
 }
@@ -267,6 +286,7 @@ mod tests {
     use solana_client::nonblocking::rpc_client::RpcClient;
     use solana_sdk::signer::Signer;
     use solana_sdk::system_instruction::transfer;
+    use solana_sdk::transaction::Transaction;
 
     use crate::util::{get_jito_tip_pubkey, init_logger, read_fund_keypair};
 
@@ -293,7 +313,7 @@ mod tests {
                 .await
                 .expect("latest blockhash"),
         );
-        let handles = send_out_bundle_to_all_regions(&[transaction])
+        let handles = send_out_bundle_to_all_regions(&[transaction.into()])
             .await
             .expect("send out bundle");
         join_all(handles).await;
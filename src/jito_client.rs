@@ -0,0 +1,555 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::TransactionConfirmationStatus;
+use tokio::sync::RwLock;
+
+use crate::jito::{JITO_BUNDLES_PATH, JITO_TRANSACTIONS_PATH};
+use crate::util::set_jito_tip_accounts;
+
+pub const JITO_MAINNET_URL: &str = "https://mainnet.block-engine.jito.wtf";
+
+/// tip accounts practically never change
+const TIP_ACCOUNTS_TTL: Duration = Duration::from_secs(600);
+
+static MAINNET_JITO_CLIENT: OnceLock<JitoClient> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum JitoError {
+    /// request did not go through
+    Http(reqwest::Error),
+    /// 429, the default limit is 5 requests per second per ip
+    RateLimited,
+    /// the block engine answered with a JSON-RPC error
+    Rpc { code: i64, message: String },
+    /// the response did not have the expected shape
+    Decode(String),
+    /// the request could not be built
+    Encode(String),
+    /// simulateBundle needs a Jito RPC url, see JitoClient::with_rpc_url
+    NoRpcUrl,
+}
+
+impl fmt::Display for JitoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "jito request failed: {}", e),
+            Self::RateLimited => write!(f, "jito rate limited"),
+            Self::Rpc { code, message } => {
+                write!(f, "jito rpc error {}: {}", code, message)
+            }
+            Self::Decode(e) => write!(f, "jito response decode: {}", e),
+            Self::Encode(e) => write!(f, "jito request encode: {}", e),
+            Self::NoRpcUrl => write!(f, "no jito rpc url for simulateBundle"),
+        }
+    }
+}
+
+impl std::error::Error for JitoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for JitoError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for JitoError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Decode(e.to_string())
+    }
+}
+
+impl From<bincode::Error> for JitoError {
+    fn from(e: bincode::Error) -> Self {
+        Self::Encode(e.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcErrorBody {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcErrorBody>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WithContext<T> {
+    value: T,
+}
+
+fn strings_to_signatures<'de, D>(
+    deserializer: D,
+) -> Result<Vec<Signature>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| Signature::from_str(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// SentTransaction is the result of sendTransaction, the block engine wraps
+/// the tx in a single tx bundle
+#[derive(Debug, Clone)]
+pub struct SentTransaction {
+    pub signature: Signature,
+    pub bundle_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum InflightStatus {
+    Pending,
+    Landed,
+    Failed,
+    /// unknown id or older than 5 minutes
+    Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct InflightBundleStatus {
+    pub bundle_id: String,
+    pub status: InflightStatus,
+    pub landed_slot: Option<Slot>,
+}
+
+/// BundleStatus is what getBundleStatuses returns for a landed bundle
+#[derive(Debug, Clone, Deserialize)]
+pub struct BundleStatus {
+    pub bundle_id: String,
+    #[serde(deserialize_with = "strings_to_signatures")]
+    pub transactions: Vec<Signature>,
+    pub slot: Slot,
+    pub confirmation_status: TransactionConfirmationStatus,
+    /// {"Ok": null} or the transaction error
+    pub err: Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateBundleConfig {
+    pub skip_sig_verify: bool,
+    pub replace_recent_blockhash: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SimulationSummary {
+    Succeeded,
+    #[serde(rename_all = "camelCase")]
+    Failed {
+        error: Value,
+        tx_signature: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedTransaction {
+    pub err: Option<Value>,
+    pub logs: Option<Vec<String>>,
+    pub units_consumed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedBundle {
+    pub summary: SimulationSummary,
+    pub transaction_results: Vec<SimulatedTransaction>,
+}
+
+impl SimulatedBundle {
+    pub fn succeeded(&self) -> bool {
+        self.summary == SimulationSummary::Succeeded
+    }
+}
+
+/// JitoClient talks to the block engine JSON-RPC, simulateBundle goes to a
+/// Jito RPC node instead since the block engine does not serve it
+pub struct JitoClient {
+    block_engine_url: String,
+    rpc_url: Option<String>,
+    client: reqwest::Client,
+    tip_accounts: RwLock<Option<(Instant, Vec<Pubkey>)>>,
+}
+
+impl JitoClient {
+    /// block_engine_url is the base url, e.g. block_engine_url("ny")
+    pub fn new(block_engine_url: String) -> Self {
+        Self::with_client(block_engine_url, reqwest::Client::new())
+    }
+
+    pub fn mainnet() -> Self {
+        Self::new(JITO_MAINNET_URL.to_string())
+    }
+
+    /// shared is the one mainnet client of the process, its tip accounts
+    /// cache outlives the callers
+    pub fn shared() -> &'static Self {
+        MAINNET_JITO_CLIENT.get_or_init(Self::mainnet)
+    }
+
+    pub fn with_client(
+        block_engine_url: String,
+        client: reqwest::Client,
+    ) -> Self {
+        Self {
+            block_engine_url,
+            rpc_url: None,
+            client,
+            tip_accounts: RwLock::new(None),
+        }
+    }
+
+    pub fn with_rpc_url(mut self, rpc_url: String) -> Self {
+        self.rpc_url = Some(rpc_url);
+        self
+    }
+
+    pub fn block_engine_url(&self) -> &str {
+        &self.block_engine_url
+    }
+
+    async fn _call(
+        &self,
+        url: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<(HeaderMap, Value), JitoError> {
+        let res = self
+            .client
+            .post(url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params
+            }))
+            .send()
+            .await?;
+        if res.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(JitoError::RateLimited);
+        }
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = res.text().await?;
+        let body =
+            serde_json::from_str::<RpcResponse>(&body).map_err(|e| {
+                JitoError::Decode(format!("{} ({}): {}", status, e, body))
+            })?;
+        if let Some(RpcErrorBody { code, message }) = body.error {
+            return Err(JitoError::Rpc { code, message });
+        }
+        Ok((headers, body.result.unwrap_or_default()))
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<T, JitoError> {
+        let url = format!("{}{}", self.block_engine_url, path);
+        let (_, result) = self._call(&url, method, params).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    fn encode(tx: &VersionedTransaction) -> Result<String, JitoError> {
+        Ok(BASE64.encode(bincode::serialize(tx)?))
+    }
+
+    /// send_bundle returns the bundle id
+    pub async fn send_bundle(
+        &self,
+        bundle: &[VersionedTransaction],
+    ) -> Result<String, JitoError> {
+        let encoded = bundle
+            .iter()
+            .map(Self::encode)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.call(
+            JITO_BUNDLES_PATH,
+            "sendBundle",
+            json!([encoded, { "encoding": "base64" }]),
+        )
+        .await
+    }
+
+    pub async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SentTransaction, JitoError> {
+        let url =
+            format!("{}{}", self.block_engine_url, JITO_TRANSACTIONS_PATH);
+        let (headers, result) = self
+            ._call(
+                &url,
+                "sendTransaction",
+                json!([Self::encode(tx)?, { "encoding": "base64" }]),
+            )
+            .await?;
+        let signature = result
+            .as_str()
+            .and_then(|s| Signature::from_str(s).ok())
+            .ok_or_else(|| {
+                JitoError::Decode(format!("bad signature: {}", result))
+            })?;
+        Ok(SentTransaction {
+            signature,
+            bundle_id: headers
+                .get("x-bundle-id")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string()),
+        })
+    }
+
+    /// get_bundle_statuses has None for the bundles that did not land
+    pub async fn get_bundle_statuses(
+        &self,
+        bundle_ids: &[String],
+    ) -> Result<Vec<Option<BundleStatus>>, JitoError> {
+        let res: WithContext<_> = self
+            .call(JITO_BUNDLES_PATH, "getBundleStatuses", json!([bundle_ids]))
+            .await?;
+        Ok(res.value)
+    }
+
+    /// get_inflight_bundle_statuses only knows the last 5 minutes, up to 5
+    /// ids per call
+    pub async fn get_inflight_bundle_statuses(
+        &self,
+        bundle_ids: &[String],
+    ) -> Result<Vec<InflightBundleStatus>, JitoError> {
+        let res: WithContext<_> = self
+            .call(
+                JITO_BUNDLES_PATH,
+                "getInflightBundleStatuses",
+                json!([bundle_ids]),
+            )
+            .await?;
+        Ok(res.value)
+    }
+
+    /// get_tip_accounts always asks the block engine, see tip_accounts
+    pub async fn get_tip_accounts(&self) -> Result<Vec<Pubkey>, JitoError> {
+        let tip_accounts: Vec<String> = self
+            .call(JITO_BUNDLES_PATH, "getTipAccounts", json!([]))
+            .await?;
+        tip_accounts
+            .iter()
+            .map(|s| {
+                Pubkey::from_str(s)
+                    .map_err(|e| JitoError::Decode(format!("{}: {}", s, e)))
+            })
+            .collect()
+    }
+
+    /// tip_accounts is cached for TIP_ACCOUNTS_TTL, a refresh also updates
+    /// the accounts get_jito_tip_pubkey picks from
+    pub async fn tip_accounts(&self) -> Result<Vec<Pubkey>, JitoError> {
+        if let Some((fetched_at, tip_accounts)) =
+            self.tip_accounts.read().await.as_ref()
+        {
+            if fetched_at.elapsed() < TIP_ACCOUNTS_TTL {
+                return Ok(tip_accounts.clone());
+            }
+        }
+        let tip_accounts = self.get_tip_accounts().await?;
+        set_jito_tip_accounts(tip_accounts.clone());
+        *self.tip_accounts.write().await =
+            Some((Instant::now(), tip_accounts.clone()));
+        Ok(tip_accounts)
+    }
+
+    pub async fn simulate_bundle(
+        &self,
+        bundle: &[VersionedTransaction],
+        config: SimulateBundleConfig,
+    ) -> Result<SimulatedBundle, JitoError> {
+        let rpc_url = self.rpc_url.as_ref().ok_or(JitoError::NoRpcUrl)?;
+        let encoded = bundle
+            .iter()
+            .map(Self::encode)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut config = serde_json::to_value(config)?;
+        config["preExecutionAccountsConfigs"] =
+            json!(vec![Value::Null; bundle.len()]);
+        config["postExecutionAccountsConfigs"] =
+            json!(vec![Value::Null; bundle.len()]);
+        let (_, result) = self
+            ._call(
+                rpc_url,
+                "simulateBundle",
+                json!([{ "encodedTransactions": encoded }, config]),
+            )
+            .await?;
+        let res: WithContext<_> = serde_json::from_value(result)?;
+        Ok(res.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_jito::{make_tx, BundleOutcome, MockBlockEngine};
+    use crate::util::get_jito_tip_pubkey;
+    use solana_sdk::signature::Keypair;
+
+    #[tokio::test]
+    async fn test_jito_client_bundles() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        engine.set_slot(69).await;
+        let client = JitoClient::new(engine.http_url.clone());
+        let payer = Keypair::new();
+
+        engine.script(vec![BundleOutcome::Dropped]).await;
+        let dropped =
+            client.send_bundle(&[make_tx(&payer, 1)]).await.unwrap();
+        let bundle = [make_tx(&payer, 2), make_tx(&payer, 3)];
+        let landed = client.send_bundle(&bundle).await.unwrap();
+        let unknown = "unknown".to_string();
+        let ids = [dropped.clone(), landed.clone(), unknown.clone()];
+
+        let inflight =
+            client.get_inflight_bundle_statuses(&ids).await.unwrap();
+        assert_eq!(
+            inflight,
+            vec![
+                InflightBundleStatus {
+                    bundle_id: dropped,
+                    status: InflightStatus::Failed,
+                    landed_slot: None,
+                },
+                InflightBundleStatus {
+                    bundle_id: landed.clone(),
+                    status: InflightStatus::Landed,
+                    landed_slot: Some(69),
+                },
+                InflightBundleStatus {
+                    bundle_id: unknown,
+                    status: InflightStatus::Invalid,
+                    landed_slot: None,
+                },
+            ]
+        );
+
+        let statuses = client.get_bundle_statuses(&ids).await.unwrap();
+        assert!(statuses[0].is_none() && statuses[2].is_none());
+        let status = statuses[1].as_ref().unwrap();
+        assert_eq!(status.bundle_id, landed);
+        assert_eq!(status.slot, 69);
+        assert_eq!(
+            status.transactions,
+            bundle.iter().map(|tx| tx.signatures[0]).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            status.confirmation_status,
+            TransactionConfirmationStatus::Confirmed
+        );
+
+        let tx = make_tx(&payer, 4);
+        let sent = client.send_transaction(&tx).await.unwrap();
+        assert_eq!(sent.signature, tx.signatures[0]);
+        assert_eq!(engine.transactions().await, vec![tx]);
+
+        match client.send_bundle(&[]).await {
+            Err(JitoError::Rpc { code, .. }) => assert_eq!(code, -32602),
+            res => panic!("expected rpc error, got {:?}", res),
+        }
+        assert!(matches!(
+            client
+                .simulate_bundle(&bundle, SimulateBundleConfig::default())
+                .await,
+            Err(JitoError::NoRpcUrl)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_jito_client_tip_accounts() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        let client = JitoClient::new(engine.http_url.clone());
+        let tip_accounts = client.tip_accounts().await.unwrap();
+        assert_eq!(tip_accounts.len(), 8);
+        assert!(tip_accounts.contains(&get_jito_tip_pubkey()));
+
+        // served from the cache once the engine is gone
+        drop(engine);
+        assert_eq!(client.tip_accounts().await.unwrap(), tip_accounts);
+        assert!(matches!(
+            JitoClient::new("http://127.0.0.1:1".to_string())
+                .tip_accounts()
+                .await,
+            Err(JitoError::Http(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_jito_client_simulate_bundle() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        let client = JitoClient::new(engine.http_url.clone())
+            .with_rpc_url(engine.http_url.clone());
+        let payer = Keypair::new();
+        let bundle = [make_tx(&payer, 1), make_tx(&payer, 2)];
+
+        let simulated = client
+            .simulate_bundle(&bundle, SimulateBundleConfig::default())
+            .await
+            .unwrap();
+        assert!(simulated.succeeded());
+        assert_eq!(simulated.transaction_results.len(), 2);
+        assert!(simulated.transaction_results[0].units_consumed.is_some());
+
+        let mut forged = make_tx(&payer, 3);
+        forged.signatures[0] = Signature::from([7; 64]);
+        let bundle = [make_tx(&payer, 4), forged.clone()];
+        let simulated = client
+            .simulate_bundle(&bundle, SimulateBundleConfig::default())
+            .await
+            .unwrap();
+        assert!(!simulated.succeeded());
+        assert_eq!(
+            simulated.summary,
+            SimulationSummary::Failed {
+                error: json!("SignatureFailure"),
+                tx_signature: Some(forged.signatures[0].to_string()),
+            }
+        );
+        assert_eq!(simulated.transaction_results.len(), 1);
+
+        let simulated = client
+            .simulate_bundle(
+                &bundle,
+                SimulateBundleConfig {
+                    skip_sig_verify: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(simulated.succeeded());
+    }
+}
@@ -4,10 +4,12 @@ use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use serde::{Deserialize, Serialize};
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::transaction::Transaction;
+use solana_sdk::message::{v0, VersionedMessage};
+use solana_sdk::transaction::VersionedTransaction;
 use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
 
 use crate::jito::send_jito_tx;
+use crate::lookup_table::get_lookup_table;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PlatformFee {
@@ -228,12 +230,26 @@ impl Jupiter {
             instructions.push(Self::convert_instruction_data(cleanup_ix)?);
         }
 
+        // Routes can need more accounts than fit in a legacy transaction
+        let mut lookup_tables = Vec::new();
+        for address in response.address_lookup_table_addresses {
+            lookup_tables.push(
+                get_lookup_table(&rpc_client, &Pubkey::from_str(&address)?)
+                    .await?,
+            );
+        }
+
         // Create and sign transaction
-        let mut tx = Transaction::new_with_payer(
+        let message = v0::Message::try_compile(
+            &signer.pubkey(),
             &instructions,
-            Some(&signer.pubkey()),
-        );
-        tx.sign(&[signer], recent_blockhash);
+            &lookup_tables,
+            recent_blockhash,
+        )?;
+        let tx = VersionedTransaction::try_new(
+            VersionedMessage::V0(message),
+            &[signer],
+        )?;
 
         send_jito_tx(tx).await?;
 
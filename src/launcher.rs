@@ -1,6 +1,5 @@
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use borsh::{BorshDeserialize, BorshSerialize};
-use jito_searcher_client::send_bundle_no_wait;
 use log::debug;
 use rand::Rng;
 use reqwest::Client;
@@ -12,32 +11,44 @@ use solana_sdk::{
     pubkey::Pubkey,
     signature::Keypair,
     signer::Signer,
-    system_instruction::transfer,
-    transaction::{Transaction, VersionedTransaction},
 };
 use std::error::Error;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{
+    blockhash::BlockhashCache,
+    compute::{ComputeBudget, ComputeEstimator},
     constants::{
-        ASSOCIATED_TOKEN_PROGRAM, EVENT_AUTHORITY, PUMP_FUN_MINT_AUTHORITY,
-        PUMP_FUN_PROGRAM, PUMP_GLOBAL_ADDRESS, RENT_PROGRAM,
-        SYSTEM_PROGRAM_ID, TOKEN_PROGRAM,
+        ASSOCIATED_TOKEN_PROGRAM, EVENT_AUTHORITY, PUMP_CREATE_METHOD,
+        PUMP_FUN_MINT_AUTHORITY, PUMP_FUN_PROGRAM, PUMP_GLOBAL_ADDRESS,
+        RENT_PROGRAM, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM,
     },
+    global::{get_pump_global, PumpGlobal},
     jito::{self, send_jito_tx, SearcherClient},
-    pump::{get_bonding_curve, get_token_amount, BondingCurveLayout},
-    util::{env, get_jito_tip_pubkey},
+    pump::{
+        get_bonding_curve, get_reserves_after_buy, get_token_amount,
+        BondingCurveLayout, PumpAccounts, PumpIxVersion,
+    },
+    tx_builder::PumpTxBuilder,
+    util::env,
     wallet::WalletManager,
 };
-use crate::{pump::_make_buy_ixs, util::make_compute_budget_ixs};
 
 pub const MPL_TOKEN_METADATA: &str =
     "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
 // this might be derived
 pub const METADATA: &str = "GgrH3ApmK1SYJVZNEuUavbZQx4Yt8WoBz3tkRuLKwj45";
 
-pub const DEFAULT_SOL_INITIAL_RESERVES: u64 = 30_000_000_000;
-pub const DEFAULT_TOKEN_INITIAL_RESERVES: u64 = 1_073_000_000_000_000;
+/// the ladder waits this long for a hash other than the create tx one
+pub const NEW_BLOCKHASH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// the create tx budget when the estimate fails
+pub const LAUNCH_COMPUTE_BUDGET: ComputeBudget = ComputeBudget {
+    price: 542_850,
+    units: 250_000,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IPFSMetaForm {
@@ -91,7 +102,7 @@ pub struct PumpCreateTokenIx {
 impl PumpCreateTokenIx {
     pub fn new(name: String, symbol: String, uri: String) -> Self {
         Self {
-            method_id: [0x18, 0x1e, 0xc8, 0x28, 0x05, 0x1c, 0x07, 0x77],
+            method_id: PUMP_CREATE_METHOD,
             name,
             symbol,
             uri,
@@ -177,8 +188,11 @@ pub struct PoolState {
     pub mint: Pubkey,
     pub bonding_curve: Pubkey,
     pub associated_bonding_curve: Pubkey,
+    /// default if the curve predates the creator field
+    pub creator: Pubkey,
     pub virtual_sol_reserves: u64,
     pub virtual_token_reserves: u64,
+    pub version: PumpIxVersion,
 }
 
 impl PoolState {
@@ -186,13 +200,18 @@ impl PoolState {
         mint: Pubkey,
         bonding_curve: Pubkey,
         associated_bonding_curve: Pubkey,
+        creator: Pubkey,
+        pump_global: &PumpGlobal,
     ) -> Self {
         Self {
             mint,
             bonding_curve,
             associated_bonding_curve,
-            virtual_sol_reserves: DEFAULT_SOL_INITIAL_RESERVES,
-            virtual_token_reserves: DEFAULT_TOKEN_INITIAL_RESERVES,
+            creator,
+            virtual_sol_reserves: pump_global.initial_virtual_sol_reserves,
+            virtual_token_reserves: pump_global
+                .initial_virtual_token_reserves,
+            version: PumpIxVersion::resolve(None),
         }
     }
 
@@ -206,10 +225,44 @@ impl PoolState {
             mint,
             bonding_curve,
             associated_bonding_curve,
+            creator: layout.creator.unwrap_or_default(),
             virtual_sol_reserves: layout.virtual_sol_reserves,
             virtual_token_reserves: layout.virtual_token_reserves,
+            version: PumpIxVersion::resolve(Some(layout)),
         }
     }
+
+    pub fn pump_accounts(&self) -> PumpAccounts {
+        PumpAccounts {
+            mint: self.mint,
+            bonding_curve: self.bonding_curve,
+            associated_bonding_curve: self.associated_bonding_curve,
+            dev: self.creator,
+            metadata: Pubkey::default(),
+        }
+    }
+
+    /// apply_buy quotes the tokens for lamports (fee excluded) against the
+    /// current reserves and moves the pool past the buy, returns the quote
+    pub fn apply_buy(
+        &mut self,
+        lamports: u64,
+    ) -> Result<u64, Box<dyn Error>> {
+        let token_amount = get_token_amount(
+            self.virtual_sol_reserves,
+            self.virtual_token_reserves,
+            None,
+            lamports,
+        )?;
+        (self.virtual_sol_reserves, self.virtual_token_reserves) =
+            get_reserves_after_buy(
+                self.virtual_sol_reserves,
+                self.virtual_token_reserves,
+                lamports,
+                token_amount,
+            )?;
+        Ok(token_amount)
+    }
 }
 
 pub async fn fetch_pool_state(
@@ -231,8 +284,8 @@ pub async fn fetch_pool_state(
 }
 
 async fn ladder_buys(
-    mint: Pubkey,
     pool_state: &mut PoolState,
+    pump_global: &PumpGlobal,
     wallet_manager: &WalletManager,
     snipe_buy: u64,
     latest_blockhash: Hash,
@@ -242,36 +295,16 @@ async fn ladder_buys(
     // let mut second_buy_bundle = vec![];
     for wallet in wallet_manager.wallets.iter() {
         let lamports_amount = jittered_lamports_amount(snipe_buy);
-        let token_amount = get_token_amount(
-            pool_state.virtual_sol_reserves,
-            pool_state.virtual_token_reserves,
-            None,
-            lamports_amount,
-        )?;
-        let ixs = _make_buy_ixs(
-            wallet.pubkey(),
-            mint,
-            pool_state.bonding_curve,
-            pool_state.associated_bonding_curve,
-            token_amount,
-            apply_fee(lamports_amount),
-        )?;
-        // if i == 4 || i == 9 {
-        //     ixs.push(transfer(
-        //         &wallet.pubkey(),
-        //         &get_jito_tip_pubkey(),
-        //         25000,
-        //     ));
-        // }
-        let buy_tx = Transaction::new_signed_with_payer(
-            &ixs,
-            Some(&wallet.pubkey()),
-            &[wallet],
-            latest_blockhash,
-        );
-        pool_state.virtual_sol_reserves += lamports_amount;
-        pool_state.virtual_token_reserves -= token_amount;
-
+        let token_amount = pool_state.apply_buy(lamports_amount)?;
+        let buy_tx = PumpTxBuilder::new(wallet.pubkey())
+            .buy(
+                pool_state.pump_accounts(),
+                pump_global.fee_recipient,
+                token_amount,
+                pump_global.apply_fee(lamports_amount),
+                pool_state.version,
+            )?
+            .build(&[wallet], latest_blockhash)?;
         send_jito_tx(buy_tx).await?;
 
         // if i < 5 {
@@ -283,13 +316,6 @@ async fn ladder_buys(
         // }
     }
 
-    #[cfg(feature = "dry-run")]
-    {
-        info!("first_buy_bundle: {:#?}", first_buy_bundle);
-        info!("second_buy_bundle: {:#?}", second_buy_bundle);
-        return Ok(());
-    }
-
     // #[cfg(not(feature = "dry-run"))]
     // {
     //     tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
@@ -315,6 +341,7 @@ pub async fn launch(
     dev_buy: Option<u64>, // lamports
     wallet_manager: Option<&WalletManager>,
     snipe_buy: Option<u64>,
+    blockhash_cache: &BlockhashCache,
 ) -> Result<(), Box<dyn Error>> {
     if wallet_manager.is_some() && snipe_buy.is_none() {
         return Err("snipe_buy must be set if wallet_manager is set".into());
@@ -333,10 +360,6 @@ pub async fn launch(
         }
     }
     let mut searcher_client = jito::make_searcher_client().await?;
-    let mut ixs = vec![];
-
-    // Add compute budget instructions
-    ixs.append(&mut make_compute_budget_ixs(542850, 250000));
 
     let image = if let Some(image_path) = image_path {
         std::fs::read(image_path)?
@@ -349,72 +372,66 @@ pub async fn launch(
         push_meta_to_pump_ipfs(&client, ipfs_meta, image).await?;
     let (mint, mint_signer) = generate_mint();
 
-    ixs.push(_make_create_token_ix(
-        ipfs_meta.name.clone(),
-        ipfs_meta.symbol.clone(),
-        metadata_uri,
-        mint,
-        signer.pubkey(),
-    ));
+    // static tip of 50000 lamports for the launch
+    let mut builder = PumpTxBuilder::new(signer.pubkey())
+        .instruction(_make_create_token_ix(
+            ipfs_meta.name.clone(),
+            ipfs_meta.symbol.clone(),
+            metadata_uri,
+            mint,
+            signer.pubkey(),
+        ))
+        .tip(50000);
 
+    let rpc_client = Arc::new(RpcClient::new(env("RPC_URL")));
+    let pump_global = get_pump_global(&rpc_client).await?;
     let (bonding_curve, associated_bonding_curve) = get_bc_and_abc(mint);
-    let mut pool_state =
-        PoolState::new(mint, bonding_curve, associated_bonding_curve);
+    let mut pool_state = PoolState::new(
+        mint,
+        bonding_curve,
+        associated_bonding_curve,
+        signer.pubkey(),
+        &pump_global,
+    );
 
     if let Some(dev_buy) = dev_buy {
-        let token_amount = get_token_amount(
-            DEFAULT_SOL_INITIAL_RESERVES,
-            DEFAULT_TOKEN_INITIAL_RESERVES,
-            None,
-            dev_buy,
-        )?;
+        let token_amount = pool_state.apply_buy(dev_buy)?;
         debug!("dev_buy: {}", dev_buy);
         debug!("token_amount: {}", token_amount);
-        ixs.append(&mut _make_buy_ixs(
-            signer.pubkey(),
-            mint,
-            bonding_curve,
-            associated_bonding_curve,
+        builder = builder.buy(
+            pool_state.pump_accounts(),
+            pump_global.fee_recipient,
             token_amount,
-            apply_fee(dev_buy),
-        )?);
-
-        pool_state.virtual_sol_reserves += dev_buy;
-        pool_state.virtual_token_reserves -= token_amount;
+            pump_global.apply_fee(dev_buy),
+            pool_state.version,
+        )?;
     }
 
-    // static tip of 50000 lamports for the launch
-    ixs.push(transfer(&signer.pubkey(), &get_jito_tip_pubkey(), 50000));
-
-    let rpc_client = RpcClient::new(env("RPC_URL"));
-    let latest_blockhash = rpc_client.get_latest_blockhash().await?;
+    let builder = builder
+        .estimated_compute_budget(
+            &ComputeEstimator::new(rpc_client),
+            LAUNCH_COMPUTE_BUDGET,
+        )
+        .await;
+    let latest_blockhash = blockhash_cache.get().await?;
     let create_tx =
-        VersionedTransaction::from(Transaction::new_signed_with_payer(
-            &ixs,
-            Some(&signer.pubkey()),
-            &[signer, &mint_signer],
-            latest_blockhash,
-        ));
+        builder.build(&[signer, &mint_signer], latest_blockhash)?;
 
     #[cfg(test)]
     {
         debug!("create_tx: {:#?}", create_tx);
     }
 
-    #[cfg(not(feature = "dry-run"))]
-    send_bundle_no_wait(&[create_tx], &mut searcher_client).await?;
+    jito::send_bundle(&[create_tx], &mut searcher_client).await?;
 
-    let latest_blockhash = loop {
-        let new_blockhash = rpc_client.get_latest_blockhash().await?;
-        if new_blockhash != latest_blockhash {
-            break new_blockhash;
-        }
-    };
+    let latest_blockhash = blockhash_cache
+        .wait_for_new(latest_blockhash, NEW_BLOCKHASH_TIMEOUT)
+        .await?;
 
     if let Some(wallet_manager) = wallet_manager {
         ladder_buys(
-            mint,
             &mut pool_state,
+            &pump_global,
             wallet_manager,
             snipe_buy.unwrap(),
             latest_blockhash,
@@ -426,10 +443,6 @@ pub async fn launch(
     Ok(())
 }
 
-pub fn apply_fee(amount: u64) -> u64 {
-    amount * 101 / 100
-}
-
 pub fn jittered_lamports_amount(lamports: u64) -> u64 {
     let mut rng = rand::thread_rng();
     let jitter = rng.gen_range(0.8..0.95);
@@ -562,6 +575,7 @@ pub fn derive_metadata_account(mint: &Pubkey) -> Pubkey {
 mod launcher_tests {
     use solana_sdk::signer::EncodableKey;
 
+    use crate::blockhash::make_blockhash_cache;
     use crate::util::{env, init_logger};
     use crate::wallet::make_manager;
 
@@ -590,6 +604,7 @@ mod launcher_tests {
             Some(500000000),
             None,
             None,
+            &make_blockhash_cache().await.unwrap(),
         )
         .await
         .unwrap();
@@ -618,6 +633,7 @@ mod launcher_tests {
             None,
             None,
             None,
+            &make_blockhash_cache().await.unwrap(),
         )
         .await
         .unwrap();
@@ -717,12 +733,13 @@ mod launcher_tests {
         let rpc_client = RpcClient::new(rpc_url);
         let latest_blockhash =
             rpc_client.get_latest_blockhash().await.unwrap();
+        let pump_global = get_pump_global(&rpc_client).await.unwrap();
 
         let mut searcher_client = jito::make_searcher_client().await.unwrap();
 
         ladder_buys(
-            mint,
             &mut pool_state,
+            &pump_global,
             &wallet_manager,
             1_000_000, // 0.001 SOL as snipe_buy amount
             latest_blockhash,
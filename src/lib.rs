@@ -2,21 +2,42 @@ pub mod analyze;
 pub mod app;
 pub mod ata;
 pub mod bench;
+pub mod block_engine;
+pub mod blockhash;
+pub mod bundle_tracker;
+pub mod compute;
 pub mod constants;
 pub mod data;
+pub mod decoder;
+pub mod dry_run;
+pub mod emulator;
+pub mod events;
 pub mod flows;
+pub mod global;
+pub mod ingest;
 pub mod jito;
+pub mod jito_client;
 pub mod jup;
 pub mod launcher;
+pub mod lookup_table;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod mock_jito;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod mock_rpc;
 pub mod moon_launcher;
 pub mod moonshot;
+pub mod preflight;
 pub mod pump;
 pub mod pump_service;
 pub mod seller;
+pub mod sender;
 pub mod slot;
 pub mod snipe;
 pub mod snipe_portal;
+pub mod tip;
+pub mod tip_tuner;
 pub mod tpu;
+pub mod tx_builder;
 pub mod util;
 pub mod volume;
 pub mod wallet;
@@ -0,0 +1,331 @@
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use log::{info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::address_lookup_table::instruction::{
+    create_lookup_table as make_create_ix,
+    extend_lookup_table as make_extend_ix,
+};
+use solana_sdk::address_lookup_table::state::AddressLookupTable;
+use solana_sdk::address_lookup_table::{self, AddressLookupTableAccount};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+
+use crate::constants::{
+    ASSOCIATED_TOKEN_PROGRAM, EVENT_AUTHORITY, PUMP_FEE_ADDRESS,
+    PUMP_FEE_PROGRAM, PUMP_FUN_PROGRAM, PUMP_GLOBAL_ADDRESS, RENT_PROGRAM,
+    SYSTEM_PROGRAM_ID, TOKEN_PROGRAM,
+};
+use crate::dry_run::send_and_confirm;
+use crate::pump::{
+    get_fee_config, get_global_volume_accumulator,
+    get_user_volume_accumulator,
+};
+use crate::wallet::make_signed_tx;
+
+/// addresses per extend tx, 30 would still fit in 1232 bytes
+pub const MAX_EXTEND_ADDRESSES: usize = 20;
+
+/// tables from LOOKUP_TABLES, the pump buy and sell txs are compiled
+/// against these
+static LOOKUP_TABLES: RwLock<Vec<AddressLookupTableAccount>> =
+    RwLock::new(Vec::new());
+
+pub fn set_lookup_tables(lookup_tables: Vec<AddressLookupTableAccount>) {
+    *LOOKUP_TABLES.write().expect("lookup tables") = lookup_tables;
+}
+
+/// get_lookup_tables is empty until load_lookup_tables
+pub fn get_lookup_tables() -> Vec<AddressLookupTableAccount> {
+    LOOKUP_TABLES.read().expect("lookup tables").clone()
+}
+
+/// pump_static_accounts are in every pump buy and sell, whatever the mint
+/// or the wallet, the jito tip accounts are left out as jito only sees a
+/// tip to a static key
+pub fn pump_static_accounts() -> Vec<Pubkey> {
+    let mut accounts = [
+        PUMP_GLOBAL_ADDRESS,
+        PUMP_FEE_ADDRESS,
+        PUMP_FUN_PROGRAM,
+        PUMP_FEE_PROGRAM,
+        EVENT_AUTHORITY,
+        SYSTEM_PROGRAM_ID,
+        TOKEN_PROGRAM,
+        ASSOCIATED_TOKEN_PROGRAM,
+        RENT_PROGRAM,
+    ]
+    .iter()
+    .map(|address| Pubkey::from_str(address).expect("parse pubkey"))
+    .collect::<Vec<_>>();
+    accounts.extend([get_global_volume_accumulator(), get_fee_config()]);
+    accounts
+}
+
+/// wallet_accounts are derived from the wallet alone, the wallet itself
+/// signs so it is never looked up
+pub fn wallet_accounts(wallet: &Pubkey) -> Vec<Pubkey> {
+    vec![get_user_volume_accumulator(wallet)]
+}
+
+pub async fn get_lookup_table(
+    rpc_client: &RpcClient,
+    address: &Pubkey,
+) -> Result<AddressLookupTableAccount, Box<dyn Error>> {
+    let account = rpc_client.get_account(address).await?;
+    if account.owner != address_lookup_table::program::id() {
+        return Err(format!("{} is not a lookup table", address).into());
+    }
+    let table = AddressLookupTable::deserialize(&account.data)?;
+    Ok(AddressLookupTableAccount {
+        key: *address,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+/// load_lookup_tables fetches the tables, set_lookup_tables keeps them for
+/// the builders
+pub async fn load_lookup_tables(
+    rpc_client: &RpcClient,
+    addresses: &[Pubkey],
+) -> Result<Vec<AddressLookupTableAccount>, Box<dyn Error>> {
+    let mut lookup_tables = vec![];
+    for address in addresses {
+        let lookup_table = get_lookup_table(rpc_client, address).await?;
+        info!(
+            "Lookup table {} with {} addresses",
+            address,
+            lookup_table.addresses.len()
+        );
+        lookup_tables.push(lookup_table);
+    }
+    Ok(lookup_tables)
+}
+
+/// load_lookup_tables_from_env loads the comma separated LOOKUP_TABLES, if
+/// set, txs stay legacy otherwise or when the tables can't be loaded
+pub async fn load_lookup_tables_from_env() {
+    match _lookup_tables_from_env().await {
+        Ok(lookup_tables) => set_lookup_tables(lookup_tables),
+        Err(e) => warn!("Not using lookup tables: {}", e),
+    }
+}
+
+async fn _lookup_tables_from_env(
+) -> Result<Vec<AddressLookupTableAccount>, Box<dyn Error>> {
+    let Ok(lookup_tables) = std::env::var("LOOKUP_TABLES") else {
+        return Ok(vec![]);
+    };
+    let addresses = lookup_tables
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(Pubkey::from_str)
+        .collect::<Result<Vec<_>, _>>()?;
+    let rpc_client = RpcClient::new(std::env::var("RPC_URL")?);
+    load_lookup_tables(&rpc_client, &addresses).await
+}
+
+/// create_lookup_table creates an empty table owned by authority, the
+/// address is derived from the authority and a recent slot
+pub async fn create_lookup_table(
+    rpc_client: &RpcClient,
+    authority: &Keypair,
+) -> Result<Pubkey, Box<dyn Error>> {
+    let recent_slot = rpc_client
+        .get_slot_with_commitment(CommitmentConfig::finalized())
+        .await?;
+    let (ix, address) =
+        make_create_ix(authority.pubkey(), authority.pubkey(), recent_slot);
+    let tx = make_signed_tx(
+        &[ix],
+        &authority.pubkey(),
+        &[authority],
+        rpc_client.get_latest_blockhash().await?,
+    );
+    let signature = send_and_confirm(rpc_client, &tx).await?;
+    info!("Created lookup table {}: {}", address, signature);
+    Ok(address)
+}
+
+/// extend_lookup_table adds the addresses the table does not have yet, the
+/// new ones can be looked up from the next slot on
+pub async fn extend_lookup_table(
+    rpc_client: &RpcClient,
+    authority: &Keypair,
+    address: &Pubkey,
+    addresses: &[Pubkey],
+) -> Result<usize, Box<dyn Error>> {
+    let lookup_table = get_lookup_table(rpc_client, address).await?;
+    let mut new_addresses: Vec<Pubkey> = vec![];
+    for pubkey in addresses {
+        if !lookup_table.addresses.contains(pubkey)
+            && !new_addresses.contains(pubkey)
+        {
+            new_addresses.push(*pubkey);
+        }
+    }
+    for chunk in new_addresses.chunks(MAX_EXTEND_ADDRESSES) {
+        let tx = make_signed_tx(
+            &[make_extend_ix(
+                *address,
+                authority.pubkey(),
+                Some(authority.pubkey()),
+                chunk.to_vec(),
+            )],
+            &authority.pubkey(),
+            &[authority],
+            rpc_client.get_latest_blockhash().await?,
+        );
+        let signature = send_and_confirm(rpc_client, &tx).await?;
+        info!(
+            "Extended lookup table {} by {}: {}",
+            address,
+            chunk.len(),
+            signature
+        );
+    }
+    Ok(new_addresses.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global::PumpGlobal;
+    use crate::mock_rpc::{MockRpcServer, RpcFixtures};
+    use crate::pump::{mint_to_pump_accounts, PumpAccounts, PumpIxVersion};
+    use crate::tx_builder::PumpTxBuilder;
+    use solana_sdk::account::Account;
+    use solana_sdk::address_lookup_table::instruction::{
+        derive_lookup_table_address, ProgramInstruction,
+    };
+    use solana_sdk::address_lookup_table::state::LookupTableMeta;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::VersionedMessage;
+    use std::borrow::Cow;
+
+    fn table_account(authority: &Pubkey, addresses: &[Pubkey]) -> Account {
+        let data = AddressLookupTable {
+            meta: LookupTableMeta::new(*authority),
+            addresses: Cow::Borrowed(addresses),
+        }
+        .serialize_for_tests()
+        .unwrap();
+        Account {
+            lamports: 1_000_000,
+            data,
+            owner: address_lookup_table::program::id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_and_extend_lookup_table() {
+        let authority = Keypair::new();
+        let (address, _) =
+            derive_lookup_table_address(&authority.pubkey(), 100);
+        let mut statics = pump_static_accounts();
+        let server = MockRpcServer::start(
+            RpcFixtures {
+                slot: 100,
+                ..Default::default()
+            }
+            .with_account(
+                address,
+                table_account(&authority.pubkey(), &statics[..5]),
+            ),
+        )
+        .await
+        .unwrap();
+        let rpc_client = RpcClient::new(server.rpc_url.clone());
+
+        assert_eq!(
+            create_lookup_table(&rpc_client, &authority).await.unwrap(),
+            address
+        );
+        // the first 5 are in already, duplicates are only added once
+        statics.extend(wallet_accounts(&authority.pubkey()));
+        statics.extend((0..20).map(|_| Pubkey::new_unique()));
+        statics.push(statics[6]);
+        let added =
+            extend_lookup_table(&rpc_client, &authority, &address, &statics)
+                .await
+                .unwrap();
+        assert_eq!(added, statics.len() - 6);
+
+        let sent = server.sent_transactions().await;
+        assert_eq!(sent.len(), 3);
+        let extended = sent[1..]
+            .iter()
+            .flat_map(|tx| {
+                match bincode::deserialize(&tx.message.instructions()[0].data)
+                    .unwrap()
+                {
+                    ProgramInstruction::ExtendLookupTable {
+                        new_addresses,
+                    } => new_addresses,
+                    ix => panic!("unexpected {:?}", ix),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(extended, statics[5..statics.len() - 1]);
+
+        let lookup_tables =
+            load_lookup_tables(&rpc_client, &[address]).await.unwrap();
+        assert_eq!(lookup_tables[0].addresses, statics[..5]);
+        let err = get_lookup_table(&rpc_client, &Pubkey::new_unique())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("AccountNotFound"));
+    }
+
+    #[test]
+    fn test_lookup_tables_shrink_pump_txs() {
+        let wallet = Keypair::new();
+        let pump_accounts = PumpAccounts {
+            dev: Pubkey::new_unique(),
+            ..mint_to_pump_accounts(&Pubkey::new_unique())
+        };
+        let mut addresses = pump_static_accounts();
+        addresses.extend(wallet_accounts(&wallet.pubkey()));
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses,
+        };
+        let builder = PumpTxBuilder::new(wallet.pubkey())
+            .compute_budget(1_000_069, 69_000)
+            .buy(
+                pump_accounts,
+                PumpGlobal::default().fee_recipient,
+                1_000,
+                2_000,
+                PumpIxVersion::default(),
+            )
+            .unwrap()
+            .tip(50_000);
+
+        let legacy = builder.build(&[&wallet], Hash::new_unique()).unwrap();
+        let v0 = builder
+            .lookup_tables(vec![table])
+            .build(&[&wallet], Hash::new_unique())
+            .unwrap();
+        let VersionedMessage::V0(message) = &v0.message else {
+            panic!("expected a v0 message");
+        };
+        assert_eq!(message.address_table_lookups.len(), 1);
+        let legacy_size = bincode::serialized_size(&legacy).unwrap();
+        let v0_size = bincode::serialized_size(&v0).unwrap();
+        // ~31 bytes less for every account looked up, the programs stay
+        assert!(
+            v0_size + 200 < legacy_size,
+            "{} vs {}",
+            v0_size,
+            legacy_size
+        );
+    }
+}
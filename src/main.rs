@@ -2,27 +2,34 @@ use clap::Parser;
 use solana_account_decoder::UiAccountData;
 use {
     dialoguer::{theme::ColorfulTheme, Confirm},
-    futures::StreamExt,
-    jito_protos::searcher::SubscribeBundleResultsRequest,
     jito_searcher_client::get_searcher_client,
     pump_rs::{
         analyze::run_analysis,
         app::{App, Command},
         ata, bench,
+        blockhash::make_blockhash_cache,
+        bundle_tracker::BundleTracker,
+        compute::{make_compute_estimator, ComputeEstimator},
         constants::{TOKEN_PROGRAM, WSOL},
         data::look_for_rpc_nodes,
+        dry_run::{get_dry_run, init_dry_run},
+        global::get_pump_global,
         jito::{
             get_bundle_status, make_searcher_client,
             start_bundle_results_listener, subscribe_tips,
         },
         jup::Jupiter,
         launcher::{self, IPFSMetaForm},
+        lookup_table,
+        preflight::PreflightSender,
         pump::{self},
         pump::{get_bonding_curve, get_token_amount},
         pump_service,
         seller::{self, get_tx_with_retries},
+        sender::{make_tx_sender, Transport},
         slot::{make_deadline_tx, update_slot},
         snipe, snipe_portal,
+        tip::TipStrategy,
         util::{env, init_logger, parse_holding},
         wallet::make_manager,
     },
@@ -49,6 +56,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     init_logger()?;
 
     let app = App::parse();
+    init_dry_run(app.args.dry_run, app.args.dry_run_file.clone());
 
     match app.command {
         Command::WalletsFund { lamports } => {
@@ -57,9 +65,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
         Command::BundleStatusListener {} => {
             let searcher_client = make_searcher_client().await?;
-            start_bundle_results_listener(Arc::new(Mutex::new(
-                searcher_client,
-            )))
+            start_bundle_results_listener(
+                Arc::new(Mutex::new(searcher_client)),
+                Arc::new(BundleTracker::new()),
+            )
             .await;
             tokio::signal::ctrl_c().await?;
         }
@@ -77,6 +86,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             let wallet_manager = make_manager().await?;
             let signer = Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
                 .expect("read wallet");
+            let blockhash_cache = make_blockhash_cache().await?;
 
             launcher::launch(
                 &IPFSMetaForm {
@@ -93,6 +103,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 Some(dev_buy),
                 Some(&wallet_manager),
                 Some(snipe_buy),
+                &blockhash_cache,
             )
             .await?;
         }
@@ -115,8 +126,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             get_bundle_status(bundle_id).await?;
         }
         Command::SubscribeTip {} => {
-            let tip = Arc::new(RwLock::new(0u64));
-            subscribe_tips(tip).await?;
+            subscribe_tips(Arc::new(RwLock::new(None))).await?;
         }
         Command::GetTx { sig } => {
             let signature = Signature::from_str(&sig).expect("parse sig");
@@ -153,6 +163,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 rpc_client.get_latest_blockhash().await?,
                 &keypair,
             );
+            if let Some(dry_run) = get_dry_run() {
+                dry_run.record("rpc", &[tx.into()])?;
+                return Ok(());
+            }
             let sig = rpc_client
                 .send_and_confirm_transaction_with_spinner_and_config(
                     &tx,
@@ -186,25 +200,72 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     .expect("makes searcher client");
             return Err("Unimplemented".into());
         }
-        Command::Seller {} => {
-            info!("Running seller");
-            seller::run_seller().await?;
+        Command::Seller { slippage_bps } => {
+            lookup_table::load_lookup_tables_from_env().await;
+            info!("Running seller, slippage {} bps", slippage_bps);
+            let sender = make_tx_sender(Transport::resolve(
+                app.args.sender,
+                Transport::JitoHttp,
+            ))
+            .await?;
+            seller::run_seller(
+                slippage_bps,
+                sender,
+                make_blockhash_cache().await?,
+            )
+            .await?;
         }
         Command::BenchPortal {} => {
             info!("Benching portal connection");
             bench::bench_pump_portal_connection().await?;
         }
+        Command::BenchBlockEngines { rounds } => {
+            info!("Benching block engines");
+            bench::bench_block_engines(rounds).await?;
+        }
         Command::BenchPump {} => {
             info!("Benching pump connection");
             bench::bench_pump_connection().await?;
         }
         Command::SnipePortal { lamports } => {
+            lookup_table::load_lookup_tables_from_env().await;
             info!("Sniping portal with {} lamports", lamports);
-            snipe_portal::snipe_portal(lamports).await?;
+            let sender = make_tx_sender(Transport::resolve(
+                app.args.sender,
+                Transport::Race,
+            ))
+            .await?;
+            snipe_portal::snipe_portal(
+                lamports,
+                sender,
+                TipStrategy::resolve(
+                    app.args.tip,
+                    TipStrategy::Percentile(75.),
+                ),
+                app.args.tune_tips,
+                make_blockhash_cache().await?,
+            )
+            .await?;
         }
         Command::SnipePump { lamports } => {
+            lookup_table::load_lookup_tables_from_env().await;
             info!("Sniping pump with {} lamports", lamports);
-            snipe::snipe_pump(lamports).await?;
+            let sender = make_tx_sender(Transport::resolve(
+                app.args.sender,
+                Transport::Race,
+            ))
+            .await?;
+            snipe::snipe_pump(
+                lamports,
+                sender,
+                TipStrategy::resolve(
+                    app.args.tip,
+                    TipStrategy::Fixed(1_000_000),
+                ),
+                app.args.tune_tips,
+                make_blockhash_cache().await?,
+            )
+            .await?;
         }
         Command::Analyze {
             wallet_path,
@@ -239,28 +300,44 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 get_searcher_client(env("BLOCK_ENGINE_URL").as_str(), &auth)
                     .await
                     .expect("makes searcher client");
-            let mut bundle_results_stream = searcher_client
-                .subscribe_bundle_results(SubscribeBundleResultsRequest {})
-                .await
-                .expect("subscribe bundle results")
-                .into_inner();
-            tokio::spawn(async move {
-                while let Some(res) = bundle_results_stream.next().await {
-                    info!("Received bundle result: {:?}", res);
-                }
-            });
+            // results come through a separate client, the sends hold the
+            // other one mutably
+            let bundle_tracker = Arc::new(BundleTracker::new());
+            start_bundle_results_listener(
+                Arc::new(Mutex::new(searcher_client.clone())),
+                bundle_tracker.clone(),
+            )
+            .await;
             ata::close_all_atas(
                 rpc_client,
                 &keypair,
                 burn,
                 &mut searcher_client,
+                &bundle_tracker,
             )
             .await?;
         }
         Command::PumpService { lamports } => {
-            pump_service::run_pump_service(lamports).await?;
+            lookup_table::load_lookup_tables_from_env().await;
+            let sender = make_tx_sender(Transport::resolve(
+                app.args.sender,
+                Transport::JitoGrpc,
+            ))
+            .await?;
+            pump_service::run_pump_service(
+                lamports,
+                sender,
+                TipStrategy::resolve(
+                    app.args.tip,
+                    TipStrategy::Percentile(75.),
+                ),
+                app.args.tune_tips,
+                make_blockhash_cache().await?,
+            )
+            .await?;
         }
         Command::BumpPump { mint } => {
+            lookup_table::load_lookup_tables_from_env().await;
             let keypair = Keypair::read_from_file(env("BUMP_KEYPAIR_PATH"))
                 .expect("read wallet");
             let rpc_client = RpcClient::new(env("RPC_URL").to_string());
@@ -272,12 +349,20 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     .await
                     .expect("makes searcher client"),
             ));
+            let sender = make_tx_sender(Transport::resolve(
+                app.args.sender,
+                Transport::JitoHttp,
+            ))
+            .await?;
+            let estimator = make_compute_estimator();
             loop {
                 match pump::send_pump_bump(
                     &keypair,
                     &rpc_client,
                     &Pubkey::from_str(&mint)?,
                     &mut searcher_client,
+                    sender.as_ref(),
+                    &estimator,
                     true,
                 )
                 .await
@@ -293,11 +378,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 tokio::time::sleep(Duration::from_secs(6)).await;
             }
         }
-        Command::SweepPump { wallet_path } => {
+        Command::SweepPump {
+            wallet_path,
+            slippage_bps,
+        } => {
+            lookup_table::load_lookup_tables_from_env().await;
             let keypair =
                 Keypair::read_from_file(wallet_path).expect("read wallet");
             info!("Wallet: {}", keypair.pubkey());
-            let rpc_client = RpcClient::new(env("RPC_URL").to_string());
+            let rpc_client =
+                Arc::new(RpcClient::new(env("RPC_URL").to_string()));
+            let pump_global = get_pump_global(&rpc_client).await?;
+            // a sell that would fail is reported instead of sent
+            let sender = PreflightSender::new(
+                make_tx_sender(Transport::resolve(
+                    app.args.sender,
+                    Transport::JitoHttp,
+                ))
+                .await?,
+                rpc_client.clone(),
+            );
+            let estimator = ComputeEstimator::new(rpc_client.clone());
             let pump_tokens =
                 pump::get_tokens_held_pump(&keypair.pubkey()).await?;
             info!("Tokens held: {}", pump_tokens.len());
@@ -317,13 +418,23 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 if holding.amount > 0 {
                     let mint = holding.mint;
                     info!("Selling {} of {}", holding.amount, mint);
-                    pump::sell_pump_token(
+                    // one sell failing preflight does not stop the sweep
+                    if let Err(e) = pump::sell_pump_token(
                         &keypair,
+                        &rpc_client,
+                        &pump_global,
                         rpc_client.get_latest_blockhash().await?,
                         pump_accounts,
                         holding.amount,
+                        slippage_bps,
+                        &sender,
+                        &estimator,
                     )
-                    .await?;
+                    .await
+                    {
+                        warn!("Sell failed for mint {}: {}", mint, e);
+                        continue;
+                    }
                     tokio::time::sleep(Duration::from_millis(300)).await;
                 }
             }
@@ -404,12 +515,58 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
         }
-        Command::SwapMode { lamports, sell } => {
+        Command::LookupTable { address } => {
+            let manager = make_manager().await?;
+            // the new table has to be readable right after the create
+            let rpc_client = RpcClient::new_with_commitment(
+                env("RPC_URL"),
+                CommitmentConfig::confirmed(),
+            );
+            let address = match address {
+                Some(address) => Pubkey::from_str(&address)?,
+                None => {
+                    lookup_table::create_lookup_table(
+                        &rpc_client,
+                        &manager.owner,
+                    )
+                    .await?
+                }
+            };
+            let mut addresses = lookup_table::pump_static_accounts();
+            for wallet in &manager.wallets {
+                addresses
+                    .extend(lookup_table::wallet_accounts(&wallet.pubkey()));
+            }
+            let added = lookup_table::extend_lookup_table(
+                &rpc_client,
+                &manager.owner,
+                &address,
+                &addresses,
+            )
+            .await?;
+            info!("Added {} addresses, LOOKUP_TABLES={}", added, address);
+        }
+        Command::SwapMode {
+            lamports,
+            sell,
+            slippage_bps,
+        } => {
+            lookup_table::load_lookup_tables_from_env().await;
             let keypair = Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
                 .expect("read wallet");
             let rpc_client =
                 Arc::new(RpcClient::new(env("RPC_URL").to_string()));
+            let pump_global = get_pump_global(&rpc_client).await?;
+            let sender = PreflightSender::new(
+                make_tx_sender(Transport::resolve(
+                    app.args.sender,
+                    Transport::JitoHttp,
+                ))
+                .await?,
+                rpc_client.clone(),
+            );
             let tip = 50_000;
+            let estimator = ComputeEstimator::new(rpc_client.clone());
 
             loop {
                 println!("Enter a mint address (or 'q' to quit):");
@@ -449,9 +606,14 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                 .unwrap();
                             match pump::sell_pump_token(
                                 &keypair,
+                                &rpc_client,
+                                &pump_global,
                                 latest_blockhash,
                                 pump_accounts,
                                 token_amount,
+                                slippage_bps,
+                                &sender,
+                                &estimator,
                             )
                             .await
                             {
@@ -478,11 +640,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
                             )?;
                             match pump::buy_pump_token(
                                 &keypair,
+                                &pump_global,
                                 latest_blockhash,
                                 pump_accounts,
+                                &bonding_curve,
                                 token_amount,
                                 lamports * 105 / 100, // slippage
                                 tip,
+                                &sender,
+                                &estimator,
                             )
                             .await
                             {
@@ -0,0 +1,896 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures_util::Stream;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use jito_protos::auth::auth_service_server::{
+    AuthService, AuthServiceServer,
+};
+use jito_protos::auth::{
+    GenerateAuthChallengeRequest, GenerateAuthChallengeResponse,
+    GenerateAuthTokensRequest, GenerateAuthTokensResponse,
+    RefreshAccessTokenRequest, RefreshAccessTokenResponse, Token,
+};
+use jito_protos::bundle::{
+    bundle_result, rejected, Accepted, BundleResult, Dropped, DroppedReason,
+    Finalized, Processed, Rejected, SimulationFailure,
+};
+use jito_protos::convert::versioned_tx_from_packet;
+use jito_protos::searcher::searcher_service_server::{
+    SearcherService, SearcherServiceServer,
+};
+use jito_protos::searcher::{
+    ConnectedLeadersRegionedRequest, ConnectedLeadersRegionedResponse,
+    ConnectedLeadersRequest, ConnectedLeadersResponse, GetRegionsRequest,
+    GetRegionsResponse, GetTipAccountsRequest, GetTipAccountsResponse,
+    NextScheduledLeaderRequest, NextScheduledLeaderResponse,
+    SendBundleRequest, SendBundleResponse, SubscribeBundleResultsRequest,
+};
+use log::{debug, warn};
+use rand::Rng;
+use serde_json::{json, Value};
+use solana_sdk::clock::Slot;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::system_instruction::transfer;
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use tonic::{Request as GrpcRequest, Response as GrpcResponse, Status};
+
+use crate::constants::JITO_TIP_ACCOUNTS;
+
+const MOCK_REGION: &str = "mock";
+
+/// make_tx is a signed transfer of lamports to a new account, the lamports
+/// tell the txs of a payer apart
+pub fn make_tx(payer: &Keypair, lamports: u64) -> VersionedTransaction {
+    VersionedTransaction::from(Transaction::new_signed_with_payer(
+        &[transfer(&payer.pubkey(), &Pubkey::new_unique(), lamports)],
+        Some(&payer.pubkey()),
+        &[payer],
+        Hash::default(),
+    ))
+}
+
+/// BundleOutcome is one scripted result the mock streams for a bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleOutcome {
+    Accepted,
+    Processed,
+    Finalized,
+    /// simulation failure with the given message
+    Rejected(String),
+    Dropped,
+}
+
+impl BundleOutcome {
+    fn landed(&self) -> bool {
+        matches!(self, Self::Accepted | Self::Processed | Self::Finalized)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleSource {
+    Grpc,
+    Http,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceivedBundle {
+    pub uuid: String,
+    pub source: BundleSource,
+    pub transactions: Vec<VersionedTransaction>,
+    pub outcomes: Vec<BundleOutcome>,
+}
+
+#[derive(Default)]
+struct MockJitoState {
+    slot: Slot,
+    /// outcomes for the next bundles, one entry per bundle, bundles are
+    /// accepted when this runs out
+    script: VecDeque<Vec<BundleOutcome>>,
+    bundles: Vec<ReceivedBundle>,
+    transactions: Vec<VersionedTransaction>,
+    challenges: HashMap<Pubkey, String>,
+    tokens: Vec<String>,
+}
+
+fn random_hex(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len / 2)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect()
+}
+
+fn bundle_result(
+    uuid: &str,
+    slot: Slot,
+    outcome: &BundleOutcome,
+    transactions: &[VersionedTransaction],
+) -> BundleResult {
+    let result = match outcome {
+        BundleOutcome::Accepted => {
+            bundle_result::Result::Accepted(Accepted {
+                slot,
+                validator_identity: MOCK_REGION.to_string(),
+            })
+        }
+        BundleOutcome::Processed => {
+            bundle_result::Result::Processed(Processed {
+                validator_identity: MOCK_REGION.to_string(),
+                slot,
+                bundle_index: 0,
+            })
+        }
+        BundleOutcome::Finalized => {
+            bundle_result::Result::Finalized(Finalized {})
+        }
+        BundleOutcome::Rejected(msg) => {
+            bundle_result::Result::Rejected(Rejected {
+                reason: Some(rejected::Reason::SimulationFailure(
+                    SimulationFailure {
+                        tx_signature: transactions
+                            .first()
+                            .and_then(|tx| tx.signatures.first())
+                            .map(|signature| signature.to_string())
+                            .unwrap_or_default(),
+                        msg: Some(msg.clone()),
+                    },
+                )),
+            })
+        }
+        BundleOutcome::Dropped => bundle_result::Result::Dropped(Dropped {
+            reason: DroppedReason::BlockhashExpired as i32,
+        }),
+    };
+    BundleResult {
+        bundle_id: uuid.to_string(),
+        result: Some(result),
+    }
+}
+
+/// Shared is what the grpc and http sides of the mock have in common
+#[derive(Clone)]
+struct Shared {
+    state: Arc<Mutex<MockJitoState>>,
+    results: broadcast::Sender<BundleResult>,
+}
+
+impl Shared {
+    async fn receive_bundle(
+        &self,
+        source: BundleSource,
+        transactions: Vec<VersionedTransaction>,
+    ) -> Result<String, String> {
+        if transactions.is_empty() || transactions.len() > 5 {
+            return Err(format!(
+                "bundle must have 1 to 5 transactions, got {}",
+                transactions.len()
+            ));
+        }
+        let mut state = self.state.lock().await;
+        let uuid = random_hex(64);
+        let outcomes = state
+            .script
+            .pop_front()
+            .unwrap_or_else(|| vec![BundleOutcome::Accepted]);
+        for outcome in &outcomes {
+            let _ = self.results.send(bundle_result(
+                &uuid,
+                state.slot,
+                outcome,
+                &transactions,
+            ));
+        }
+        debug!("Mock block engine got bundle {} ({:?})", uuid, outcomes);
+        state.bundles.push(ReceivedBundle {
+            uuid: uuid.clone(),
+            source,
+            transactions,
+            outcomes,
+        });
+        Ok(uuid)
+    }
+
+    async fn authorize<T>(
+        &self,
+        request: &GrpcRequest<T>,
+    ) -> Result<(), Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing token"))?;
+        if !self.state.lock().await.tokens.iter().any(|t| t == token) {
+            return Err(Status::unauthenticated("invalid token"));
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl AuthService for Shared {
+    async fn generate_auth_challenge(
+        &self,
+        request: GrpcRequest<GenerateAuthChallengeRequest>,
+    ) -> Result<GrpcResponse<GenerateAuthChallengeResponse>, Status> {
+        let pubkey = Pubkey::try_from(request.into_inner().pubkey.as_slice())
+            .map_err(|_| Status::invalid_argument("invalid pubkey"))?;
+        let challenge = random_hex(16);
+        self.state
+            .lock()
+            .await
+            .challenges
+            .insert(pubkey, challenge.clone());
+        Ok(GrpcResponse::new(GenerateAuthChallengeResponse {
+            challenge,
+        }))
+    }
+
+    async fn generate_auth_tokens(
+        &self,
+        request: GrpcRequest<GenerateAuthTokensRequest>,
+    ) -> Result<GrpcResponse<GenerateAuthTokensResponse>, Status> {
+        let request = request.into_inner();
+        let pubkey = Pubkey::try_from(request.client_pubkey.as_slice())
+            .map_err(|_| Status::invalid_argument("invalid pubkey"))?;
+        let signature =
+            Signature::try_from(request.signed_challenge.as_slice())
+                .map_err(|_| Status::invalid_argument("invalid signature"))?;
+        let mut state = self.state.lock().await;
+        let challenge = state
+            .challenges
+            .remove(&pubkey)
+            .ok_or_else(|| Status::permission_denied("no challenge"))?;
+        if request.challenge != format!("{}-{}", pubkey, challenge)
+            || !signature
+                .verify(pubkey.as_ref(), request.challenge.as_bytes())
+        {
+            return Err(Status::permission_denied("invalid challenge"));
+        }
+        let access_token = random_hex(32);
+        state.tokens.push(access_token.clone());
+        Ok(GrpcResponse::new(GenerateAuthTokensResponse {
+            access_token: Some(Token {
+                value: access_token,
+                expires_at_utc: None,
+            }),
+            refresh_token: Some(Token {
+                value: random_hex(32),
+                expires_at_utc: None,
+            }),
+        }))
+    }
+
+    async fn refresh_access_token(
+        &self,
+        _request: GrpcRequest<RefreshAccessTokenRequest>,
+    ) -> Result<GrpcResponse<RefreshAccessTokenResponse>, Status> {
+        let access_token = random_hex(32);
+        self.state.lock().await.tokens.push(access_token.clone());
+        Ok(GrpcResponse::new(RefreshAccessTokenResponse {
+            access_token: Some(Token {
+                value: access_token,
+                expires_at_utc: None,
+            }),
+        }))
+    }
+}
+
+type BundleResultStream =
+    Pin<Box<dyn Stream<Item = Result<BundleResult, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl SearcherService for Shared {
+    type SubscribeBundleResultsStream = BundleResultStream;
+
+    async fn subscribe_bundle_results(
+        &self,
+        request: GrpcRequest<SubscribeBundleResultsRequest>,
+    ) -> Result<GrpcResponse<Self::SubscribeBundleResultsStream>, Status>
+    {
+        self.authorize(&request).await?;
+        let rx = self.results.subscribe();
+        let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(result) => return Some((Ok(result), rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(GrpcResponse::new(Box::pin(stream)))
+    }
+
+    async fn send_bundle(
+        &self,
+        request: GrpcRequest<SendBundleRequest>,
+    ) -> Result<GrpcResponse<SendBundleResponse>, Status> {
+        self.authorize(&request).await?;
+        let packets = request
+            .into_inner()
+            .bundle
+            .map(|bundle| bundle.packets)
+            .unwrap_or_default();
+        let transactions = packets
+            .iter()
+            .map(versioned_tx_from_packet)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Status::invalid_argument("invalid packet"))?;
+        let uuid = self
+            .receive_bundle(BundleSource::Grpc, transactions)
+            .await
+            .map_err(Status::invalid_argument)?;
+        Ok(GrpcResponse::new(SendBundleResponse { uuid }))
+    }
+
+    async fn get_next_scheduled_leader(
+        &self,
+        request: GrpcRequest<NextScheduledLeaderRequest>,
+    ) -> Result<GrpcResponse<NextScheduledLeaderResponse>, Status> {
+        self.authorize(&request).await?;
+        let slot = self.state.lock().await.slot;
+        Ok(GrpcResponse::new(NextScheduledLeaderResponse {
+            current_slot: slot,
+            next_leader_slot: slot + 1,
+            next_leader_identity: Pubkey::default().to_string(),
+            next_leader_region: MOCK_REGION.to_string(),
+        }))
+    }
+
+    async fn get_connected_leaders(
+        &self,
+        request: GrpcRequest<ConnectedLeadersRequest>,
+    ) -> Result<GrpcResponse<ConnectedLeadersResponse>, Status> {
+        self.authorize(&request).await?;
+        Ok(GrpcResponse::new(ConnectedLeadersResponse::default()))
+    }
+
+    async fn get_connected_leaders_regioned(
+        &self,
+        request: GrpcRequest<ConnectedLeadersRegionedRequest>,
+    ) -> Result<GrpcResponse<ConnectedLeadersRegionedResponse>, Status> {
+        self.authorize(&request).await?;
+        Ok(GrpcResponse::new(
+            ConnectedLeadersRegionedResponse::default(),
+        ))
+    }
+
+    async fn get_tip_accounts(
+        &self,
+        request: GrpcRequest<GetTipAccountsRequest>,
+    ) -> Result<GrpcResponse<GetTipAccountsResponse>, Status> {
+        self.authorize(&request).await?;
+        Ok(GrpcResponse::new(GetTipAccountsResponse {
+            accounts: JITO_TIP_ACCOUNTS
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+        }))
+    }
+
+    async fn get_regions(
+        &self,
+        request: GrpcRequest<GetRegionsRequest>,
+    ) -> Result<GrpcResponse<GetRegionsResponse>, Status> {
+        self.authorize(&request).await?;
+        Ok(GrpcResponse::new(GetRegionsResponse {
+            current_region: MOCK_REGION.to_string(),
+            available_regions: vec![MOCK_REGION.to_string()],
+        }))
+    }
+}
+
+type RpcError = (i64, String);
+
+fn decode_transaction(
+    encoded: &Value,
+    encoding: Option<&str>,
+) -> Result<VersionedTransaction, RpcError> {
+    let encoded = encoded
+        .as_str()
+        .ok_or((-32602, "expected encoded transaction".to_string()))?;
+    let data = match encoding {
+        Some("base64") => BASE64
+            .decode(encoded)
+            .map_err(|e| (-32602, e.to_string()))?,
+        _ => bs58::decode(encoded)
+            .into_vec()
+            .map_err(|e| (-32602, e.to_string()))?,
+    };
+    bincode::deserialize(&data).map_err(|e| (-32602, e.to_string()))
+}
+
+impl Shared {
+    /// bundle statuses, as reported by getInflightBundleStatuses (inflight)
+    /// and getBundleStatuses
+    async fn bundle_statuses(
+        &self,
+        params: &Value,
+        inflight: bool,
+    ) -> Result<Value, RpcError> {
+        let uuids = params[0]
+            .as_array()
+            .ok_or((-32602, "expected bundle ids".to_string()))?;
+        let state = self.state.lock().await;
+        let statuses = uuids
+            .iter()
+            .map(|uuid| {
+                let bundle = state.bundles.iter().find(|bundle| {
+                    Some(bundle.uuid.as_str()) == uuid.as_str()
+                });
+                let landed = bundle.is_some_and(|bundle| {
+                    bundle.outcomes.last().is_some_and(BundleOutcome::landed)
+                });
+                match (bundle, inflight) {
+                    (None, true) => json!({
+                        "bundle_id": uuid,
+                        "status": "Invalid",
+                        "landed_slot": null,
+                    }),
+                    (Some(_), true) => json!({
+                        "bundle_id": uuid,
+                        "status": if landed { "Landed" } else { "Failed" },
+                        "landed_slot": landed.then_some(state.slot),
+                    }),
+                    (Some(bundle), false) if landed => json!({
+                        "bundle_id": uuid,
+                        "transactions": bundle
+                            .transactions
+                            .iter()
+                            .map(|tx| tx.signatures[0].to_string())
+                            .collect::<Vec<_>>(),
+                        "slot": state.slot,
+                        "confirmation_status": "confirmed",
+                        "err": { "Ok": null },
+                    }),
+                    _ => Value::Null,
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({
+            "context": { "slot": state.slot },
+            "value": statuses,
+        }))
+    }
+
+    /// simulate_bundle only checks the signatures, every tx that gets
+    /// through succeeds
+    async fn simulate_bundle(
+        &self,
+        params: &Value,
+    ) -> Result<Value, RpcError> {
+        let transactions = params[0]["encodedTransactions"]
+            .as_array()
+            .ok_or((-32602, "expected encodedTransactions".to_string()))?
+            .iter()
+            .map(|tx| decode_transaction(tx, Some("base64")))
+            .collect::<Result<Vec<_>, _>>()?;
+        let skip_sig_verify =
+            params[1]["skipSigVerify"].as_bool().unwrap_or_default();
+        let mut summary = json!("succeeded");
+        let mut results = vec![];
+        for tx in &transactions {
+            if !skip_sig_verify
+                && !tx.verify_with_results().iter().all(|ok| *ok)
+            {
+                summary = json!({
+                    "failed": {
+                        "error": "SignatureFailure",
+                        "txSignature": tx.signatures[0].to_string(),
+                    }
+                });
+                break;
+            }
+            results.push(json!({
+                "err": null,
+                "logs": [
+                    format!("Program {} invoke [1]", Pubkey::default()),
+                    format!("Program {} success", Pubkey::default()),
+                ],
+                "unitsConsumed": 150,
+                "returnData": null,
+                "preExecutionAccounts": null,
+                "postExecutionAccounts": null,
+            }));
+        }
+        Ok(json!({
+            "context": { "slot": self.state.lock().await.slot },
+            "value": {
+                "summary": summary,
+                "transactionResults": results,
+            },
+        }))
+    }
+
+    async fn handle_http(
+        &self,
+        path: &str,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, RpcError> {
+        match (path, method) {
+            (crate::jito::JITO_BUNDLES_PATH, "sendBundle") => {
+                let encoding = params[1]["encoding"].as_str();
+                let transactions = params[0]
+                    .as_array()
+                    .ok_or((-32602, "expected transactions".to_string()))?
+                    .iter()
+                    .map(|tx| decode_transaction(tx, encoding))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let uuid = self
+                    .receive_bundle(BundleSource::Http, transactions)
+                    .await
+                    .map_err(|e| (-32602, e))?;
+                Ok(json!(uuid))
+            }
+            (crate::jito::JITO_BUNDLES_PATH, "getTipAccounts") => {
+                Ok(json!(JITO_TIP_ACCOUNTS))
+            }
+            (crate::jito::JITO_BUNDLES_PATH, "getInflightBundleStatuses") => {
+                self.bundle_statuses(params, true).await
+            }
+            (crate::jito::JITO_BUNDLES_PATH, "getBundleStatuses") => {
+                self.bundle_statuses(params, false).await
+            }
+            // jito rpc nodes, not the block engine, serve this one
+            ("/", "simulateBundle") => self.simulate_bundle(params).await,
+            (crate::jito::JITO_TRANSACTIONS_PATH, "sendTransaction") => {
+                let tx = decode_transaction(
+                    &params[0],
+                    params[1]["encoding"].as_str(),
+                )?;
+                let signature = tx.signatures[0];
+                self.state.lock().await.transactions.push(tx);
+                Ok(json!(signature.to_string()))
+            }
+            _ => Err((
+                -32601,
+                format!("Method not found: {} {}", path, method),
+            )),
+        }
+    }
+}
+
+async fn handle_http_request(
+    req: Request<Incoming>,
+    shared: Shared,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let path = req.uri().path().to_string();
+    let body = req.into_body().collect().await?.to_bytes();
+    let request = serde_json::from_slice::<Value>(&body).unwrap_or_default();
+    let method = request["method"].as_str().unwrap_or_default();
+    let response =
+        match shared.handle_http(&path, method, &request["params"]).await {
+            Ok(result) => json!({
+                "jsonrpc": "2.0",
+                "result": result,
+                "id": request["id"],
+            }),
+            Err((code, message)) => {
+                warn!("Mock block engine {} failed: {}", method, message);
+                json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": code, "message": message },
+                    "id": request["id"],
+                })
+            }
+        };
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(response.to_string())))
+        .expect("build response"))
+}
+
+async fn serve_http(listener: TcpListener, shared: Shared) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Mock block engine accept failed: {}", e);
+                continue;
+            }
+        };
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                handle_http_request(req, shared.clone())
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("Mock block engine connection closed: {}", e);
+            }
+        });
+    }
+}
+
+/// MockBlockEngine serves the searcher and auth grpc services on url and
+/// the bundles/transactions JSON-RPC endpoints on http_url, bundles get the
+/// scripted outcomes streamed to SubscribeBundleResults, the servers stop
+/// when it is dropped
+pub struct MockBlockEngine {
+    pub url: String,
+    pub http_url: String,
+    shared: Shared,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl MockBlockEngine {
+    pub async fn start() -> Result<Self, Box<dyn Error>> {
+        let (results, _) = broadcast::channel(1024);
+        let shared = Shared {
+            state: Arc::new(Mutex::new(MockJitoState::default())),
+            results,
+        };
+
+        let grpc_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let http_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", grpc_listener.local_addr()?);
+        let http_url = format!("http://{}", http_listener.local_addr()?);
+
+        let grpc = tonic::transport::Server::builder()
+            .add_service(AuthServiceServer::new(shared.clone()))
+            .add_service(SearcherServiceServer::new(shared.clone()))
+            .serve_with_incoming(futures_util::stream::unfold(
+                grpc_listener,
+                |listener| async {
+                    let conn =
+                        listener.accept().await.map(|(stream, _)| stream);
+                    Some((conn, listener))
+                },
+            ));
+        let handles = vec![
+            tokio::spawn(async move {
+                if let Err(e) = grpc.await {
+                    warn!("Mock block engine grpc stopped: {}", e);
+                }
+            }),
+            tokio::spawn(serve_http(http_listener, shared.clone())),
+        ];
+
+        Ok(Self {
+            url,
+            http_url,
+            shared,
+            handles,
+        })
+    }
+
+    /// script sets the outcomes for the next bundle, calls queue up
+    pub async fn script(&self, outcomes: Vec<BundleOutcome>) {
+        self.shared.state.lock().await.script.push_back(outcomes);
+    }
+
+    pub async fn set_slot(&self, slot: Slot) {
+        self.shared.state.lock().await.slot = slot;
+    }
+
+    pub async fn bundles(&self) -> Vec<ReceivedBundle> {
+        self.shared.state.lock().await.bundles.clone()
+    }
+
+    /// transactions sent to the transactions endpoint
+    pub async fn transactions(&self) -> Vec<VersionedTransaction> {
+        self.shared.state.lock().await.transactions.clone()
+    }
+}
+
+impl Drop for MockBlockEngine {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jito::{_get_bundle_status, _send_jito_tx, _send_out_bundle};
+    use futures::future::join_all;
+    use futures_util::StreamExt;
+    use jito_protos::searcher::searcher_service_client::SearcherServiceClient;
+    use jito_searcher_client::{get_searcher_client, send_bundle_no_wait};
+    use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::{v0, VersionedMessage};
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction::transfer;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_mock_block_engine_grpc() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        engine.set_slot(420).await;
+        let auth = Arc::new(Keypair::new());
+        let mut searcher_client =
+            get_searcher_client(&engine.url, &auth).await.unwrap();
+        let mut results = searcher_client
+            .subscribe_bundle_results(SubscribeBundleResultsRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+
+        let payer = Keypair::new();
+        let tx = make_tx(&payer, 1);
+        let uuid = send_bundle_no_wait(
+            std::slice::from_ref(&tx),
+            &mut searcher_client,
+        )
+        .await
+        .unwrap()
+        .into_inner()
+        .uuid;
+        let result =
+            tokio::time::timeout(Duration::from_secs(5), results.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+        assert_eq!(result.bundle_id, uuid);
+        assert!(matches!(
+            result.result,
+            Some(bundle_result::Result::Accepted(Accepted { slot: 420, .. }))
+        ));
+
+        engine
+            .script(vec![BundleOutcome::Rejected(
+                "insufficient funds".into(),
+            )])
+            .await;
+        engine.script(vec![BundleOutcome::Dropped]).await;
+        for expected in ["rejected", "dropped"] {
+            let uuid = send_bundle_no_wait(
+                std::slice::from_ref(&tx),
+                &mut searcher_client,
+            )
+            .await
+            .unwrap()
+            .into_inner()
+            .uuid;
+            let result =
+                tokio::time::timeout(Duration::from_secs(5), results.next())
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(result.bundle_id, uuid);
+            match (expected, result.result) {
+                ("rejected", Some(bundle_result::Result::Rejected(r))) => {
+                    let Some(rejected::Reason::SimulationFailure(failure)) =
+                        r.reason
+                    else {
+                        panic!("expected simulation failure");
+                    };
+                    assert_eq!(
+                        failure.tx_signature,
+                        tx.signatures[0].to_string()
+                    );
+                    assert_eq!(
+                        failure.msg.as_deref(),
+                        Some("insufficient funds")
+                    );
+                }
+                ("dropped", Some(bundle_result::Result::Dropped(_))) => {}
+                (expected, result) => {
+                    panic!("expected {}, got {:?}", expected, result)
+                }
+            }
+        }
+
+        let bundles = engine.bundles().await;
+        assert_eq!(bundles.len(), 3);
+        assert_eq!(bundles[0].source, BundleSource::Grpc);
+        assert_eq!(bundles[0].transactions, vec![tx.clone()]);
+
+        // no auth, no bundles
+        let mut unauthenticated =
+            SearcherServiceClient::connect(engine.url.clone())
+                .await
+                .unwrap();
+        let status = unauthenticated
+            .send_bundle(SendBundleRequest { bundle: None })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_mock_block_engine_http() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        let payer = Keypair::new();
+
+        engine.script(vec![BundleOutcome::Dropped]).await;
+        let handles = _send_out_bundle(
+            &[engine.http_url.clone(), engine.http_url.clone()],
+            &[make_tx(&payer, 1), make_tx(&payer, 2)],
+        )
+        .await
+        .unwrap();
+        join_all(handles).await;
+        let bundles = engine.bundles().await;
+        assert_eq!(bundles.len(), 2);
+        assert!(bundles
+            .iter()
+            .all(|b| b.source == BundleSource::Http
+                && b.transactions.len() == 2));
+        assert_eq!(
+            bundles
+                .iter()
+                .filter(|b| b.outcomes == vec![BundleOutcome::Dropped])
+                .count(),
+            1
+        );
+        _get_bundle_status(&engine.http_url, bundles[0].uuid.clone())
+            .await
+            .unwrap();
+
+        // v0 txs go through the same way
+        let to = Pubkey::new_unique();
+        let message = v0::Message::try_compile(
+            &payer.pubkey(),
+            &[transfer(&payer.pubkey(), &to, 3)],
+            &[AddressLookupTableAccount {
+                key: Pubkey::new_unique(),
+                addresses: vec![to],
+            }],
+            Hash::default(),
+        )
+        .unwrap();
+        let tx = VersionedTransaction::try_new(
+            VersionedMessage::V0(message),
+            &[&payer],
+        )
+        .unwrap();
+        _send_jito_tx(&engine.http_url, tx.clone()).await.unwrap();
+        assert_eq!(engine.transactions().await, vec![tx]);
+
+        // statuses follow the scripted outcome
+        let client = reqwest::Client::new();
+        let body = client
+            .post(format!(
+                "{}{}",
+                engine.http_url,
+                crate::jito::JITO_BUNDLES_PATH
+            ))
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "getInflightBundleStatuses",
+                "params": [[bundles[0].uuid, bundles[1].uuid, "unknown"]]
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap();
+        let statuses = body["result"]["value"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|status| status["status"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        let mut landed = statuses[..2].to_vec();
+        landed.sort();
+        assert_eq!(landed, vec!["Failed", "Landed"]);
+        assert_eq!(statuses[2], "Invalid");
+    }
+}
@@ -0,0 +1,1031 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::rpc_response::{RpcLogsResponse, SlotInfo};
+use solana_sdk::account::Account;
+use solana_sdk::clock::Slot;
+use solana_sdk::hash::Hash;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{TransactionError, VersionedTransaction};
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::constants::TOKEN_PROGRAM;
+use crate::events::get_account_keys;
+
+/// pump tokens have 6 decimals, used when the mint is not in the fixtures
+const DEFAULT_DECIMALS: u8 = 6;
+
+/// RpcFixtures is what the mock serves, signatures for an address are
+/// derived from the transactions that include it, newest slot first
+#[derive(Debug, Default)]
+pub struct RpcFixtures {
+    pub slot: Slot,
+    pub block_height: u64,
+    pub blockhash: Hash,
+    pub accounts: HashMap<Pubkey, Account>,
+    pub transactions:
+        HashMap<Signature, EncodedConfirmedTransactionWithStatusMeta>,
+    /// what every simulateTransaction reports
+    pub units_consumed: u64,
+    pub simulation_err: Option<TransactionError>,
+    pub simulation_logs: Vec<String>,
+    /// getRecentPrioritizationFees, one per slot counting back from slot
+    pub prioritization_fees: Vec<u64>,
+}
+
+impl RpcFixtures {
+    pub fn with_account(mut self, pubkey: Pubkey, account: Account) -> Self {
+        self.accounts.insert(pubkey, account);
+        self
+    }
+
+    /// with_token_account adds an initialized spl token account
+    pub fn with_token_account(
+        self,
+        address: Pubkey,
+        mint: Pubkey,
+        owner: Pubkey,
+        amount: u64,
+    ) -> Self {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        self.with_account(
+            address,
+            Account {
+                lamports: 2_039_280,
+                data,
+                owner: Pubkey::from_str(TOKEN_PROGRAM)
+                    .expect("parse token program"),
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+    }
+
+    pub fn with_transaction(
+        mut self,
+        tx: EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Self {
+        let signature =
+            transaction_signature(&tx).expect("fixture tx signature");
+        self.transactions.insert(signature, tx);
+        self
+    }
+}
+
+fn transaction_signature(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Option<Signature> {
+    match &tx.transaction.transaction {
+        EncodedTransaction::Json(ui_tx) => {
+            Signature::from_str(ui_tx.signatures.first()?).ok()
+        }
+        encoded => encoded.decode()?.signatures.first().copied(),
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    fixtures: RpcFixtures,
+    sent_transactions: Vec<VersionedTransaction>,
+    simulated_transactions: Vec<VersionedTransaction>,
+}
+
+type RpcError = (i64, String);
+
+fn invalid_params(message: impl std::fmt::Display) -> RpcError {
+    (-32602, format!("Invalid params: {}", message))
+}
+
+fn param<T: std::str::FromStr>(
+    params: &Value,
+    index: usize,
+) -> Result<T, RpcError>
+where
+    T::Err: std::fmt::Display,
+{
+    params[index]
+        .as_str()
+        .ok_or_else(|| invalid_params(format!("missing param #{}", index)))?
+        .parse::<T>()
+        .map_err(invalid_params)
+}
+
+/// decode_transaction reads the tx of sendTransaction and
+/// simulateTransaction
+fn decode_transaction(
+    params: &Value,
+) -> Result<VersionedTransaction, RpcError> {
+    let encoded = params[0]
+        .as_str()
+        .ok_or_else(|| invalid_params("expected transaction"))?;
+    let data = match params[1]["encoding"].as_str() {
+        Some("base64") => BASE64.decode(encoded).map_err(invalid_params)?,
+        _ => bs58::decode(encoded).into_vec().map_err(invalid_params)?,
+    };
+    bincode::deserialize(&data).map_err(invalid_params)
+}
+
+fn encoding(params: &Value, index: usize) -> UiAccountEncoding {
+    serde_json::from_value(params[index]["encoding"].clone())
+        .unwrap_or(UiAccountEncoding::Binary)
+}
+
+impl MockState {
+    fn with_context(&self, value: Value) -> Value {
+        json!({
+            "context": { "slot": self.fixtures.slot },
+            "value": value,
+        })
+    }
+
+    fn decimals(&self, mint: &Pubkey) -> u8 {
+        self.fixtures
+            .accounts
+            .get(mint)
+            .and_then(|account| {
+                spl_token::state::Mint::unpack(&account.data).ok()
+            })
+            .map(|mint| mint.decimals)
+            .unwrap_or(DEFAULT_DECIMALS)
+    }
+
+    fn token_account(
+        &self,
+        address: &Pubkey,
+    ) -> Option<spl_token::state::Account> {
+        spl_token::state::Account::unpack(
+            &self.fixtures.accounts.get(address)?.data,
+        )
+        .ok()
+    }
+
+    fn token_amount(&self, amount: u64, decimals: u8) -> Value {
+        let ui_amount = amount as f64 / 10f64.powi(decimals as i32);
+        json!({
+            "amount": amount.to_string(),
+            "decimals": decimals,
+            "uiAmount": ui_amount,
+            "uiAmountString": ui_amount.to_string(),
+        })
+    }
+
+    /// encode_account mirrors the rpc, jsonParsed is only implemented for
+    /// token accounts, everything else falls back to base64
+    fn encode_account(
+        &self,
+        pubkey: &Pubkey,
+        account: &Account,
+        encoding: UiAccountEncoding,
+    ) -> Value {
+        if encoding == UiAccountEncoding::JsonParsed {
+            if let Some(token_account) = self.token_account(pubkey) {
+                let decimals = self.decimals(&token_account.mint);
+                return json!({
+                    "lamports": account.lamports,
+                    "data": {
+                        "program": "spl-token",
+                        "parsed": {
+                            "info": {
+                                "isNative": false,
+                                "mint": token_account.mint.to_string(),
+                                "owner": token_account.owner.to_string(),
+                                "state": "initialized",
+                                "tokenAmount": self.token_amount(
+                                    token_account.amount,
+                                    decimals
+                                ),
+                            },
+                            "type": "account",
+                        },
+                        "space": account.data.len(),
+                    },
+                    "owner": account.owner.to_string(),
+                    "executable": account.executable,
+                    "rentEpoch": account.rent_epoch,
+                    "space": account.data.len(),
+                });
+            }
+        }
+        let encoding = match encoding {
+            UiAccountEncoding::JsonParsed => UiAccountEncoding::Base64,
+            encoding => encoding,
+        };
+        json!(UiAccount::encode(pubkey, account, encoding, None, None))
+    }
+
+    fn handle(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "getHealth" => Ok(json!("ok")),
+            "getVersion" => {
+                Ok(json!({ "solana-core": "2.0.3", "feature-set": 0 }))
+            }
+            "getSlot" => Ok(json!(self.fixtures.slot)),
+            "getBlockHeight" => Ok(json!(self.fixtures.block_height)),
+            "getMinimumBalanceForRentExemption" => {
+                let data_len = params[0]
+                    .as_u64()
+                    .ok_or_else(|| invalid_params("expected data length"))?;
+                Ok(json!(Rent::default().minimum_balance(data_len as usize)))
+            }
+            "getLatestBlockhash" => Ok(self.with_context(json!({
+                "blockhash": self.fixtures.blockhash.to_string(),
+                "lastValidBlockHeight": self.fixtures.block_height + 150,
+            }))),
+            "getBalance" => {
+                let pubkey: Pubkey = param(params, 0)?;
+                let lamports = self
+                    .fixtures
+                    .accounts
+                    .get(&pubkey)
+                    .map(|account| account.lamports)
+                    .unwrap_or_default();
+                Ok(self.with_context(json!(lamports)))
+            }
+            "getAccountInfo" => {
+                let pubkey: Pubkey = param(params, 0)?;
+                let value = match self.fixtures.accounts.get(&pubkey) {
+                    Some(account) => self.encode_account(
+                        &pubkey,
+                        account,
+                        encoding(params, 1),
+                    ),
+                    None => Value::Null,
+                };
+                Ok(self.with_context(value))
+            }
+            "getTokenAccountBalance" => {
+                let pubkey: Pubkey = param(params, 0)?;
+                let token_account =
+                    self.token_account(&pubkey).ok_or_else(|| {
+                        invalid_params("could not find account")
+                    })?;
+                let decimals = self.decimals(&token_account.mint);
+                Ok(self.with_context(
+                    self.token_amount(token_account.amount, decimals),
+                ))
+            }
+            "getTokenAccountsByOwner" => {
+                let owner: Pubkey = param(params, 0)?;
+                let mint = params[1]["mint"]
+                    .as_str()
+                    .map(Pubkey::from_str)
+                    .transpose()
+                    .map_err(invalid_params)?;
+                let encoding = encoding(params, 2);
+                let mut accounts = self
+                    .fixtures
+                    .accounts
+                    .iter()
+                    .filter_map(|(pubkey, account)| {
+                        let token_account = self.token_account(pubkey)?;
+                        if token_account.owner != owner
+                            || mint.is_some_and(|mint| {
+                                mint != token_account.mint
+                            })
+                        {
+                            return None;
+                        }
+                        Some(json!({
+                            "pubkey": pubkey.to_string(),
+                            "account": self.encode_account(
+                                pubkey, account, encoding
+                            ),
+                        }))
+                    })
+                    .collect::<Vec<_>>();
+                accounts.sort_by_key(|account| account["pubkey"].to_string());
+                Ok(self.with_context(json!(accounts)))
+            }
+            "getSignaturesForAddress" => {
+                let address: Pubkey = param(params, 0)?;
+                let limit =
+                    params[1]["limit"].as_u64().unwrap_or(1000) as usize;
+                let mut signatures = self
+                    .fixtures
+                    .transactions
+                    .iter()
+                    .filter(|(_, tx)| {
+                        get_account_keys(tx).contains(&address.to_string())
+                    })
+                    .map(|(signature, tx)| (tx.slot, signature, tx))
+                    .collect::<Vec<_>>();
+                signatures.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+                let signatures = signatures
+                    .into_iter()
+                    .take(limit)
+                    .map(|(slot, signature, tx)| {
+                        json!({
+                            "signature": signature.to_string(),
+                            "slot": slot,
+                            "err": tx
+                                .transaction
+                                .meta
+                                .as_ref()
+                                .and_then(|meta| meta.err.clone()),
+                            "memo": null,
+                            "blockTime": tx.block_time,
+                            "confirmationStatus": "finalized",
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Ok(json!(signatures))
+            }
+            "getTransaction" => {
+                let signature: Signature = param(params, 0)?;
+                Ok(json!(self.fixtures.transactions.get(&signature)))
+            }
+            "getSignatureStatuses" => {
+                let statuses = params[0]
+                    .as_array()
+                    .ok_or_else(|| invalid_params("expected signatures"))?
+                    .iter()
+                    .map(|signature| {
+                        let signature = signature
+                            .as_str()
+                            .and_then(|s| Signature::from_str(s).ok())?;
+                        let (slot, err) = if let Some(tx) =
+                            self.fixtures.transactions.get(&signature)
+                        {
+                            (
+                                tx.slot,
+                                tx.transaction
+                                    .meta
+                                    .as_ref()
+                                    .and_then(|meta| meta.err.clone()),
+                            )
+                        } else if self.sent_transactions.iter().any(|tx| {
+                            tx.signatures.first() == Some(&signature)
+                        }) {
+                            (self.fixtures.slot, None)
+                        } else {
+                            return None;
+                        };
+                        let status: Result<(), TransactionError> = match &err
+                        {
+                            Some(err) => Err(err.clone()),
+                            None => Ok(()),
+                        };
+                        Some(json!({
+                            "slot": slot,
+                            "confirmations": null,
+                            "err": err,
+                            "status": status,
+                            "confirmationStatus": "confirmed",
+                        }))
+                    })
+                    .collect::<Vec<_>>();
+                Ok(self.with_context(json!(statuses)))
+            }
+            "sendTransaction" => {
+                let tx = decode_transaction(params)?;
+                let signature = *tx
+                    .signatures
+                    .first()
+                    .ok_or_else(|| invalid_params("unsigned transaction"))?;
+                debug!("Mock RPC received {}", signature);
+                self.sent_transactions.push(tx);
+                Ok(json!(signature.to_string()))
+            }
+            "simulateTransaction" => {
+                let tx = decode_transaction(params)?;
+                self.simulated_transactions.push(tx);
+                Ok(self.with_context(json!({
+                    "err": self.fixtures.simulation_err,
+                    "logs": self.fixtures.simulation_logs,
+                    "accounts": null,
+                    "unitsConsumed": self.fixtures.units_consumed,
+                    "returnData": null,
+                })))
+            }
+            "getRecentPrioritizationFees" => {
+                let fees = self
+                    .fixtures
+                    .prioritization_fees
+                    .iter()
+                    .enumerate()
+                    .map(|(i, fee)| {
+                        json!({
+                            "slot": self.fixtures.slot.saturating_sub(i as u64),
+                            "prioritizationFee": fee,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Ok(json!(fees))
+            }
+            _ => Err((-32601, format!("Method not found: {}", method))),
+        }
+    }
+}
+
+/// MockRpcServer serves JSON-RPC and PubSub from fixtures on localhost, for
+/// tests that would otherwise need RPC_URL and WS_URL, the servers stop
+/// when it is dropped
+pub struct MockRpcServer {
+    pub rpc_url: String,
+    pub ws_url: String,
+    state: Arc<RwLock<MockState>>,
+    slots: broadcast::Sender<SlotInfo>,
+    logs: broadcast::Sender<(Slot, RpcLogsResponse)>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl MockRpcServer {
+    pub async fn start(
+        fixtures: RpcFixtures,
+    ) -> Result<Self, Box<dyn Error>> {
+        let state = Arc::new(RwLock::new(MockState {
+            fixtures,
+            ..Default::default()
+        }));
+        let (slots, _) = broadcast::channel(1024);
+        let (logs, _) = broadcast::channel(1024);
+
+        let rpc_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let rpc_url = format!("http://{}", rpc_listener.local_addr()?);
+        let ws_url = format!("ws://{}", ws_listener.local_addr()?);
+
+        let handles = vec![
+            tokio::spawn(serve_rpc(rpc_listener, state.clone())),
+            tokio::spawn(serve_pubsub(
+                ws_listener,
+                slots.clone(),
+                logs.clone(),
+            )),
+        ];
+
+        Ok(Self {
+            rpc_url,
+            ws_url,
+            state,
+            slots,
+            logs,
+            handles,
+        })
+    }
+
+    pub async fn set_account(&self, pubkey: Pubkey, account: Account) {
+        self.state
+            .write()
+            .await
+            .fixtures
+            .accounts
+            .insert(pubkey, account);
+    }
+
+    pub async fn add_transaction(
+        &self,
+        tx: EncodedConfirmedTransactionWithStatusMeta,
+    ) {
+        let mut state = self.state.write().await;
+        state.fixtures =
+            std::mem::take(&mut state.fixtures).with_transaction(tx);
+    }
+
+    pub async fn set_blockhash(&self, blockhash: Hash) {
+        self.state.write().await.fixtures.blockhash = blockhash;
+    }
+
+    /// sent_transactions returns everything that came in through
+    /// sendTransaction, in order
+    pub async fn sent_transactions(&self) -> Vec<VersionedTransaction> {
+        self.state.read().await.sent_transactions.clone()
+    }
+
+    /// set_simulation_err makes the next simulations fail, None for them
+    /// to succeed again
+    pub async fn set_simulation_err(
+        &self,
+        err: Option<TransactionError>,
+        logs: Vec<String>,
+    ) {
+        let mut state = self.state.write().await;
+        state.fixtures.simulation_err = err;
+        state.fixtures.simulation_logs = logs;
+    }
+
+    /// set_units_consumed changes what the next simulations consume
+    pub async fn set_units_consumed(&self, units_consumed: u64) {
+        self.state.write().await.fixtures.units_consumed = units_consumed;
+    }
+
+    /// simulated_transactions returns everything that came in through
+    /// simulateTransaction, in order
+    pub async fn simulated_transactions(&self) -> Vec<VersionedTransaction> {
+        self.state.read().await.simulated_transactions.clone()
+    }
+
+    /// slot_subscribers is how many slotSubscribe subscriptions are open,
+    /// a slot pushed before there are any is lost
+    pub fn slot_subscribers(&self) -> usize {
+        self.slots.receiver_count()
+    }
+
+    /// push_slot bumps the served slot and notifies slot subscribers
+    pub async fn push_slot(&self, slot: Slot) {
+        self.state.write().await.fixtures.slot = slot;
+        let _ = self.slots.send(SlotInfo {
+            slot,
+            parent: slot.saturating_sub(1),
+            root: slot.saturating_sub(32),
+        });
+    }
+
+    /// push_logs notifies logs subscribers, a mentions filter matches if the
+    /// pubkey appears in any of the log lines
+    pub async fn push_logs(
+        &self,
+        signature: Signature,
+        err: Option<TransactionError>,
+        logs: Vec<String>,
+    ) {
+        let slot = self.state.read().await.fixtures.slot;
+        let _ = self.logs.send((
+            slot,
+            RpcLogsResponse {
+                signature: signature.to_string(),
+                err,
+                logs,
+            },
+        ));
+    }
+}
+
+impl Drop for MockRpcServer {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+async fn handle_rpc_request(
+    req: Request<Incoming>,
+    state: Arc<RwLock<MockState>>,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let body = req.into_body().collect().await?.to_bytes();
+    let response = match serde_json::from_slice::<Value>(&body) {
+        Ok(request) => {
+            let method = request["method"].as_str().unwrap_or_default();
+            let result =
+                state.write().await.handle(method, &request["params"]);
+            match result {
+                Ok(result) => json!({
+                    "jsonrpc": "2.0",
+                    "result": result,
+                    "id": request["id"],
+                }),
+                Err((code, message)) => {
+                    warn!("Mock RPC {} failed: {}", method, message);
+                    json!({
+                        "jsonrpc": "2.0",
+                        "error": { "code": code, "message": message },
+                        "id": request["id"],
+                    })
+                }
+            }
+        }
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32700, "message": e.to_string() },
+            "id": null,
+        }),
+    };
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(response.to_string())))
+        .expect("build response"))
+}
+
+async fn serve_rpc(listener: TcpListener, state: Arc<RwLock<MockState>>) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Mock RPC accept failed: {}", e);
+                continue;
+            }
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service =
+                service_fn(move |req| handle_rpc_request(req, state.clone()));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("Mock RPC connection closed: {}", e);
+            }
+        });
+    }
+}
+
+async fn serve_pubsub(
+    listener: TcpListener,
+    slots: broadcast::Sender<SlotInfo>,
+    logs: broadcast::Sender<(Slot, RpcLogsResponse)>,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Mock PubSub accept failed: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(handle_pubsub_connection(
+            stream,
+            addr,
+            slots.clone(),
+            logs.clone(),
+        ));
+    }
+}
+
+fn notification(method: &str, subscription: u64, result: Value) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": { "result": result, "subscription": subscription },
+    })
+    .to_string()
+}
+
+/// mentions filter of a logsSubscribe request, None means all
+fn logs_mentions(params: &Value) -> Option<Vec<String>> {
+    let mentions = params[0]["mentions"].as_array()?;
+    Some(
+        mentions
+            .iter()
+            .filter_map(|mention| mention.as_str().map(String::from))
+            .collect(),
+    )
+}
+
+async fn handle_pubsub_connection(
+    stream: TcpStream,
+    addr: SocketAddr,
+    slots: broadcast::Sender<SlotInfo>,
+    logs: broadcast::Sender<(Slot, RpcLogsResponse)>,
+) {
+    let ws = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            warn!("Mock PubSub handshake with {} failed: {}", addr, e);
+            return;
+        }
+    };
+    let (mut write, mut read) = ws.split();
+    let (out, mut out_rx) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::spawn(async move {
+        while let Some(msg) = out_rx.recv().await {
+            if write.send(Message::Text(msg)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut subscriptions: HashMap<u64, JoinHandle<()>> = HashMap::new();
+    let mut next_subscription = 0u64;
+    while let Some(Ok(msg)) = read.next().await {
+        let Message::Text(text) = msg else {
+            continue;
+        };
+        let Ok(request) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+        let id = request["id"].clone();
+        let method = request["method"].as_str().unwrap_or_default();
+        let result = match method {
+            "slotSubscribe" => {
+                next_subscription += 1;
+                let subscription = next_subscription;
+                let mut rx = slots.subscribe();
+                let out = out.clone();
+                subscriptions.insert(
+                    subscription,
+                    tokio::spawn(async move {
+                        while let Ok(slot_info) = rx.recv().await {
+                            let _ = out.send(notification(
+                                "slotNotification",
+                                subscription,
+                                json!(slot_info),
+                            ));
+                        }
+                    }),
+                );
+                json!(subscription)
+            }
+            "logsSubscribe" => {
+                next_subscription += 1;
+                let subscription = next_subscription;
+                let mentions = logs_mentions(&request["params"]);
+                let mut rx = logs.subscribe();
+                let out = out.clone();
+                subscriptions.insert(
+                    subscription,
+                    tokio::spawn(async move {
+                        while let Ok((slot, response)) = rx.recv().await {
+                            if let Some(mentions) = &mentions {
+                                if !mentions.iter().any(|mention| {
+                                    response
+                                        .logs
+                                        .iter()
+                                        .any(|log| log.contains(mention))
+                                }) {
+                                    continue;
+                                }
+                            }
+                            let _ = out.send(notification(
+                                "logsNotification",
+                                subscription,
+                                json!({
+                                    "context": { "slot": slot },
+                                    "value": response,
+                                }),
+                            ));
+                        }
+                    }),
+                );
+                json!(subscription)
+            }
+            "slotUnsubscribe" | "logsUnsubscribe" => {
+                let removed = request["params"][0]
+                    .as_u64()
+                    .and_then(|subscription| {
+                        subscriptions.remove(&subscription)
+                    })
+                    .map(|handle| handle.abort())
+                    .is_some();
+                json!(removed)
+            }
+            _ => {
+                let _ = out.send(
+                    json!({
+                        "jsonrpc": "2.0",
+                        "error": {
+                            "code": -32601,
+                            "message": format!("Method not found: {}", method),
+                        },
+                        "id": id,
+                    })
+                    .to_string(),
+                );
+                continue;
+            }
+        };
+        let _ = out.send(
+            json!({ "jsonrpc": "2.0", "result": result, "id": id })
+                .to_string(),
+        );
+    }
+
+    for (_, handle) in subscriptions {
+        handle.abort();
+    }
+    writer.abort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global::{
+        get_pump_global, PumpGlobal, PUMP_GLOBAL_DISCRIMINATOR,
+    };
+    use crate::seller::{
+        get_token_balance_with_retries, get_tx_with_retries,
+    };
+    use solana_client::nonblocking::pubsub_client::PubsubClient;
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_client::rpc_config::{
+        RpcTransactionLogsConfig, RpcTransactionLogsFilter,
+    };
+    use solana_client::rpc_request::TokenAccountsFilter;
+    use solana_sdk::commitment_config::CommitmentConfig;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::system_instruction::transfer;
+    use solana_sdk::transaction::Transaction;
+    use std::time::Duration;
+
+    fn make_tx(
+        payer: &Keypair,
+        to: &Pubkey,
+        lamports: u64,
+    ) -> VersionedTransaction {
+        VersionedTransaction::from(Transaction::new_signed_with_payer(
+            &[transfer(&payer.pubkey(), to, lamports)],
+            Some(&payer.pubkey()),
+            &[payer],
+            Hash::default(),
+        ))
+    }
+
+    fn make_encoded_tx(
+        tx: &VersionedTransaction,
+        slot: Slot,
+    ) -> EncodedConfirmedTransactionWithStatusMeta {
+        serde_json::from_value(json!({
+            "slot": slot,
+            "transaction": [
+                BASE64.encode(bincode::serialize(tx).unwrap()),
+                "base64"
+            ],
+            "meta": null,
+            "blockTime": null,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mock_rpc() {
+        let owner = Keypair::new();
+        let mint = Pubkey::new_unique();
+        let ata = spl_associated_token_account::get_associated_token_address(
+            &owner.pubkey(),
+            &mint,
+        );
+        let mut global_data = PUMP_GLOBAL_DISCRIMINATOR.to_vec();
+        global_data.resize(PumpGlobal::LEN, 0);
+        let older = make_tx(&owner, &mint, 1);
+        let newer = make_tx(&owner, &mint, 2);
+        let fixtures = RpcFixtures {
+            slot: 280_000_000,
+            blockhash: Hash::new_unique(),
+            ..Default::default()
+        }
+        .with_account(
+            Pubkey::from_str(crate::constants::PUMP_GLOBAL_ADDRESS).unwrap(),
+            Account {
+                lamports: 1,
+                data: global_data,
+                owner: Pubkey::from_str(crate::constants::PUMP_FUN_PROGRAM)
+                    .unwrap(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .with_token_account(ata, mint, owner.pubkey(), 420_000_000)
+        .with_transaction(make_encoded_tx(&older, 1))
+        .with_transaction(make_encoded_tx(&newer, 2));
+        let blockhash = fixtures.blockhash;
+        let server = MockRpcServer::start(fixtures).await.unwrap();
+        let rpc_client = RpcClient::new(server.rpc_url.clone());
+
+        assert_eq!(
+            rpc_client.get_latest_blockhash().await.unwrap(),
+            blockhash
+        );
+        assert_eq!(rpc_client.get_slot().await.unwrap(), 280_000_000);
+        let global = get_pump_global(&rpc_client).await.unwrap();
+        assert!(!global.initialized);
+        assert!(rpc_client.get_account(&Pubkey::new_unique()).await.is_err());
+
+        let signatures =
+            rpc_client.get_signatures_for_address(&mint).await.unwrap();
+        assert_eq!(
+            signatures
+                .iter()
+                .map(|s| s.signature.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                newer.signatures[0].to_string(),
+                older.signatures[0].to_string()
+            ]
+        );
+        let tx = get_tx_with_retries(&rpc_client, &older.signatures[0])
+            .await
+            .unwrap();
+        assert_eq!(tx.slot, 1);
+
+        assert_eq!(
+            get_token_balance_with_retries(&rpc_client, &ata)
+                .await
+                .unwrap(),
+            420_000_000
+        );
+        let token_accounts = rpc_client
+            .get_token_accounts_by_owner(
+                &owner.pubkey(),
+                TokenAccountsFilter::Mint(mint),
+            )
+            .await
+            .unwrap();
+        assert_eq!(token_accounts.len(), 1);
+        assert_eq!(token_accounts[0].pubkey, ata.to_string());
+        assert!(rpc_client
+            .get_token_accounts_by_owner(
+                &Pubkey::new_unique(),
+                TokenAccountsFilter::Mint(mint),
+            )
+            .await
+            .unwrap()
+            .is_empty());
+
+        let tx = make_tx(&owner, &mint, 3);
+        let signature = rpc_client.send_transaction(&tx).await.unwrap();
+        assert_eq!(signature, tx.signatures[0]);
+        assert_eq!(server.sent_transactions().await, vec![tx]);
+        assert!(
+            rpc_client
+                .confirm_transaction_with_commitment(
+                    &signature,
+                    CommitmentConfig::confirmed()
+                )
+                .await
+                .unwrap()
+                .value
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_pubsub() {
+        let server =
+            MockRpcServer::start(RpcFixtures::default()).await.unwrap();
+        let pubsub_client = PubsubClient::new(&server.ws_url).await.unwrap();
+        let wallet = Pubkey::new_unique();
+
+        let (mut slots, slot_unsub) =
+            pubsub_client.slot_subscribe().await.unwrap();
+        let (mut logs, logs_unsub) = pubsub_client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![wallet.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(CommitmentConfig::processed()),
+                },
+            )
+            .await
+            .unwrap();
+
+        server.push_slot(420).await;
+        let slot_info =
+            tokio::time::timeout(Duration::from_secs(5), slots.next())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(slot_info.slot, 420);
+
+        // filtered out, does not mention the wallet
+        server
+            .push_logs(
+                Signature::new_unique(),
+                None,
+                vec!["Program 11111111111111111111111111111111 invoke [1]"
+                    .to_string()],
+            )
+            .await;
+        let signature = Signature::new_unique();
+        server
+            .push_logs(
+                signature,
+                None,
+                vec![format!("Program log: Transfer from {}", wallet)],
+            )
+            .await;
+        let response =
+            tokio::time::timeout(Duration::from_secs(5), logs.next())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(response.context.slot, 420);
+        assert_eq!(response.value.signature, signature.to_string());
+
+        slot_unsub().await;
+        logs_unsub().await;
+    }
+}
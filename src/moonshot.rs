@@ -1,7 +1,9 @@
+use log::warn;
 use solana_sdk::instruction::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
 
+use crate::compute::{ComputeBudget, ComputeEstimator};
 use crate::constants::SYSTEM_PROGRAM_ID;
 use crate::util::make_compute_budget_ixs;
 
@@ -12,6 +14,12 @@ pub const MOONSHOT_PROGRAM: &str =
 pub const CONFIG_ACCOUNT: &str =
     "36Eru7v11oU5Pfrojyn5oY3nETA1a1iqsw2WUu6afkM9";
 
+/// the trade budget when the estimate fails
+pub const MOONSHOT_COMPUTE_BUDGET: ComputeBudget = ComputeBudget {
+    price: 69,
+    units: 200_000,
+};
+
 #[derive(borsh::BorshSerialize)]
 struct TradeParams {
     token_amount: u64,
@@ -95,27 +103,47 @@ impl Moonshot {
         ]
     }
 
-    pub async fn buy(&self, token_amount: u64, collateral_amount: u64) {
-        let mut ixs = vec![];
-        ixs.append(&mut make_compute_budget_ixs(69, 200000));
+    pub async fn buy(
+        &self,
+        estimator: &ComputeEstimator,
+        token_amount: u64,
+        collateral_amount: u64,
+    ) -> Vec<Instruction> {
+        self._make_trade_ixs(
+            estimator,
+            self._make_swap_ix(token_amount, collateral_amount, 1, 1000),
+        )
+        .await
+    }
 
-        ixs.push(self._make_swap_ix(
-            token_amount,
-            collateral_amount,
-            1,
-            1000,
-        ));
+    pub async fn sell(
+        &self,
+        estimator: &ComputeEstimator,
+        token_amount: u64,
+        collateral_amount: u64,
+    ) -> Vec<Instruction> {
+        self._make_trade_ixs(
+            estimator,
+            self._make_swap_ix(token_amount, collateral_amount, 0, 1000),
+        )
+        .await
     }
 
-    pub async fn sell(&self, token_amount: u64, collateral_amount: u64) {
-        let mut ixs = vec![];
-        ixs.append(&mut make_compute_budget_ixs(69, 200000));
-        ixs.push(self._make_swap_ix(
-            token_amount,
-            collateral_amount,
-            0,
-            1000,
-        ));
+    async fn _make_trade_ixs(
+        &self,
+        estimator: &ComputeEstimator,
+        swap_ix: Instruction,
+    ) -> Vec<Instruction> {
+        let budget = estimator
+            .estimate(&self.sender, std::slice::from_ref(&swap_ix), &[])
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Using the fallback compute budget: {}", e);
+                MOONSHOT_COMPUTE_BUDGET
+            });
+        let mut ixs = make_compute_budget_ixs(budget.price, budget.units);
+        ixs.push(swap_ix);
+        ixs
     }
 }
 
@@ -0,0 +1,462 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::debug;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::decode_error::DecodeError;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::{TransactionError, VersionedTransaction};
+use spl_token::error::TokenError;
+
+use crate::constants::{PUMP_FUN_PROGRAM, SLOT_CHECKER_MAINNET};
+use crate::sender::{SendResult, Transport, TxSender};
+
+/// PumpError is why a pump tx failed, as far as the simulation tells
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PumpError {
+    /// TooMuchSolRequired on buys, TooLittleSolReceived on sells
+    SlippageExceeded,
+    /// the curve is done, the token trades on the amm now
+    CurveComplete,
+    /// not enough tokens left on the curve
+    InsufficientReserves,
+    /// the slot checker ix ran past its deadline
+    DeadlinePassed,
+    Token(TokenError),
+    /// any other pump error, the name is from the anchor logs
+    Program {
+        code: u32,
+        name: Option<String>,
+    },
+    Transaction(TransactionError),
+}
+
+impl fmt::Display for PumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PumpError::SlippageExceeded => write!(f, "Slippage exceeded"),
+            PumpError::CurveComplete => write!(f, "Bonding curve complete"),
+            PumpError::InsufficientReserves => {
+                write!(f, "Insufficient curve reserves")
+            }
+            PumpError::DeadlinePassed => write!(f, "Deadline passed"),
+            PumpError::Token(e) => write!(f, "Token program: {}", e),
+            PumpError::Program { code, name } => match name {
+                Some(name) => write!(f, "Pump error {} ({})", name, code),
+                None => write!(f, "Pump error {}", code),
+            },
+            PumpError::Transaction(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// PreflightError is a failed simulation, with the log lines that explain
+/// it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightError {
+    pub error: PumpError,
+    pub logs: Vec<String>,
+}
+
+impl fmt::Display for PreflightError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Preflight failed: {}", self.error)?;
+        if !self.logs.is_empty() {
+            write!(f, " [{}]", self.logs.join(" | "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for PreflightError {}
+
+/// failed_program is the innermost program that failed, the first one
+/// logging "Program <id> failed", CPI errors surface on the outer ix
+fn failed_program(logs: &[String]) -> Option<Pubkey> {
+    logs.iter().find_map(|log| {
+        let rest = log.strip_prefix("Program ")?;
+        let (program_id, rest) = rest.split_once(' ')?;
+        if !rest.starts_with("failed") {
+            return None;
+        }
+        Pubkey::from_str(program_id).ok()
+    })
+}
+
+/// anchor logs "Error Code: TooMuchSolRequired. Error Number: 6002."
+fn anchor_error_name(logs: &[String]) -> Option<String> {
+    logs.iter().find_map(|log| {
+        let (_, rest) = log.split_once("Error Code: ")?;
+        let (name, _) = rest.split_once('.')?;
+        Some(name.to_string())
+    })
+}
+
+fn decode_pump_error(code: u32, logs: &[String]) -> PumpError {
+    let name = anchor_error_name(logs);
+    match (name.as_deref(), code) {
+        (Some("TooMuchSolRequired" | "TooLittleSolReceived"), _)
+        | (None, 6002 | 6003) => PumpError::SlippageExceeded,
+        (Some("BondingCurveComplete"), _) | (None, 6005) => {
+            PumpError::CurveComplete
+        }
+        (Some(name), _)
+            if name.starts_with("NotEnoughTokens")
+                || name.starts_with("Insufficient") =>
+        {
+            PumpError::InsufficientReserves
+        }
+        _ => PumpError::Program { code, name },
+    }
+}
+
+/// program_ids is the program of each ix of the tx, in order
+pub fn program_ids(tx: &VersionedTransaction) -> Vec<Pubkey> {
+    let keys = tx.message.static_account_keys();
+    tx.message
+        .instructions()
+        .iter()
+        .map(|ix| {
+            keys.get(ix.program_id_index as usize)
+                .copied()
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// decode_failure maps a simulation error of the tx to a PumpError
+pub fn decode_failure(
+    tx: &VersionedTransaction,
+    err: &TransactionError,
+    logs: &[String],
+) -> PumpError {
+    decode_program_failure(&program_ids(tx), err, logs)
+}
+
+/// decode_rejection is decode_failure for the message of a bundle rejected
+/// in simulation, e.g. "Error processing Instruction 4: custom program
+/// error: 0x1", programs are the program_ids of the failed tx, None if the
+/// message is not about a custom error of one of them
+pub fn decode_rejection(msg: &str, programs: &[Pubkey]) -> Option<PumpError> {
+    let (_, rest) = msg.split_once("Error processing Instruction ")?;
+    let index = rest
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()?;
+    let (_, rest) = rest.split_once("custom program error: 0x")?;
+    let code = u32::from_str_radix(
+        &rest
+            .chars()
+            .take_while(char::is_ascii_hexdigit)
+            .collect::<String>(),
+        16,
+    )
+    .ok()?;
+    if index as usize >= programs.len() {
+        return None;
+    }
+    Some(decode_program_failure(
+        programs,
+        &TransactionError::InstructionError(
+            index,
+            InstructionError::Custom(code),
+        ),
+        &[],
+    ))
+}
+
+fn decode_program_failure(
+    programs: &[Pubkey],
+    err: &TransactionError,
+    logs: &[String],
+) -> PumpError {
+    let TransactionError::InstructionError(index, ix_err) = err else {
+        return PumpError::Transaction(err.clone());
+    };
+    let program_id = failed_program(logs)
+        .or_else(|| programs.get(*index as usize).copied());
+    let Some(program_id) = program_id else {
+        return PumpError::Transaction(err.clone());
+    };
+    let program = program_id.to_string();
+    match (program.as_str(), ix_err) {
+        (SLOT_CHECKER_MAINNET, _) => PumpError::DeadlinePassed,
+        (PUMP_FUN_PROGRAM, InstructionError::Custom(code)) => {
+            decode_pump_error(*code, logs)
+        }
+        (_, InstructionError::Custom(code))
+            if program_id == spl_token::id() =>
+        {
+            match TokenError::decode_custom_error_to_enum(*code) {
+                Some(token_error) => PumpError::Token(token_error),
+                None => PumpError::Transaction(err.clone()),
+            }
+        }
+        _ => PumpError::Transaction(err.clone()),
+    }
+}
+
+/// relevant_logs are the lines about the failure, not every invoke and
+/// consumed units line
+pub fn relevant_logs(logs: &[String]) -> Vec<String> {
+    logs.iter()
+        .filter(|log| {
+            let log = log.to_lowercase();
+            ["error", "failed", "insufficient", "exceeded"]
+                .iter()
+                .any(|word| log.contains(word))
+        })
+        .cloned()
+        .collect()
+}
+
+/// preflight simulates the signed tx, a failure comes back as a
+/// PreflightError
+pub async fn preflight(
+    rpc_client: &RpcClient,
+    tx: &VersionedTransaction,
+) -> Result<(), Box<dyn Error>> {
+    let result = rpc_client
+        .simulate_transaction_with_config(
+            tx,
+            RpcSimulateTransactionConfig {
+                commitment: Some(CommitmentConfig::processed()),
+                ..Default::default()
+            },
+        )
+        .await?
+        .value;
+    let logs = result.logs.unwrap_or_default();
+    if let Some(err) = result.err {
+        return Err(PreflightError {
+            error: decode_failure(tx, &err, &logs),
+            logs: relevant_logs(&logs),
+        }
+        .into());
+    }
+    debug!(
+        "Preflight of {} ok, {:?} units",
+        tx.signatures[0], result.units_consumed
+    );
+    Ok(())
+}
+
+/// PreflightSender simulates every tx before handing it to the inner
+/// sender, a tx that would fail is not sent
+pub struct PreflightSender {
+    inner: Arc<dyn TxSender>,
+    rpc_client: Arc<RpcClient>,
+}
+
+impl PreflightSender {
+    pub fn new(inner: Arc<dyn TxSender>, rpc_client: Arc<RpcClient>) -> Self {
+        Self { inner, rpc_client }
+    }
+}
+
+#[async_trait]
+impl TxSender for PreflightSender {
+    fn transport(&self) -> Transport {
+        self.inner.transport()
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>> {
+        preflight(&self.rpc_client, tx).await?;
+        self.inner.send_transaction(tx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global::PumpGlobal;
+    use crate::mock_rpc::{MockRpcServer, RpcFixtures};
+    use crate::pump::{mint_to_pump_accounts, PumpAccounts, PumpIxVersion};
+    use crate::sender::RpcSender;
+    use crate::tx_builder::PumpTxBuilder;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn buy_tx(wallet: &Keypair) -> VersionedTransaction {
+        PumpTxBuilder::new(wallet.pubkey())
+            .compute_budget(1_000, 100_000)
+            .buy(
+                PumpAccounts {
+                    dev: Pubkey::new_unique(),
+                    ..mint_to_pump_accounts(&Pubkey::new_unique())
+                },
+                PumpGlobal::default().fee_recipient,
+                1_000,
+                2_000,
+                PumpIxVersion::default(),
+            )
+            .unwrap()
+            .deadline(420)
+            .build(&[wallet], Hash::new_unique())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decode_failure() {
+        let tx = buy_tx(&Keypair::new());
+        // compute budget x2, ata create, buy, deadline
+        let slippage = logs(&[
+            "Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P invoke [1]",
+            "Program log: Instruction: Buy",
+            "Program log: AnchorError thrown in programs/pump/src/lib.rs:1. Error Code: TooMuchSolRequired. Error Number: 6002. Error Message: slippage: Too much SOL required to buy the given amount of tokens..",
+            "Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P consumed 30000 of 98000 compute units",
+            "Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P failed: custom program error: 0x1772",
+        ]);
+        let err = TransactionError::InstructionError(
+            3,
+            InstructionError::Custom(6002),
+        );
+        assert_eq!(
+            decode_failure(&tx, &err, &slippage),
+            PumpError::SlippageExceeded
+        );
+        // the anchor error and the failure, not the invoke and units
+        assert_eq!(
+            relevant_logs(&slippage),
+            vec![slippage[2].clone(), slippage[4].clone()]
+        );
+        // no logs, the code is enough
+        assert_eq!(
+            decode_failure(
+                &tx,
+                &TransactionError::InstructionError(
+                    3,
+                    InstructionError::Custom(6005)
+                ),
+                &[]
+            ),
+            PumpError::CurveComplete
+        );
+        assert_eq!(
+            decode_failure(
+                &tx,
+                &TransactionError::InstructionError(
+                    3,
+                    InstructionError::Custom(6023)
+                ),
+                &logs(&["Program log: AnchorError occurred. Error Code: NotEnoughTokensToSell. Error Number: 6023."])
+            ),
+            PumpError::InsufficientReserves
+        );
+
+        // the token program fails inside the pump cpi
+        let token_cpi = logs(&[
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]",
+            "Program log: Error: insufficient funds",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA failed: custom program error: 0x1",
+            "Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P failed: custom program error: 0x1",
+        ]);
+        assert_eq!(
+            decode_failure(
+                &tx,
+                &TransactionError::InstructionError(
+                    3,
+                    InstructionError::Custom(1)
+                ),
+                &token_cpi
+            ),
+            PumpError::Token(TokenError::InsufficientFunds)
+        );
+
+        assert_eq!(
+            decode_failure(
+                &tx,
+                &TransactionError::InstructionError(
+                    4,
+                    InstructionError::Custom(0)
+                ),
+                &[]
+            ),
+            PumpError::DeadlinePassed
+        );
+        assert_eq!(
+            decode_failure(&tx, &TransactionError::BlockhashNotFound, &[]),
+            PumpError::Transaction(TransactionError::BlockhashNotFound)
+        );
+    }
+
+    #[test]
+    fn test_decode_rejection() {
+        let programs = program_ids(&buy_tx(&Keypair::new()));
+        assert_eq!(
+            decode_rejection(
+                "simulation failure 5x: Error processing Instruction 4: custom program error: 0x0",
+                &programs
+            ),
+            Some(PumpError::DeadlinePassed)
+        );
+        assert_eq!(
+            decode_rejection(
+                "Error processing Instruction 3: custom program error: 0x1772",
+                &programs
+            ),
+            Some(PumpError::SlippageExceeded)
+        );
+        // no index, an index past the ixs of the tx, not a custom error
+        for msg in [
+            "custom program error: 0x1772",
+            "Error processing Instruction 9: custom program error: 0x0",
+            "Error processing Instruction 4: invalid account data",
+        ] {
+            assert_eq!(decode_rejection(msg, &programs), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preflight_sender() {
+        let server =
+            MockRpcServer::start(RpcFixtures::default()).await.unwrap();
+        let rpc_client = Arc::new(RpcClient::new(server.rpc_url.clone()));
+        let sender = PreflightSender::new(
+            Arc::new(RpcSender::new(rpc_client.clone())),
+            rpc_client,
+        );
+        let wallet = Keypair::new();
+
+        let tx = buy_tx(&wallet);
+        sender.send_transaction(&tx).await.unwrap();
+        assert_eq!(server.simulated_transactions().await, vec![tx.clone()]);
+        assert_eq!(server.sent_transactions().await, vec![tx]);
+
+        server
+            .set_simulation_err(
+                Some(TransactionError::InstructionError(
+                    3,
+                    InstructionError::Custom(6005),
+                )),
+                logs(&["Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P failed: custom program error: 0x1775"]),
+            )
+            .await;
+        let err =
+            sender.send_transaction(&buy_tx(&wallet)).await.unwrap_err();
+        let err = err.downcast_ref::<PreflightError>().unwrap();
+        assert_eq!(err.error, PumpError::CurveComplete);
+        assert_eq!(err.logs.len(), 1);
+        assert_eq!(server.sent_transactions().await.len(), 1);
+    }
+}
@@ -1,20 +1,15 @@
-use crate::jito::{send_jito_tx, SearcherClient};
-use crate::launcher::apply_fee;
+use crate::dry_run::get_dry_run;
+use crate::jito::{send_bundle, SearcherClient};
 use futures_util::stream::StreamExt;
 use jito_protos::searcher::SubscribeBundleResultsRequest;
-use jito_searcher_client::{
-    send_bundle_no_wait, send_bundle_with_confirmation,
-};
+use jito_searcher_client::send_bundle_with_confirmation;
 use log::{debug, error, info, warn};
 use solana_account_decoder::UiAccountEncoding;
 use solana_client::nonblocking::pubsub_client::PubsubClient;
 use solana_client::rpc_config::{
-    RpcAccountInfoConfig, RpcSendTransactionConfig, RpcTransactionConfig,
-    RpcTransactionLogsConfig, RpcTransactionLogsFilter,
+    RpcAccountInfoConfig, RpcTransactionConfig, RpcTransactionLogsConfig,
+    RpcTransactionLogsFilter,
 };
-use solana_sdk::hash::Hash;
-use solana_sdk::system_instruction::transfer;
-use solana_sdk::transaction::{Transaction, VersionedTransaction};
 use std::error::Error;
 
 use std::str::FromStr;
@@ -23,11 +18,11 @@ use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration};
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshSerialize;
 
 use serde::{Deserialize, Serialize};
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::instruction::{AccountMeta, Instruction};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Keypair, Signature};
@@ -37,16 +32,21 @@ use solana_transaction_status::{
     UiParsedMessage, UiTransactionEncoding,
 };
 
+use crate::compute::{ComputeBudget, ComputeEstimator};
 use crate::constants::{
     ASSOCIATED_TOKEN_PROGRAM, EVENT_AUTHORITY, PUMP_BUY_METHOD,
-    PUMP_FEE_ADDRESS, PUMP_FUN_MINT_AUTHORITY, PUMP_FUN_PROGRAM,
+    PUMP_FEE_PROGRAM, PUMP_FUN_MINT_AUTHORITY, PUMP_FUN_PROGRAM,
     PUMP_GLOBAL_ADDRESS, PUMP_SELL_METHOD, RENT_PROGRAM, SYSTEM_PROGRAM_ID,
     TOKEN_PROGRAM,
 };
+use crate::events::parse_events_from_logs;
+use crate::global::{get_pump_global, PumpGlobal};
+use crate::sender::{SendResult, TxSender};
+use crate::tx_builder::PumpTxBuilder;
 use crate::util::{
-    env, get_jito_tip_pubkey, make_compute_budget_ixs, pubkey_to_string,
-    string_to_pubkey, /*string_to_u64,*/
+    env, pubkey_to_string, string_to_pubkey, /*string_to_u64,*/
 };
+use crate::wallet::TxLifetime;
 
 #[derive(BorshSerialize)]
 pub struct PumpFunSwapInstructionData {
@@ -55,15 +55,33 @@ pub struct PumpFunSwapInstructionData {
     pub lamports: u64,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+/// sha256("account:BondingCurve")[..8]
+pub const BONDING_CURVE_DISCRIMINATOR: [u8; 8] =
+    [23, 183, 248, 55, 96, 216, 172, 96];
+
+/// BondingCurveVersion is the account layout the curve was decoded from, the
+/// program only ever appends fields so every version is a prefix of the next
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BondingCurveVersion {
+    /// reserves, supply and complete flag, 49 bytes
+    #[default]
+    V1,
+    /// creator appended after the complete flag, 81 bytes or more
+    V2,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct BondingCurveLayout {
-    pub blob1: u64,
+    pub discriminator: [u8; 8],
     pub virtual_token_reserves: u64,
     pub virtual_sol_reserves: u64,
     pub real_token_reserves: u64,
     pub real_sol_reserves: u64,
-    pub blob4: u64,
+    pub token_total_supply: u64,
     pub complete: bool,
+    /// only present from V2 onwards
+    pub creator: Option<Pubkey>,
+    pub version: BondingCurveVersion,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -80,9 +98,92 @@ pub struct PumpTokenData {
 
 impl BondingCurveLayout {
     pub const LEN: usize = 8 + 8 + 8 + 8 + 8 + 8 + 1;
+    pub const LEN_V2: usize = Self::LEN + 32;
+
+    /// parse accepts any layout at least LEN long, bytes past the last
+    /// known field are ignored so that appended fields do not break decoding
+    pub fn parse(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if data.len() < Self::LEN {
+            return Err(format!("Invalid data length: {}", data.len()).into());
+        }
+        if data[..8] != BONDING_CURVE_DISCRIMINATOR {
+            return Err("Invalid discriminator, not a bonding curve".into());
+        }
+
+        let u64_at = |offset: usize| -> Result<u64, Box<dyn Error>> {
+            Ok(u64::from_le_bytes(data[offset..offset + 8].try_into()?))
+        };
+
+        let (creator, version) = if data.len() >= Self::LEN_V2 {
+            (
+                Some(Pubkey::try_from(&data[Self::LEN..Self::LEN_V2])?),
+                BondingCurveVersion::V2,
+            )
+        } else {
+            (None, BondingCurveVersion::V1)
+        };
+
+        Ok(Self {
+            discriminator: BONDING_CURVE_DISCRIMINATOR,
+            virtual_token_reserves: u64_at(8)?,
+            virtual_sol_reserves: u64_at(16)?,
+            real_token_reserves: u64_at(24)?,
+            real_sol_reserves: u64_at(32)?,
+            token_total_supply: u64_at(40)?,
+            complete: data[48] != 0,
+            creator,
+            version,
+        })
+    }
 
-    pub fn parse(data: &[u8]) -> Result<Self, std::io::Error> {
-        Self::try_from_slice(data)
+    /// apply_buy moves the curve as the program would after a buy of
+    /// token_amount for sol_amount (fee excluded)
+    pub fn apply_buy(
+        &mut self,
+        sol_amount: u64,
+        token_amount: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        (self.virtual_sol_reserves, self.virtual_token_reserves) =
+            get_reserves_after_buy(
+                self.virtual_sol_reserves,
+                self.virtual_token_reserves,
+                sol_amount,
+                token_amount,
+            )?;
+        self.real_sol_reserves = self
+            .real_sol_reserves
+            .checked_add(sol_amount)
+            .ok_or("Overflow in real SOL reserves after buy")?;
+        self.real_token_reserves = self
+            .real_token_reserves
+            .checked_sub(token_amount)
+            .ok_or("Underflow in real token reserves after buy")?;
+        Ok(())
+    }
+
+    /// apply_sell moves the curve as the program would after a sell of
+    /// token_amount for sol_amount (fee included)
+    pub fn apply_sell(
+        &mut self,
+        sol_amount: u64,
+        token_amount: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        (self.virtual_sol_reserves, self.virtual_token_reserves) =
+            get_reserves_after_sell(
+                self.virtual_sol_reserves,
+                self.virtual_token_reserves,
+                sol_amount,
+                token_amount,
+            )?;
+        self.real_sol_reserves = self
+            .real_sol_reserves
+            .checked_sub(sol_amount)
+            .ok_or("Underflow in real SOL reserves after sell")?;
+        self.real_token_reserves = self
+            .real_token_reserves
+            .checked_add(token_amount)
+            .ok_or("Overflow in real token reserves after sell")?;
+        Ok(())
     }
 }
 
@@ -156,11 +257,14 @@ pub fn subscribe_to_pump(slot: Arc<RwLock<u64>>) -> JoinHandle<()> {
                 .expect("logs subscribe");
             info!("Subscribed to pump logs");
             while let Some(data) = stream.next().await {
-                tracing::info!(
-                    "{}: {}",
-                    slot.read().await,
-                    data.value.signature
-                );
+                for event in parse_events_from_logs(&data.value.logs) {
+                    tracing::info!(
+                        "{}: {} {:?}",
+                        slot.read().await,
+                        data.value.signature,
+                        event
+                    );
+                }
             }
             unsub().await;
         }
@@ -203,32 +307,9 @@ pub async fn get_bonding_curve(
         {
             Ok(res) => {
                 if let Some(account) = res.value {
-                    // Convert Vec<u8> to [u8; 49]
-                    let data_length = account.data.len();
-                    let data: [u8; 49] =
-                        account.data.try_into().map_err(|_| {
-                            format!("Invalid data length: {}", data_length)
-                        })?;
-
-                    debug!("Raw bytes: {:?}", data);
-
-                    let layout = BondingCurveLayout {
-                        blob1: u64::from_le_bytes(data[0..8].try_into()?),
-                        virtual_token_reserves: u64::from_le_bytes(
-                            data[8..16].try_into()?,
-                        ),
-                        virtual_sol_reserves: u64::from_le_bytes(
-                            data[16..24].try_into()?,
-                        ),
-                        real_token_reserves: u64::from_le_bytes(
-                            data[24..32].try_into()?,
-                        ),
-                        real_sol_reserves: u64::from_le_bytes(
-                            data[32..40].try_into()?,
-                        ),
-                        blob4: u64::from_le_bytes(data[40..48].try_into()?),
-                        complete: data[48] != 0,
-                    };
+                    debug!("Raw bytes: {:?}", account.data);
+
+                    let layout = BondingCurveLayout::parse(&account.data)?;
 
                     debug!("Parsed BondingCurveLayout: {:?}", layout);
                     return Ok(layout);
@@ -316,6 +397,185 @@ pub fn get_token_amount(
     Ok(final_amount_out as u64)
 }
 
+/// jito tip on sells, the seller is not racing anyone
+pub const SELL_TIP: u64 = 30_000;
+
+/// budgets for when the estimate fails, what the buys and sells used
+/// before the estimates
+pub const BUY_COMPUTE_BUDGET: ComputeBudget = ComputeBudget {
+    price: 1_000_069,
+    units: 69_000,
+};
+pub const SELL_COMPUTE_BUDGET: ComputeBudget = ComputeBudget {
+    price: 69_000,
+    units: 69_000,
+};
+
+/// 1%, pump takes it on top of the sol in on buys and out of the sol out on
+/// sells, it never stays in the curve
+pub const DEFAULT_FEE_BASIS_POINTS: u64 = 100;
+
+pub fn calculate_fee(
+    lamports: u64,
+    fee_basis_points: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let fee = (lamports as u128)
+        .checked_mul(fee_basis_points as u128)
+        .ok_or("Overflow in fee calculation")?
+        / 10_000;
+
+    Ok(fee as u64)
+}
+
+/// get_sol_amount returns the lamports received for selling token_amount
+/// into the curve, with the fee already taken out, this is what goes into
+/// min_sol_output before applying slippage
+pub fn get_sol_amount(
+    virtual_sol_reserves: u64,
+    virtual_token_reserves: u64,
+    token_amount: u64,
+    fee_basis_points: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let virtual_sol_reserves = virtual_sol_reserves as u128;
+    let virtual_token_reserves = virtual_token_reserves as u128;
+    let amount_in = token_amount as u128;
+
+    let new_virtual_token_reserve = virtual_token_reserves
+        .checked_add(amount_in)
+        .ok_or("Overflow in new virtual token reserve calculation")?;
+
+    let sol_out = amount_in
+        .checked_mul(virtual_sol_reserves)
+        .ok_or("Overflow in sol out calculation")?
+        .checked_div(new_virtual_token_reserve)
+        .ok_or("Division by zero in sol out calculation")?;
+
+    let sol_out: u64 = sol_out
+        .try_into()
+        .map_err(|_| "Overflow in sol out conversion")?;
+    let fee = calculate_fee(sol_out, fee_basis_points)?;
+
+    Ok(sol_out
+        .checked_sub(fee)
+        .ok_or("Underflow in sol out after fee")?)
+}
+
+/// get_buy_cost is the exact-output counterpart of get_token_amount, returns
+/// the lamports (fee included) needed to receive exactly token_amount, this
+/// is the lowest max_sol_cost that still fills
+pub fn get_buy_cost(
+    virtual_sol_reserves: u64,
+    virtual_token_reserves: u64,
+    token_amount: u64,
+    fee_basis_points: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let virtual_sol_reserves = virtual_sol_reserves as u128;
+    let virtual_token_reserves = virtual_token_reserves as u128;
+    let amount_out = token_amount as u128;
+
+    let reserves_product = virtual_sol_reserves
+        .checked_mul(virtual_token_reserves)
+        .ok_or("Overflow in reserves product calculation")?;
+
+    let new_virtual_token_reserve = virtual_token_reserves
+        .checked_sub(amount_out)
+        .filter(|reserve| *reserve > 0)
+        .ok_or("Not enough virtual token reserves for amount out")?;
+
+    let new_virtual_sol_reserve = reserves_product
+        .checked_div(new_virtual_token_reserve)
+        .ok_or("Division by zero in new virtual SOL reserve calculation")?
+        .checked_add(1)
+        .ok_or("Overflow in new virtual SOL reserve calculation")?;
+
+    let sol_cost: u64 = new_virtual_sol_reserve
+        .checked_sub(virtual_sol_reserves)
+        .ok_or("Underflow in sol cost calculation")?
+        .try_into()
+        .map_err(|_| "Overflow in sol cost conversion")?;
+    let fee = calculate_fee(sol_cost, fee_basis_points)?;
+
+    sol_cost
+        .checked_add(fee)
+        .ok_or_else(|| "Overflow in sol cost with fee calculation".into())
+}
+
+/// apply_slippage lowers an expected output by slippage_bps, e.g. 500 bps
+/// on 1 sol -> 0.95 sol, to be used as the minimum the program accepts
+pub fn apply_slippage(
+    amount: u64,
+    slippage_bps: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    if slippage_bps > 10_000 {
+        return Err(format!("Invalid slippage bps: {}", slippage_bps).into());
+    }
+    let min_amount = (amount as u128)
+        .checked_mul((10_000 - slippage_bps) as u128)
+        .ok_or("Overflow in slippage calculation")?
+        / 10_000;
+
+    Ok(min_amount as u64)
+}
+
+/// get_min_sol_output quotes a sell of token_amount against the curve and
+/// applies slippage_bps, errors if the curve has already completed
+pub fn get_min_sol_output(
+    bonding_curve: &BondingCurveLayout,
+    token_amount: u64,
+    slippage_bps: u64,
+    fee_basis_points: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    if bonding_curve.complete {
+        return Err("Bonding curve complete, cannot sell on pump".into());
+    }
+    let sol_amount = get_sol_amount(
+        bonding_curve.virtual_sol_reserves,
+        bonding_curve.virtual_token_reserves,
+        token_amount,
+        fee_basis_points,
+    )?;
+
+    apply_slippage(sol_amount, slippage_bps)
+}
+
+/// get_reserves_after_buy returns the (virtual_sol_reserves,
+/// virtual_token_reserves) after sol_amount (fee excluded) went in and
+/// token_amount came out
+pub fn get_reserves_after_buy(
+    virtual_sol_reserves: u64,
+    virtual_token_reserves: u64,
+    sol_amount: u64,
+    token_amount: u64,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    Ok((
+        virtual_sol_reserves
+            .checked_add(sol_amount)
+            .ok_or("Overflow in virtual SOL reserves after buy")?,
+        virtual_token_reserves
+            .checked_sub(token_amount)
+            .ok_or("Underflow in virtual token reserves after buy")?,
+    ))
+}
+
+/// get_reserves_after_sell returns the (virtual_sol_reserves,
+/// virtual_token_reserves) after token_amount went in and sol_amount (fee
+/// included) came out
+pub fn get_reserves_after_sell(
+    virtual_sol_reserves: u64,
+    virtual_token_reserves: u64,
+    sol_amount: u64,
+    token_amount: u64,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    Ok((
+        virtual_sol_reserves
+            .checked_sub(sol_amount)
+            .ok_or("Underflow in virtual SOL reserves after sell")?,
+        virtual_token_reserves
+            .checked_add(token_amount)
+            .ok_or("Overflow in virtual token reserves after sell")?,
+    ))
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PumpBuyRequest {
     #[serde(
@@ -341,146 +601,277 @@ pub struct PumpBuyRequest {
     pub virtual_sol_reserves: u64,
 
     pub slot: Option<u64>,
+    /// needed for the creator vault, older clients do not send it
+    #[serde(
+        default,
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub creator: Pubkey,
+}
+
+/// resolve_buy_accounts takes the creator of the request, fresh curve, not
+/// worth the extra rpc call to detect the version, the curve is only read
+/// when the request has no creator, no creator at all is an error since
+/// the creator vault would be wrong
+pub async fn resolve_buy_accounts(
+    rpc_client: &RpcClient,
+    pump_buy_request: &PumpBuyRequest,
+) -> Result<(PumpAccounts, PumpIxVersion), Box<dyn Error>> {
+    let pump_accounts = PumpAccounts {
+        mint: pump_buy_request.mint,
+        bonding_curve: pump_buy_request.bonding_curve,
+        associated_bonding_curve: pump_buy_request.associated_bonding_curve,
+        dev: pump_buy_request.creator,
+        metadata: Pubkey::default(),
+    };
+    if pump_buy_request.creator != Pubkey::default() {
+        return Ok((pump_accounts, PumpIxVersion::resolve(None)));
+    }
+    // a single try, no time for the retries of get_bonding_curve here
+    let bonding_curve = rpc_client
+        .get_account_with_commitment(
+            &pump_buy_request.bonding_curve,
+            CommitmentConfig::processed(),
+        )
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|res| res.value.ok_or("not found".to_string()))
+        .and_then(|account| {
+            BondingCurveLayout::parse(&account.data)
+                .map_err(|e| e.to_string())
+        });
+    let (pump_accounts, version) = match bonding_curve {
+        Ok(bonding_curve) => (
+            pump_accounts.with_curve(&bonding_curve),
+            PumpIxVersion::resolve(Some(&bonding_curve)),
+        ),
+        Err(e) => {
+            warn!("Curve of {} unavailable: {}", pump_buy_request.mint, e);
+            (pump_accounts, PumpIxVersion::resolve(None))
+        }
+    };
+    if version > PumpIxVersion::Legacy
+        && pump_accounts.dev == Pubkey::default()
+    {
+        return Err(format!(
+            "No creator for {}, not buying",
+            pump_buy_request.mint
+        )
+        .into());
+    }
+    Ok((pump_accounts, version))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn buy_pump_token(
     wallet: &Keypair,
-    latest_blockhash: Hash,
+    pump_global: &PumpGlobal,
+    lifetime: impl Into<TxLifetime>,
     pump_accounts: PumpAccounts,
+    bonding_curve: &BondingCurveLayout,
     token_amount: u64,
     lamports: u64,
     tip: u64,
-) -> Result<(), Box<dyn Error>> {
+    sender: &dyn TxSender,
+    estimator: &ComputeEstimator,
+) -> Result<SendResult, Box<dyn Error>> {
     let owner = wallet.pubkey();
 
     info!("{} buying {} {}", owner, token_amount, pump_accounts.mint);
 
-    let mut ixs = _make_buy_ixs(
-        owner,
-        pump_accounts.mint,
-        pump_accounts.bonding_curve,
-        pump_accounts.associated_bonding_curve,
-        token_amount,
-        apply_fee(lamports),
-    )?;
+    let builder = PumpTxBuilder::new(owner)
+        .buy(
+            pump_accounts.with_curve(bonding_curve),
+            pump_global.fee_recipient,
+            token_amount,
+            pump_global.apply_fee(lamports),
+            PumpIxVersion::resolve(Some(bonding_curve)),
+        )?
+        .tip(tip)
+        .cached_lookup_tables();
+    let tx = builder
+        .estimated_compute_budget(estimator, BUY_COMPUTE_BUDGET)
+        .await
+        .build(&[wallet], lifetime)?;
+
+    let res = sender.send_transaction(&tx).await?;
+    info!("Buy {} sent through {}", res.signature, res.transport);
 
-    ixs.push(transfer(&owner, &get_jito_tip_pubkey(), tip));
+    Ok(res)
+}
 
-    let tx = Transaction::new_signed_with_payer(
-        &ixs,
-        Some(&owner),
-        &[wallet],
-        latest_blockhash,
+/// sell_pump_token fetches the curve right before the sell so that
+/// min_sol_output reflects the current price, if the price moves more than
+/// slippage_bps before landing (sandwich, dev dump) the sell reverts
+#[allow(clippy::too_many_arguments)]
+#[timed::timed(duration(printer = "info!"))]
+pub async fn sell_pump_token(
+    wallet: &Keypair,
+    rpc_client: &RpcClient,
+    pump_global: &PumpGlobal,
+    lifetime: impl Into<TxLifetime>,
+    pump_accounts: PumpAccounts,
+    token_amount: u64,
+    slippage_bps: u64,
+    sender: &dyn TxSender,
+    estimator: &ComputeEstimator,
+) -> Result<SendResult, Box<dyn Error>> {
+    let owner = wallet.pubkey();
+
+    let bonding_curve =
+        get_bonding_curve(rpc_client, pump_accounts.bonding_curve).await?;
+    let min_sol_output = get_min_sol_output(
+        &bonding_curve,
+        token_amount,
+        slippage_bps,
+        pump_global.fee_basis_points,
+    )?;
+    info!(
+        "{} selling {} {} for at least {} lamports",
+        owner, token_amount, pump_accounts.mint, min_sol_output
     );
 
-    send_jito_tx(tx).await?;
+    let builder = PumpTxBuilder::new(owner)
+        .sell(
+            pump_accounts.with_curve(&bonding_curve),
+            pump_global.fee_recipient,
+            token_amount,
+            min_sol_output,
+            PumpIxVersion::resolve(Some(&bonding_curve)),
+        )?
+        .tip(SELL_TIP)
+        .cached_lookup_tables();
+    let tx = builder
+        .estimated_compute_budget(estimator, SELL_COMPUTE_BUDGET)
+        .await
+        .build(&[wallet], lifetime)?;
 
-    Ok(())
+    let res = sender.send_transaction(&tx).await?;
+    info!("Sell {} sent through {}", res.signature, res.transport);
+
+    Ok(res)
 }
 
-pub fn _make_buy_ixs(
-    owner: Pubkey,
-    mint: Pubkey,
-    bonding_curve: Pubkey,
-    associated_bonding_curve: Pubkey,
-    token_amount: u64,
-    lamports: u64,
-) -> Result<Vec<Instruction>, Box<dyn Error>> {
-    let mut ixs = vec![];
-    let ata = spl_associated_token_account::get_associated_token_address(
-        &owner, &mint,
-    );
+/// PumpIxVersion is the account layout of the program the buy and sell ixs
+/// are built for, each version extends the previous one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PumpIxVersion {
+    /// 12 accounts, rent sysvar on buys, ata program on sells
+    Legacy,
+    /// creator vault in place of the rent sysvar/ata program
+    CreatorVault,
+    /// global and user volume accumulators appended to buys
+    VolumeAccumulator,
+    /// fee config and fee program appended to buys and sells
+    #[default]
+    FeeConfig,
+}
 
-    ixs.push(
-        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
-            &owner,
-            &owner,
-            &mint,
-            &spl_token::id(),
-        ),
-    );
-    ixs.push(make_pump_swap_ix(
-        owner,
-        mint,
-        bonding_curve,
-        associated_bonding_curve,
-        token_amount,
-        lamports,
-        ata,
-    )?);
+impl FromStr for PumpIxVersion {
+    type Err = String;
 
-    Ok(ixs)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "legacy" => Ok(Self::Legacy),
+            "creator-vault" => Ok(Self::CreatorVault),
+            "volume-accumulator" => Ok(Self::VolumeAccumulator),
+            "fee-config" => Ok(Self::FeeConfig),
+            _ => Err(format!("Invalid pump ix version: {}", s)),
+        }
+    }
 }
 
-async fn _send_tx_standard(
-    ixs: Vec<Instruction>,
-    wallet: &Keypair,
-    rpc_client: &RpcClient,
-    owner: Pubkey,
-) -> Result<(), Box<dyn Error>> {
-    let transaction =
-        VersionedTransaction::from(Transaction::new_signed_with_payer(
-            &ixs,
-            Some(&owner),
-            &[wallet],
-            rpc_client.get_latest_blockhash().await?,
-        ));
-    let res = rpc_client
-        .send_transaction_with_config(
-            &transaction,
-            RpcSendTransactionConfig {
-                skip_preflight: true,
-                min_context_slot: None,
-                preflight_commitment: Some(CommitmentLevel::Processed),
-                max_retries: None,
-                encoding: None,
-            },
-        )
-        .await;
+impl PumpIxVersion {
+    pub const LATEST: Self = Self::FeeConfig;
 
-    match res {
-        Ok(sig) => {
-            info!("Transaction sent: {}", sig);
+    /// from_env reads PUMP_IX_VERSION, for pinning the layout when the
+    /// program gets upgraded
+    pub fn from_env() -> Option<Self> {
+        let version = std::env::var("PUMP_IX_VERSION").ok()?;
+        match version.parse() {
+            Ok(version) => Some(version),
+            Err(e) => {
+                warn!("{}, falling back to detection", e);
+                None
+            }
         }
-        Err(e) => {
-            return Err(e.into());
+    }
+
+    /// detect goes off the curve account, curves without the creator field
+    /// predate the creator vault
+    pub fn detect(bonding_curve: &BondingCurveLayout) -> Self {
+        match bonding_curve.version {
+            BondingCurveVersion::V1 => Self::Legacy,
+            BondingCurveVersion::V2 => Self::LATEST,
         }
     }
 
-    Ok(())
+    /// resolve prefers the env config, then the curve, then LATEST (new
+    /// tokens, where the curve is not there yet)
+    pub fn resolve(bonding_curve: Option<&BondingCurveLayout>) -> Self {
+        Self::from_env().unwrap_or_else(|| {
+            bonding_curve.map_or(Self::LATEST, Self::detect)
+        })
+    }
 }
 
-#[timed::timed(duration(printer = "info!"))]
-pub async fn sell_pump_token(
-    wallet: &Keypair,
-    latest_blockhash: Hash,
-    pump_accounts: PumpAccounts,
-    token_amount: u64,
-) -> Result<(), Box<dyn Error>> {
-    let owner = wallet.pubkey();
+pub fn get_creator_vault(creator: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"creator-vault", creator.as_ref()],
+        &Pubkey::from_str(PUMP_FUN_PROGRAM).unwrap(),
+    )
+    .0
+}
 
-    let ata = spl_associated_token_account::get_associated_token_address(
-        &owner,
-        &pump_accounts.mint,
-    );
+pub fn get_global_volume_accumulator() -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"global_volume_accumulator"],
+        &Pubkey::from_str(PUMP_FUN_PROGRAM).unwrap(),
+    )
+    .0
+}
 
-    let mut ixs = vec![];
-    let mut compute_budget_ixs = make_compute_budget_ixs(69_000, 69_000);
-    let sell_ix = make_pump_sell_ix(owner, pump_accounts, token_amount, ata)?;
-    ixs.append(&mut compute_budget_ixs);
-    ixs.push(sell_ix);
-    ixs.push(transfer(&owner, &get_jito_tip_pubkey(), 30_000));
-
-    let tx = Transaction::new_signed_with_payer(
-        &ixs,
-        Some(&owner),
-        &[wallet],
-        latest_blockhash,
-    );
+pub fn get_user_volume_accumulator(user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"user_volume_accumulator", user.as_ref()],
+        &Pubkey::from_str(PUMP_FUN_PROGRAM).unwrap(),
+    )
+    .0
+}
 
-    send_jito_tx(tx).await?;
+pub fn get_fee_config() -> Pubkey {
+    let program = Pubkey::from_str(PUMP_FUN_PROGRAM).unwrap();
+    Pubkey::find_program_address(
+        &[b"fee_config", program.as_ref()],
+        &Pubkey::from_str(PUMP_FEE_PROGRAM).unwrap(),
+    )
+    .0
+}
 
-    Ok(())
+/// creator vault account, pump_accounts.dev has to be the curve creator
+fn creator_vault_meta(
+    pump_accounts: &PumpAccounts,
+    version: PumpIxVersion,
+) -> Result<AccountMeta, Box<dyn Error>> {
+    if pump_accounts.dev == Pubkey::default() {
+        return Err(format!(
+            "{:?} ixs require the curve creator for {}",
+            version, pump_accounts.mint
+        )
+        .into());
+    }
+    Ok(AccountMeta::new(
+        get_creator_vault(&pump_accounts.dev),
+        false,
+    ))
+}
+
+fn fee_config_metas() -> Result<[AccountMeta; 2], Box<dyn Error>> {
+    Ok([
+        AccountMeta::new_readonly(get_fee_config(), false),
+        AccountMeta::new_readonly(Pubkey::from_str(PUMP_FEE_PROGRAM)?, false),
+    ])
 }
 
 /// Interact With Pump.Fun - 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P
@@ -492,22 +883,27 @@ pub async fn sell_pump_token(
 /// #6 - Associated Token Account (ATA) [Writable]
 /// #7 - User [Writable Signer Fee-Payer]
 /// #8 - System Program
-/// #9 - Associated Token Program
+/// #9 - Associated Token Program (Legacy), Creator Vault [Writable]
 /// #10 - Token Program
 /// #11 - Event Authority
 /// #12 - Program: Pump.fun Program
+/// #13 - Fee Config (FeeConfig)
+/// #14 - Fee Program (FeeConfig)
 pub fn make_pump_sell_ix(
     owner: Pubkey,
     pump_accounts: PumpAccounts,
+    fee_recipient: Pubkey,
     token_amount: u64,
+    min_sol_output: u64,
     ata: Pubkey,
+    version: PumpIxVersion,
 ) -> Result<Instruction, Box<dyn Error>> {
-    let accounts: [AccountMeta; 12] = [
+    let mut accounts = vec![
         AccountMeta::new_readonly(
             Pubkey::from_str(PUMP_GLOBAL_ADDRESS)?,
             false,
         ),
-        AccountMeta::new(Pubkey::from_str(PUMP_FEE_ADDRESS)?, false),
+        AccountMeta::new(fee_recipient, false),
         AccountMeta::new_readonly(pump_accounts.mint, false),
         AccountMeta::new(pump_accounts.bonding_curve, false),
         AccountMeta::new(pump_accounts.associated_bonding_curve, false),
@@ -517,26 +913,34 @@ pub fn make_pump_sell_ix(
             Pubkey::from_str(SYSTEM_PROGRAM_ID)?,
             false,
         ),
-        AccountMeta::new_readonly(
+    ];
+    if version == PumpIxVersion::Legacy {
+        accounts.push(AccountMeta::new_readonly(
             Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM)?,
             false,
-        ),
+        ));
+    } else {
+        accounts.push(creator_vault_meta(&pump_accounts, version)?);
+    }
+    accounts.extend([
         AccountMeta::new_readonly(Pubkey::from_str(TOKEN_PROGRAM)?, false),
         AccountMeta::new_readonly(Pubkey::from_str(EVENT_AUTHORITY)?, false),
         AccountMeta::new_readonly(Pubkey::from_str(PUMP_FUN_PROGRAM)?, false),
-    ];
+    ]);
+    if version >= PumpIxVersion::FeeConfig {
+        accounts.extend(fee_config_metas()?);
+    }
 
-    // max slippage, careful if not using frontrun protection
     let data = PumpFunSwapInstructionData {
         method_id: PUMP_SELL_METHOD,
         token_amount,
-        lamports: 0,
+        lamports: min_sol_output,
     };
 
     Ok(Instruction::new_with_borsh(
         Pubkey::from_str(PUMP_FUN_PROGRAM)?,
         &data,
-        accounts.to_vec(),
+        accounts,
     ))
 }
 
@@ -551,27 +955,31 @@ pub fn make_pump_sell_ix(
 /// #7 - User - owner, sender [Writable, Signer, Fee Payer]
 /// #8 - System Program (11111111111111111111111111111111)
 /// #9 - Token Program (TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA)
-/// #10 - Rent (SysvarRent111111111111111111111111111111111)
+/// #10 - Rent (Legacy), Creator Vault [Writable]
 /// #11 - Event Authority: Ce6TQqeHC9p8KetsN6JsjHK7UTZk7nasjjnr7XxXp9F1
 /// #12 - Program: Pump.fun Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P
+/// #13 - Global Volume Accumulator [Writable] (VolumeAccumulator)
+/// #14 - User Volume Accumulator [Writable] (VolumeAccumulator)
+/// #15 - Fee Config (FeeConfig)
+/// #16 - Fee Program (FeeConfig)
 pub fn make_pump_swap_ix(
     owner: Pubkey,
-    mint: Pubkey,
-    bonding_curve: Pubkey,
-    associated_bonding_curve: Pubkey,
+    pump_accounts: PumpAccounts,
+    fee_recipient: Pubkey,
     token_amount: u64,
     lamports: u64,
     ata: Pubkey,
+    version: PumpIxVersion,
 ) -> Result<Instruction, Box<dyn Error>> {
-    let accounts: [AccountMeta; 12] = [
+    let mut accounts = vec![
         AccountMeta::new_readonly(
             Pubkey::from_str(PUMP_GLOBAL_ADDRESS)?,
             false,
         ),
-        AccountMeta::new(Pubkey::from_str(PUMP_FEE_ADDRESS)?, false),
-        AccountMeta::new_readonly(mint, false),
-        AccountMeta::new(bonding_curve, false),
-        AccountMeta::new(associated_bonding_curve, false),
+        AccountMeta::new(fee_recipient, false),
+        AccountMeta::new_readonly(pump_accounts.mint, false),
+        AccountMeta::new(pump_accounts.bonding_curve, false),
+        AccountMeta::new(pump_accounts.associated_bonding_curve, false),
         AccountMeta::new(ata, false),
         AccountMeta::new(owner, true),
         AccountMeta::new_readonly(
@@ -579,10 +987,28 @@ pub fn make_pump_swap_ix(
             false,
         ),
         AccountMeta::new_readonly(Pubkey::from_str(TOKEN_PROGRAM)?, false),
-        AccountMeta::new_readonly(Pubkey::from_str(RENT_PROGRAM)?, false),
+    ];
+    if version == PumpIxVersion::Legacy {
+        accounts.push(AccountMeta::new_readonly(
+            Pubkey::from_str(RENT_PROGRAM)?,
+            false,
+        ));
+    } else {
+        accounts.push(creator_vault_meta(&pump_accounts, version)?);
+    }
+    accounts.extend([
         AccountMeta::new_readonly(Pubkey::from_str(EVENT_AUTHORITY)?, false),
         AccountMeta::new_readonly(Pubkey::from_str(PUMP_FUN_PROGRAM)?, false),
-    ];
+    ]);
+    if version >= PumpIxVersion::VolumeAccumulator {
+        accounts.extend([
+            AccountMeta::new(get_global_volume_accumulator(), false),
+            AccountMeta::new(get_user_volume_accumulator(&owner), false),
+        ]);
+    }
+    if version >= PumpIxVersion::FeeConfig {
+        accounts.extend(fee_config_metas()?);
+    }
 
     let data = PumpFunSwapInstructionData {
         method_id: PUMP_BUY_METHOD,
@@ -593,7 +1019,7 @@ pub fn make_pump_swap_ix(
     Ok(Instruction::new_with_borsh(
         Pubkey::from_str(PUMP_FUN_PROGRAM)?,
         &data,
-        accounts.to_vec(),
+        accounts,
     ))
 }
 
@@ -626,6 +1052,17 @@ pub struct PumpAccounts {
     pub metadata: Pubkey,
 }
 
+impl PumpAccounts {
+    /// with_curve sets dev to the curve creator, if the curve has one, the
+    /// creator vault is derived from it
+    pub fn with_curve(mut self, bonding_curve: &BondingCurveLayout) -> Self {
+        if let Some(creator) = bonding_curve.creator {
+            self.dev = creator;
+        }
+        self
+    }
+}
+
 pub fn parse_pump_accounts(
     tx: EncodedConfirmedTransactionWithStatusMeta,
 ) -> Result<PumpAccounts, Box<dyn Error>> {
@@ -766,12 +1203,16 @@ pub async fn send_pump_bump(
     rpc_client: &RpcClient,
     mint: &Pubkey,
     searcher_client: &mut Arc<RwLock<SearcherClient>>,
+    sender: &dyn TxSender,
+    estimator: &ComputeEstimator,
     wait_for_confirmation: bool,
 ) -> Result<(), Box<dyn Error>> {
     let lamports = 22_800_000;
+    let slippage_bps = 500;
     let owner = wallet.pubkey();
+    let pump_global = get_pump_global(rpc_client).await?;
     let pump_accounts = mint_to_pump_accounts(mint);
-    let bonding_curve =
+    let mut bonding_curve =
         get_bonding_curve(rpc_client, pump_accounts.bonding_curve).await?;
     let token_amount = get_token_amount(
         bonding_curve.virtual_sol_reserves,
@@ -793,53 +1234,73 @@ pub async fn send_pump_bump(
         warn!("ata does not exist, creating it through buy and sell");
         buy_pump_token(
             wallet,
+            &pump_global,
             latest_blockhash,
             pump_accounts,
+            &bonding_curve,
             token_amount,
             lamports,
             tip,
+            sender,
+            estimator,
         )
         .await?;
 
         sell_pump_token(
             wallet,
+            rpc_client,
+            &pump_global,
             latest_blockhash,
             pump_accounts,
             token_amount,
+            slippage_bps,
+            sender,
+            estimator,
         )
         .await?;
         return Ok(());
     }
 
-    let mut ixs = vec![];
-    // ixs.append(&mut make_compute_budget_ixs(262500, 10000));
-
-    ixs.push(make_pump_swap_ix(
-        owner,
-        pump_accounts.mint,
-        pump_accounts.bonding_curve,
-        pump_accounts.associated_bonding_curve,
+    // the sell executes against the curve moved by the buy in the same tx
+    let buy_cost = get_buy_cost(
+        bonding_curve.virtual_sol_reserves,
+        bonding_curve.virtual_token_reserves,
         token_amount,
-        lamports,
-        ata,
-    )?);
-
-    ixs.push(make_pump_sell_ix(owner, pump_accounts, token_amount, ata)?);
-
-    // 0.00005 sol
-    let tip = 50_000;
-    ixs.push(transfer(&owner, &get_jito_tip_pubkey(), tip));
+        0,
+    )?;
+    bonding_curve.apply_buy(buy_cost, token_amount)?;
+    let min_sol_output = get_min_sol_output(
+        &bonding_curve,
+        token_amount,
+        slippage_bps,
+        pump_global.fee_basis_points,
+    )?;
 
-    let tx = VersionedTransaction::from(Transaction::new_signed_with_payer(
-        &ixs,
-        Some(&owner),
-        &[wallet],
-        rpc_client.get_latest_blockhash().await?,
-    ));
+    let version = PumpIxVersion::resolve(Some(&bonding_curve));
+    let pump_accounts = pump_accounts.with_curve(&bonding_curve);
+    let tx = PumpTxBuilder::new(owner)
+        .buy(
+            pump_accounts,
+            pump_global.fee_recipient,
+            token_amount,
+            lamports,
+            version,
+        )?
+        .sell(
+            pump_accounts,
+            pump_global.fee_recipient,
+            token_amount,
+            min_sol_output,
+            version,
+        )?
+        .tip(tip)
+        .cached_lookup_tables()
+        .build(&[wallet], rpc_client.get_latest_blockhash().await?)?;
 
     let mut searcher_client = searcher_client.write().await;
 
-    if wait_for_confirmation {
+    // a dry run has nothing to confirm
+    if wait_for_confirmation && get_dry_run().is_none() {
         let mut bundle_results_subscription = searcher_client
             .subscribe_bundle_results(SubscribeBundleResultsRequest {})
             .await
@@ -854,7 +1315,7 @@ pub async fn send_pump_bump(
         )
         .await?;
     } else {
-        send_bundle_no_wait(&[tx], &mut searcher_client).await?;
+        send_bundle(&[tx], &mut searcher_client).await?;
     }
 
     Ok(())
@@ -863,8 +1324,11 @@ pub async fn send_pump_bump(
 #[cfg(test)]
 mod tests {
     use jito_searcher_client::get_searcher_client;
+    use solana_sdk::account::Account;
     use solana_sdk::signer::EncodableKey;
 
+    use crate::mock_rpc::{MockRpcServer, RpcFixtures};
+    use crate::sender::JitoHttpSender;
     use crate::util::env;
 
     use super::*;
@@ -874,8 +1338,9 @@ mod tests {
         dotenv::from_filename(".env").unwrap();
         let wallet = Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
             .expect("read wallet");
-        let rpc_client =
-            RpcClient::new("https://api.mainnet-beta.solana.com".to_string());
+        let rpc_client = Arc::new(RpcClient::new(
+            "https://api.mainnet-beta.solana.com".to_string(),
+        ));
         let mint =
             Pubkey::from_str("8ALbiQ2aWD4V63bx6s5qtf21LA4r9uBaY2THbg9epump")
                 .unwrap();
@@ -893,6 +1358,8 @@ mod tests {
             &rpc_client,
             &mint,
             &mut searcher_client,
+            &JitoHttpSender::new(env("BLOCK_ENGINE_URL")),
+            &ComputeEstimator::new(rpc_client.clone()),
             true,
         )
         .await
@@ -930,10 +1397,11 @@ mod tests {
 
     #[test]
     fn test_parse_pump_accounts() {
-        let sample_tx =
-            std::fs::read_to_string("pump_fun_tx.json").expect("read tx");
         let tx: EncodedConfirmedTransactionWithStatusMeta =
-            serde_json::from_str(&sample_tx).expect("parse tx");
+            serde_json::from_str(include_str!(
+                "../tests/fixtures/pump_fun_tx.json"
+            ))
+            .expect("parse tx");
         let accounts = parse_pump_accounts(tx).expect("parse accounts");
         println!("{:?}", accounts);
         assert!(
@@ -983,16 +1451,24 @@ mod tests {
         let rpc_client =
             RpcClient::new("https://api.mainnet-beta.solana.com".to_string());
         let tip = 50_000;
+        let bonding_curve =
+            get_bonding_curve(&rpc_client, pump_accounts.bonding_curve)
+                .await
+                .expect("get bonding curve");
         buy_pump_token(
             &wallet,
+            &PumpGlobal::default(),
             rpc_client
                 .get_latest_blockhash()
                 .await
                 .expect("get blockhash"),
             pump_accounts,
+            &bonding_curve,
             100_000,
             lamports,
             tip,
+            &JitoHttpSender::new(env("BLOCK_ENGINE_URL")),
+            &ComputeEstimator::new(Arc::new(rpc_client)),
         )
         .await
         .expect("buy pump token");
@@ -1042,17 +1518,140 @@ mod tests {
         assert_eq!(bonding_curve.real_token_reserves, 0);
     }
 
+    fn make_bonding_curve_data(
+        creator: Option<Pubkey>,
+        len: usize,
+    ) -> Vec<u8> {
+        let mut data = BONDING_CURVE_DISCRIMINATOR.to_vec();
+        for value in [
+            1_072_964_268_463_317u64,
+            30_000_999_057,
+            793_064_268_463_317,
+            999_057,
+            1_000_000_000_000_000,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.push(0);
+        if let Some(creator) = creator {
+            data.extend_from_slice(creator.as_ref());
+        }
+        data.resize(len, 0);
+        data
+    }
+
+    #[test]
+    fn test_parse_bonding_curve_versions() {
+        let curve = BondingCurveLayout::parse(&make_bonding_curve_data(
+            None,
+            BondingCurveLayout::LEN,
+        ))
+        .expect("parse v1");
+        assert_eq!(curve.version, BondingCurveVersion::V1);
+        assert_eq!(curve.creator, None);
+        assert_eq!(curve.virtual_sol_reserves, 30_000_999_057);
+        assert_eq!(curve.token_total_supply, 1_000_000_000_000_000);
+        assert!(!curve.complete);
+
+        // the creator field, and whatever gets appended after it
+        let creator = Pubkey::new_unique();
+        for len in [BondingCurveLayout::LEN_V2, 150] {
+            let curve = BondingCurveLayout::parse(&make_bonding_curve_data(
+                Some(creator),
+                len,
+            ))
+            .expect("parse v2");
+            assert_eq!(curve.version, BondingCurveVersion::V2);
+            assert_eq!(curve.creator, Some(creator));
+            assert_eq!(curve.real_token_reserves, 793_064_268_463_317);
+        }
+    }
+
+    #[test]
+    fn test_parse_bonding_curve_invalid() {
+        let mut data = make_bonding_curve_data(None, BondingCurveLayout::LEN);
+        assert!(BondingCurveLayout::parse(&data[..48]).is_err());
+        data[0] = 0;
+        assert!(BondingCurveLayout::parse(&data).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_buy_accounts() {
+        let creator = Pubkey::new_unique();
+        let request = PumpBuyRequest {
+            mint: Pubkey::new_unique(),
+            bonding_curve: Pubkey::new_unique(),
+            associated_bonding_curve: Pubkey::new_unique(),
+            virtual_token_reserves: 1_072_964_268_463_317,
+            virtual_sol_reserves: 30_000_999_057,
+            slot: None,
+            creator: Pubkey::default(),
+        };
+        let server =
+            MockRpcServer::start(RpcFixtures::default()).await.unwrap();
+        let rpc_client = RpcClient::new(server.rpc_url.clone());
+
+        // the rpc is behind and the request has no creator
+        let err = resolve_buy_accounts(&rpc_client, &request)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No creator"));
+
+        // the creator of the request is used as is
+        let (pump_accounts, version) = resolve_buy_accounts(
+            &rpc_client,
+            &PumpBuyRequest {
+                creator,
+                ..request.clone()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(pump_accounts.dev, creator);
+        assert_eq!(version, PumpIxVersion::resolve(None));
+
+        // the curve fills in a missing creator
+        server
+            .set_account(
+                request.bonding_curve,
+                Account {
+                    lamports: 1_000_000,
+                    data: make_bonding_curve_data(Some(creator), 150),
+                    owner: Pubkey::from_str(PUMP_FUN_PROGRAM).unwrap(),
+                    executable: false,
+                    rent_epoch: 0,
+                },
+            )
+            .await;
+        let (pump_accounts, version) =
+            resolve_buy_accounts(&rpc_client, &request).await.unwrap();
+        assert_eq!(pump_accounts.dev, creator);
+        assert_eq!(pump_accounts.mint, request.mint);
+        assert_eq!(
+            version,
+            PumpIxVersion::resolve(Some(
+                &BondingCurveLayout::parse(&make_bonding_curve_data(
+                    Some(creator),
+                    150
+                ))
+                .unwrap()
+            ))
+        );
+    }
+
     #[tokio::test]
     async fn test_get_token_amount() {
         // captured from prod
         let bonding_curve = BondingCurveLayout {
-            blob1: 6966180631402821399,
+            discriminator: BONDING_CURVE_DISCRIMINATOR,
             virtual_token_reserves: 1072964268463317,
             virtual_sol_reserves: 30000999057,
             real_token_reserves: 793064268463317,
             real_sol_reserves: 999057,
-            blob4: 1000000000000000,
+            token_total_supply: 1000000000000000,
             complete: false,
+            creator: None,
+            version: BondingCurveVersion::V1,
         };
         let lamports = 500000;
         let expected_token_amount = 17852389307u64;
@@ -1071,4 +1670,401 @@ mod tests {
         assert!(token_amount >= low_thresh);
         assert!(token_amount <= high_thresh);
     }
+
+    #[test]
+    fn test_get_buy_cost_matches_get_token_amount() {
+        let virtual_sol_reserves = 30_000_999_057;
+        let virtual_token_reserves = 1_072_964_268_463_317;
+        let lamports = 500_000_000;
+        let token_amount = get_token_amount(
+            virtual_sol_reserves,
+            virtual_token_reserves,
+            None,
+            lamports,
+        )
+        .expect("get token amount");
+
+        // without the fee the exact-output quote inverts the input quote
+        let cost = get_buy_cost(
+            virtual_sol_reserves,
+            virtual_token_reserves,
+            token_amount,
+            0,
+        )
+        .expect("get buy cost");
+        assert!(cost <= lamports);
+        assert!(lamports - cost <= 2);
+
+        let cost_with_fee = get_buy_cost(
+            virtual_sol_reserves,
+            virtual_token_reserves,
+            token_amount,
+            DEFAULT_FEE_BASIS_POINTS,
+        )
+        .expect("get buy cost");
+        assert_eq!(cost_with_fee, cost + cost / 100);
+
+        assert!(get_buy_cost(
+            virtual_sol_reserves,
+            virtual_token_reserves,
+            virtual_token_reserves,
+            DEFAULT_FEE_BASIS_POINTS,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_get_min_sol_output() {
+        let mut curve = BondingCurveLayout {
+            discriminator: BONDING_CURVE_DISCRIMINATOR,
+            virtual_token_reserves: 1_073_000_000_000_000,
+            virtual_sol_reserves: 30_000_000_000,
+            real_token_reserves: 793_100_000_000_000,
+            real_sol_reserves: 0,
+            token_total_supply: 1_000_000_000_000_000,
+            complete: false,
+            creator: None,
+            version: BondingCurveVersion::V1,
+        };
+        let token_amount = 10_000_000_000_000;
+        let expected = get_sol_amount(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            token_amount,
+            DEFAULT_FEE_BASIS_POINTS,
+        )
+        .expect("get sol amount");
+        let min_sol_output = get_min_sol_output(
+            &curve,
+            token_amount,
+            500,
+            DEFAULT_FEE_BASIS_POINTS,
+        )
+        .expect("get min sol output");
+        assert_eq!(min_sol_output, expected * 95 / 100);
+        assert_eq!(
+            get_min_sol_output(
+                &curve,
+                token_amount,
+                0,
+                DEFAULT_FEE_BASIS_POINTS
+            )
+            .unwrap(),
+            expected
+        );
+        assert!(get_min_sol_output(
+            &curve,
+            token_amount,
+            10_001,
+            DEFAULT_FEE_BASIS_POINTS
+        )
+        .is_err());
+
+        curve.complete = true;
+        assert!(get_min_sol_output(
+            &curve,
+            token_amount,
+            500,
+            DEFAULT_FEE_BASIS_POINTS
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_make_pump_sell_ix_min_sol_output() {
+        let owner = Pubkey::new_unique();
+        let pump_accounts = mint_to_pump_accounts(&Pubkey::new_unique());
+        let ix = make_pump_sell_ix(
+            owner,
+            pump_accounts,
+            PumpGlobal::default().fee_recipient,
+            1_000,
+            420_000,
+            Pubkey::new_unique(),
+            PumpIxVersion::Legacy,
+        )
+        .expect("make sell ix");
+        assert_eq!(ix.data[..8], PUMP_SELL_METHOD);
+        assert_eq!(ix.data[8..16], 1_000u64.to_le_bytes());
+        assert_eq!(ix.data[16..24], 420_000u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_get_sol_amount_round_trip() {
+        let mut curve = BondingCurveLayout {
+            discriminator: BONDING_CURVE_DISCRIMINATOR,
+            virtual_token_reserves: 1_073_000_000_000_000,
+            virtual_sol_reserves: 30_000_000_000,
+            real_token_reserves: 793_100_000_000_000,
+            real_sol_reserves: 0,
+            token_total_supply: 1_000_000_000_000_000,
+            complete: false,
+            creator: None,
+            version: BondingCurveVersion::V1,
+        };
+        let lamports = 1_000_000_000;
+        let token_amount = get_token_amount(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            Some(curve.real_token_reserves),
+            lamports,
+        )
+        .expect("get token amount");
+        curve.apply_buy(lamports, token_amount).expect("apply buy");
+        assert_eq!(curve.real_sol_reserves, lamports);
+        assert_eq!(curve.virtual_sol_reserves, 31_000_000_000);
+
+        // selling everything back returns what went in, minus rounding
+        let gross = get_sol_amount(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            token_amount,
+            0,
+        )
+        .expect("get sol amount");
+        assert!(gross <= lamports);
+        assert!(lamports - gross <= 2);
+
+        let net = get_sol_amount(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            token_amount,
+            DEFAULT_FEE_BASIS_POINTS,
+        )
+        .expect("get sol amount");
+        assert_eq!(net, gross - gross / 100);
+        // a fee over 100% is an error, not a wrap around
+        assert!(get_sol_amount(
+            curve.virtual_sol_reserves,
+            curve.virtual_token_reserves,
+            token_amount,
+            20_000,
+        )
+        .is_err());
+
+        curve.apply_sell(gross, token_amount).expect("apply sell");
+        assert_eq!(curve.virtual_token_reserves, 1_073_000_000_000_000);
+        assert_eq!(curve.real_token_reserves, 793_100_000_000_000);
+        assert!(curve.real_sol_reserves <= 2);
+    }
+
+    // golden accounts, (pubkey, writable, signer), in program order
+    const GLOBAL: (&str, bool, bool) =
+        ("4wTV1YmiEkRvAtNtsSGPtUrqRYQMe5SKy2uB4Jjaxnjf", false, false);
+    const FEE_RECIPIENT: (&str, bool, bool) =
+        ("CebN5WGQ4jvEPvsVU4EoHEpgzq1VV7AbicfhtW4xC9iM", true, false);
+    const MINT: (&str, bool, bool) =
+        ("5KEDcNGebCcLptWzknqVmPRNLHfiHA9Mm2djVE26pump", false, false);
+    const BONDING_CURVE: (&str, bool, bool) =
+        ("Drhj4djqLsPyiA9qK2YmBngteFba8XhhvuQoBToW6pMS", true, false);
+    const ASSOCIATED_BONDING_CURVE: (&str, bool, bool) =
+        ("7uXq8diH862Dh8NgMHt5Tzsai8SvURhH58rArgxvs7o1", true, false);
+    const ATA: (&str, bool, bool) =
+        ("9tMCARZbrwaY1P2ABgrA9twaZzz3bbdersKcSG3kaW6X", true, false);
+    const OWNER: (&str, bool, bool) =
+        ("Gizxxed4uXCzL7Q8DyALDVoEEDfMkSV7XyUNrPDnPJ9J", true, true);
+    const SYSTEM: (&str, bool, bool) =
+        ("11111111111111111111111111111111", false, false);
+    const TOKEN: (&str, bool, bool) =
+        ("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", false, false);
+    const RENT: (&str, bool, bool) =
+        ("SysvarRent111111111111111111111111111111111", false, false);
+    const ATA_PROGRAM: (&str, bool, bool) =
+        ("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL", false, false);
+    const CREATOR_VAULT: (&str, bool, bool) =
+        ("4D5HPqnfUUeoSFW8Z9q6jsmPJdkQSiAiSnELUXcY9NxC", true, false);
+    const EVENT_AUTH: (&str, bool, bool) =
+        ("Ce6TQqeHC9p8KetsN6JsjHK7UTZk7nasjjnr7XxXp9F1", false, false);
+    const PROGRAM: (&str, bool, bool) =
+        ("6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P", false, false);
+    const GLOBAL_VOLUME: (&str, bool, bool) =
+        ("Hq2wp8uJ9jCPsYgNHex8RtqdvMPfVGoYwjvF1ATiwn2Y", true, false);
+    const USER_VOLUME: (&str, bool, bool) =
+        ("64LfZW4scKQQovJ958noFHgYDA437EHByMrWzoYrxyx9", true, false);
+    const FEE_CONFIG: (&str, bool, bool) =
+        ("8Wf5TiAheLUqBrKXeYg2JtAFFMWtKdG2BSFgqUcPVwTt", false, false);
+    const FEE_PROGRAM: (&str, bool, bool) =
+        ("pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ", false, false);
+
+    fn golden_pump_accounts() -> PumpAccounts {
+        PumpAccounts {
+            mint: Pubkey::from_str(MINT.0).unwrap(),
+            bonding_curve: Pubkey::from_str(BONDING_CURVE.0).unwrap(),
+            associated_bonding_curve: Pubkey::from_str(
+                ASSOCIATED_BONDING_CURVE.0,
+            )
+            .unwrap(),
+            dev: Pubkey::from_str(
+                "2wgo94ZaiUNUkFBSKNaKsUgEANgSdex7gRpFKR39DPzw",
+            )
+            .unwrap(),
+            metadata: Pubkey::default(),
+        }
+    }
+
+    fn assert_golden(ix: &Instruction, expected: &[(&str, bool, bool)]) {
+        let accounts = ix
+            .accounts
+            .iter()
+            .map(|meta| {
+                (meta.pubkey.to_string(), meta.is_writable, meta.is_signer)
+            })
+            .collect::<Vec<_>>();
+        let expected = expected
+            .iter()
+            .map(|(pubkey, writable, signer)| {
+                (pubkey.to_string(), *writable, *signer)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(accounts, expected);
+        assert_eq!(ix.program_id.to_string(), PROGRAM.0);
+    }
+
+    #[test]
+    fn test_make_pump_swap_ix_golden() {
+        let base = [
+            GLOBAL,
+            FEE_RECIPIENT,
+            MINT,
+            BONDING_CURVE,
+            ASSOCIATED_BONDING_CURVE,
+            ATA,
+            OWNER,
+            SYSTEM,
+            TOKEN,
+        ];
+        let cases = [
+            (PumpIxVersion::Legacy, vec![RENT, EVENT_AUTH, PROGRAM]),
+            (
+                PumpIxVersion::CreatorVault,
+                vec![CREATOR_VAULT, EVENT_AUTH, PROGRAM],
+            ),
+            (
+                PumpIxVersion::VolumeAccumulator,
+                vec![
+                    CREATOR_VAULT,
+                    EVENT_AUTH,
+                    PROGRAM,
+                    GLOBAL_VOLUME,
+                    USER_VOLUME,
+                ],
+            ),
+            (
+                PumpIxVersion::FeeConfig,
+                vec![
+                    CREATOR_VAULT,
+                    EVENT_AUTH,
+                    PROGRAM,
+                    GLOBAL_VOLUME,
+                    USER_VOLUME,
+                    FEE_CONFIG,
+                    FEE_PROGRAM,
+                ],
+            ),
+        ];
+        for (version, tail) in cases {
+            let ix = make_pump_swap_ix(
+                Pubkey::from_str(OWNER.0).unwrap(),
+                golden_pump_accounts(),
+                Pubkey::from_str(FEE_RECIPIENT.0).unwrap(),
+                1_000_000,
+                50_000_000,
+                Pubkey::from_str(ATA.0).unwrap(),
+                version,
+            )
+            .expect("make swap ix");
+            assert_golden(&ix, &[base.to_vec(), tail].concat());
+            assert_eq!(
+                hex::encode(&ix.data),
+                "66063d1201daebea40420f000000000080f0fa0200000000"
+            );
+        }
+    }
+
+    #[test]
+    fn test_make_pump_sell_ix_golden() {
+        let base = [
+            GLOBAL,
+            FEE_RECIPIENT,
+            MINT,
+            BONDING_CURVE,
+            ASSOCIATED_BONDING_CURVE,
+            ATA,
+            OWNER,
+            SYSTEM,
+        ];
+        let creator_vault_tail =
+            vec![CREATOR_VAULT, TOKEN, EVENT_AUTH, PROGRAM];
+        let cases = [
+            (
+                PumpIxVersion::Legacy,
+                vec![ATA_PROGRAM, TOKEN, EVENT_AUTH, PROGRAM],
+            ),
+            (PumpIxVersion::CreatorVault, creator_vault_tail.clone()),
+            // volume is only tracked on buys
+            (PumpIxVersion::VolumeAccumulator, creator_vault_tail.clone()),
+            (
+                PumpIxVersion::FeeConfig,
+                [creator_vault_tail, vec![FEE_CONFIG, FEE_PROGRAM]].concat(),
+            ),
+        ];
+        for (version, tail) in cases {
+            let ix = make_pump_sell_ix(
+                Pubkey::from_str(OWNER.0).unwrap(),
+                golden_pump_accounts(),
+                Pubkey::from_str(FEE_RECIPIENT.0).unwrap(),
+                1_000_000,
+                50_000_000,
+                Pubkey::from_str(ATA.0).unwrap(),
+                version,
+            )
+            .expect("make sell ix");
+            assert_golden(&ix, &[base.to_vec(), tail].concat());
+            assert_eq!(
+                hex::encode(&ix.data),
+                "33e685a4017f83ad40420f000000000080f0fa0200000000"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pump_ix_version_requires_creator() {
+        let mut pump_accounts = golden_pump_accounts();
+        pump_accounts.dev = Pubkey::default();
+        let owner = Pubkey::from_str(OWNER.0).unwrap();
+        let ata = Pubkey::from_str(ATA.0).unwrap();
+        assert!(make_pump_swap_ix(
+            owner,
+            pump_accounts,
+            PumpGlobal::default().fee_recipient,
+            1,
+            1,
+            ata,
+            PumpIxVersion::Legacy
+        )
+        .is_ok());
+        assert!(make_pump_swap_ix(
+            owner,
+            pump_accounts,
+            PumpGlobal::default().fee_recipient,
+            1,
+            1,
+            ata,
+            PumpIxVersion::CreatorVault
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_pump_ix_version_detect() {
+        let mut curve = BondingCurveLayout::default();
+        assert_eq!(PumpIxVersion::detect(&curve), PumpIxVersion::Legacy);
+        curve.version = BondingCurveVersion::V2;
+        assert_eq!(PumpIxVersion::detect(&curve), PumpIxVersion::LATEST);
+        assert_eq!(
+            "creator-vault".parse::<PumpIxVersion>(),
+            Ok(PumpIxVersion::CreatorVault)
+        );
+        assert!("v3".parse::<PumpIxVersion>().is_err());
+    }
 }
@@ -1,12 +1,25 @@
-use crate::jito::{/*send_jito_tx*/ subscribe_tips, SearcherClient};
+use crate::blockhash::BlockhashCache;
+use crate::bundle_tracker::{
+    poll_inflight_statuses, BundleTracker, INFLIGHT_POLL_INTERVAL,
+};
+use crate::compute::ComputeEstimator;
+use crate::global::{
+    update_pump_global, PumpGlobal, PUMP_GLOBAL_REFRESH_INTERVAL,
+};
+use crate::jito::subscribe_tips;
 use crate::pump::{self, PumpBuyRequest};
-use crate::slot::{make_deadline_ix, update_slot};
-use crate::util::{get_jito_tip_pubkey, make_compute_budget_ixs};
+use crate::sender::TxSender;
+use crate::slot::update_slot;
+use crate::tip::{
+    TipConfig, TipFloor, TipStrategy, DEFAULT_MAX_TIP, SNIPE_MIN_TIP,
+};
+use crate::tip_tuner::{start_tip_tuning, TipTuner, TuningTarget};
+use crate::tx_builder::PumpTxBuilder;
+use actix_web::error::ErrorServiceUnavailable;
 use actix_web::web::Data;
 use actix_web::{get, post, web::Json, App, Error, HttpResponse, HttpServer};
 
-use jito_searcher_client::{get_searcher_client, send_bundle_no_wait};
-use log::{debug, error, info, warn};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use solana_client::nonblocking::rpc_client::RpcClient;
@@ -14,54 +27,38 @@ use solana_sdk::clock::Slot;
 use solana_sdk::hash::Hash;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::{EncodableKey, Signer};
-use solana_sdk::system_instruction::transfer;
-
-use solana_sdk::transaction::{Transaction, VersionedTransaction};
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::sync::{Mutex, RwLock};
-use tokio::time::interval;
 
 fn env(key: &str) -> String {
     std::env::var(key).unwrap_or_else(|_| panic!("{} env var not set", key))
 }
 
-pub async fn update_latest_blockhash(
-    rpc_client: Arc<RpcClient>,
-    latest_blockhash: Arc<RwLock<Hash>>,
-) {
-    let mut interval = interval(Duration::from_secs(1));
-    loop {
-        interval.tick().await;
-        match rpc_client.get_latest_blockhash().await {
-            Ok(new_blockhash) => {
-                let mut blockhash = latest_blockhash.write().await;
-                *blockhash = new_blockhash;
-                debug!("Updated latest blockhash: {}", new_blockhash);
-            }
-            Err(e) => {
-                error!("Failed to get latest blockhash: {}", e);
-            }
-        }
-    }
-}
-
 pub struct AppState {
     pub wallet: Arc<Mutex<Keypair>>,
-    pub searcher_client: Arc<Mutex<SearcherClient>>,
-    pub latest_blockhash: Arc<RwLock<Hash>>,
-    pub dynamic_tip: Arc<RwLock<u64>>,
+    pub rpc_client: Arc<RpcClient>,
+    pub sender: Arc<dyn TxSender>,
+    pub bundle_tracker: Arc<BundleTracker>,
+    pub blockhash_cache: Arc<BlockhashCache>,
+    pub compute_estimator: Arc<ComputeEstimator>,
+    pub tip_floor: Arc<RwLock<Option<TipFloor>>>,
+    pub tip_tuner: Arc<TipTuner>,
     pub lamports: u64,
     pub slot: Arc<RwLock<u64>>,
+    pub pump_global: Arc<RwLock<PumpGlobal>>,
 }
 
 #[get("/blockhash")]
 #[timed::timed(duration(printer = "info!"))]
 pub async fn get_blockhash(state: Data<AppState>) -> HttpResponse {
-    let blockhash = state.latest_blockhash.read().await;
-    HttpResponse::Ok().json(json!({
-        "blockhash": blockhash.to_string()
-    }))
+    match state.blockhash_cache.get().await {
+        Ok(blockhash) => HttpResponse::Ok().json(json!({
+            "blockhash": blockhash.to_string()
+        })),
+        Err(e) => HttpResponse::ServiceUnavailable().json(json!({
+            "error": e.to_string()
+        })),
+    }
 }
 
 use crate::util::{pubkey_to_string, string_to_pubkey};
@@ -93,6 +90,12 @@ pub struct CreatePumpTokenEvent {
     pub num_dev_buy_txs: u64,
     pub virtual_sol_reserves: u64,
     pub virtual_token_reserves: u64,
+    #[serde(
+        default,
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub creator: Pubkey,
 }
 
 #[post("/v2/pump-buy")]
@@ -112,6 +115,7 @@ pub async fn handle_pump_buy_v2(
         virtual_token_reserves: create_pump_token_event
             .virtual_token_reserves,
         slot: Some(create_pump_token_event.slot),
+        creator: create_pump_token_event.creator,
     };
     if create_pump_token_event.dev_max_sol_cost > 1_500_000_000 {
         warn!("dev_max_sol_cost too high");
@@ -125,23 +129,34 @@ pub async fn handle_pump_buy_v2(
         create_pump_token_event.slot
     );
     let wallet = state.wallet.lock().await;
-    let mut searcher_client = state.searcher_client.lock().await;
-    let latest_blockhash = state.latest_blockhash.read().await;
-    // let dynamic_tip = state.dynamic_tip.read().await;
+    let latest_blockhash = state
+        .blockhash_cache
+        .get()
+        .await
+        .map_err(ErrorServiceUnavailable)?;
+    let pump_global = state.pump_global.read().await;
+    let tip = state
+        .tip_tuner
+        .tip(state.tip_floor.read().await.as_ref(), state.lamports)
+        .await;
     let deadline = create_pump_token_event.slot + 1;
     let buy_config = BuyConfig {
         lamports: state.lamports,
         jitter: 1,
         num_tries: 1,
         deadline: Some(deadline),
-        tip: 1_000_000,
+        tip,
     };
     _handle_pump_buy(
         buy_config,
         pump_buy_request,
         &wallet,
-        &mut searcher_client,
+        &state.rpc_client,
+        state.sender.as_ref(),
+        &state.bundle_tracker,
         &latest_blockhash,
+        &pump_global,
+        &state.compute_estimator,
     )
     .await?;
 
@@ -164,9 +179,16 @@ pub async fn handle_pump_buy(
     let mint = pump_buy_request.mint;
     let pump_buy_request = pump_buy_request.clone();
     let wallet = state.wallet.lock().await;
-    let mut searcher_client = state.searcher_client.lock().await;
-    let latest_blockhash = state.latest_blockhash.read().await;
-    let dynamic_tip = state.dynamic_tip.read().await;
+    let latest_blockhash = state
+        .blockhash_cache
+        .get()
+        .await
+        .map_err(ErrorServiceUnavailable)?;
+    let pump_global = state.pump_global.read().await;
+    let tip = state
+        .tip_tuner
+        .tip(state.tip_floor.read().await.as_ref(), state.lamports)
+        .await;
     let deadline = if pump_buy_request.slot.is_some() {
         Some(pump_buy_request.slot.unwrap() + 1)
     } else {
@@ -177,14 +199,18 @@ pub async fn handle_pump_buy(
         jitter: 1,
         num_tries: 1,
         deadline,
-        tip: *dynamic_tip,
+        tip,
     };
     _handle_pump_buy(
         buy_config,
         pump_buy_request,
         &wallet,
-        &mut searcher_client,
+        &state.rpc_client,
+        state.sender.as_ref(),
+        &state.bundle_tracker,
         &latest_blockhash,
+        &pump_global,
+        &state.compute_estimator,
     )
     .await?;
     Ok(HttpResponse::Ok().json(json!({
@@ -194,13 +220,6 @@ pub async fn handle_pump_buy(
     })))
 }
 
-/// apply_fee puts the 1% pump fee on the lamports in
-/// e.g. 1 sol -> 1.01 sol
-pub fn apply_fee(lamports: u64) -> u64 {
-    let fee = lamports / 100;
-    lamports + fee
-}
-
 pub struct BuyConfig {
     pub lamports: u64,
     pub jitter: u64,
@@ -209,13 +228,18 @@ pub struct BuyConfig {
     pub tip: u64,
 }
 
+#[allow(clippy::too_many_arguments, clippy::explicit_counter_loop)]
 #[timed::timed(duration(printer = "info!"))]
 pub async fn _handle_pump_buy(
     buy_config: BuyConfig,
     pump_buy_request: PumpBuyRequest,
     wallet: &Keypair,
-    searcher_client: &mut SearcherClient,
+    rpc_client: &RpcClient,
+    sender: &dyn TxSender,
+    tracker: &BundleTracker,
     latest_blockhash: &Hash,
+    pump_global: &PumpGlobal,
+    estimator: &Arc<ComputeEstimator>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Calculate token amount once, using the original lamports value
     let token_amount = pump::get_token_amount(
@@ -225,55 +249,58 @@ pub async fn _handle_pump_buy(
         buy_config.lamports,
     )?;
 
+    let (pump_accounts, version) =
+        pump::resolve_buy_accounts(rpc_client, &pump_buy_request).await?;
+
     let mut jitter = buy_config.jitter;
     for i in 0..buy_config.num_tries {
-        let mut ixs = vec![];
-        ixs.append(&mut make_compute_budget_ixs(1000069, 69000));
-        let lamports = apply_fee(buy_config.lamports) * 2 + i as u64 + jitter;
-        ixs.append(&mut pump::_make_buy_ixs(
-            wallet.pubkey(),
-            pump_buy_request.mint,
-            pump_buy_request.bonding_curve,
-            pump_buy_request.associated_bonding_curve,
-            token_amount,
-            lamports,
-        )?);
+        let lamports = pump_global.apply_fee(buy_config.lamports) * 2
+            + i as u64
+            + jitter;
         info!("buying {} tokens with {} lamports", token_amount, lamports);
 
-        ixs.push(transfer(
-            &wallet.pubkey(),
-            &get_jito_tip_pubkey(),
-            std::cmp::max(buy_config.tip, 50_000),
-        ));
-
+        let mut builder = PumpTxBuilder::new(wallet.pubkey())
+            .buy(
+                pump_accounts,
+                pump_global.fee_recipient,
+                token_amount,
+                lamports,
+                version,
+            )?
+            .tip(buy_config.tip)
+            .cached_lookup_tables();
+        // a simulation per snipe would cost more than the budget saves
+        builder = builder
+            .cached_compute_budget(estimator, pump::BUY_COMPUTE_BUDGET)
+            .await;
         if let Some(deadline) = buy_config.deadline {
-            ixs.push(make_deadline_ix(deadline));
+            builder = builder.deadline(deadline);
         }
 
-        let swap_tx = Transaction::new_signed_with_payer(
-            ixs.as_slice(),
-            Some(&wallet.pubkey()),
-            &[wallet],
-            *latest_blockhash,
-        );
-
-        // tokio::spawn(async move {
-        //     send_jito_tx(swap_tx).await.expect("send jito tx");
-        // });
+        let swap_tx = builder.build(&[wallet], *latest_blockhash)?;
 
-        let res = send_bundle_no_wait(
-            &[VersionedTransaction::from(swap_tx.clone())],
-            searcher_client,
-        )
-        .await
-        .expect("send bundle no wait");
+        let res = sender.send_transaction(&swap_tx).await?;
 
         info!(
-            "Bundle {} sent through gRPC: {} {:#?}",
+            "Buy {} sent through {} in {:?}: {} {:?}",
             i + 1,
-            res.into_inner().uuid,
-            swap_tx.signatures[0]
+            res.transport,
+            res.latency,
+            res.signature,
+            res.bundle_id
         );
+        if let Some(bundle_id) = res.bundle_id {
+            tracker
+                .track_tx(
+                    bundle_id,
+                    &swap_tx,
+                    Some(pump_buy_request.mint),
+                    buy_config.tip,
+                    pump_buy_request.slot,
+                    buy_config.deadline,
+                )
+                .await;
+        }
 
         jitter += 1;
     }
@@ -293,44 +320,65 @@ pub async fn healthz(request: actix_web::HttpRequest) -> HttpResponse {
     }))
 }
 
-pub async fn run_pump_service(lamports: u64) -> std::io::Result<()> {
+pub async fn run_pump_service(
+    lamports: u64,
+    sender: Arc<dyn TxSender>,
+    tip_strategy: TipStrategy,
+    tune_tips: bool,
+    blockhash_cache: Arc<BlockhashCache>,
+) -> std::io::Result<()> {
     // keep all of the state in the app state not to re-init
     let wallet = Arc::new(Mutex::new(
         Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
             .expect("read fund keypair"),
     ));
-    let auth =
-        Arc::new(Keypair::read_from_file(env("AUTH_KEYPAIR_PATH")).unwrap());
-    let searcher_client = Arc::new(Mutex::new(
-        get_searcher_client(env("BLOCK_ENGINE_URL").as_str(), &auth)
-            .await
-            .expect("makes searcher client"),
-    ));
 
-    // no need if the seller is running
-    // start_bundle_results_listener(searcher_client.clone()).await;
-
-    let dynamic_tip = Arc::new(RwLock::new(0));
-    subscribe_tips(dynamic_tip.clone());
+    let tip_floor = Arc::new(RwLock::new(None));
+    if tip_strategy.uses_tip_stream() {
+        subscribe_tips(tip_floor.clone());
+    }
 
     let slot = Arc::new(RwLock::new(0));
     update_slot(slot.clone());
 
+    // no results stream here, the grpc sender holds the searcher client
+    let bundle_tracker = Arc::new(BundleTracker::new());
+    poll_inflight_statuses(
+        bundle_tracker.clone(),
+        env("BLOCK_ENGINE_URL"),
+        INFLIGHT_POLL_INTERVAL,
+    );
+    let tip_tuner = Arc::new(TipTuner::new(
+        TipConfig::new(tip_strategy)
+            .with_caps(SNIPE_MIN_TIP, DEFAULT_MAX_TIP),
+        TuningTarget::default(),
+    ));
+    if tune_tips {
+        start_tip_tuning(tip_tuner.clone(), bundle_tracker.clone());
+    }
+
+    let rpc_client = Arc::new(RpcClient::new(env("RPC_URL")));
     let app_state = Data::new(AppState {
         wallet,
-        searcher_client,
-        latest_blockhash: Arc::new(RwLock::new(Hash::default())),
-        dynamic_tip,
+        rpc_client: rpc_client.clone(),
+        sender,
+        bundle_tracker,
+        blockhash_cache,
+        compute_estimator: Arc::new(ComputeEstimator::new(
+            rpc_client.clone(),
+        )),
+        tip_floor,
+        tip_tuner,
         lamports,
         slot,
+        pump_global: Arc::new(RwLock::new(PumpGlobal::default())),
     });
 
-    // poll for latest blockhash to trim 200ms
-    let rpc_client = Arc::new(RpcClient::new(env("RPC_URL")));
-    tokio::spawn(update_latest_blockhash(
+    update_pump_global(
         rpc_client.clone(),
-        app_state.latest_blockhash.clone(),
-    ));
+        app_state.pump_global.clone(),
+        PUMP_GLOBAL_REFRESH_INTERVAL,
+    );
 
     info!("Running pump service on 6969");
     HttpServer::new(move || {
@@ -6,7 +6,6 @@ use solana_client::rpc_config::{
     RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter,
 };
 use solana_sdk::commitment_config::CommitmentConfig;
-use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Keypair, Signature};
 use solana_sdk::signer::{EncodableKey, Signer};
@@ -16,9 +15,16 @@ use solana_transaction_status::{
 };
 use tokio::sync::{Mutex, RwLock};
 
+use crate::blockhash::BlockhashCache;
+use crate::bundle_tracker::BundleTracker;
+use crate::compute::ComputeEstimator;
+use crate::events::{parse_events_from_tx, PumpEvent};
+use crate::global::{
+    update_pump_global, PumpGlobal, PUMP_GLOBAL_REFRESH_INTERVAL,
+};
 use crate::jito::start_bundle_results_listener;
-use crate::pump::{mint_to_pump_accounts, sell_pump_token};
-use crate::pump_service::update_latest_blockhash;
+use crate::pump::{mint_to_pump_accounts, sell_pump_token, SELL_TIP};
+use crate::sender::TxSender;
 use crate::util::env;
 use log::{info, warn};
 use std::collections::HashMap;
@@ -26,9 +32,12 @@ use std::error::Error;
 use std::str::FromStr;
 use std::sync::Arc;
 
-pub async fn run_seller() -> Result<(), Box<dyn Error>> {
+pub async fn run_seller(
+    slippage_bps: u64,
+    sender: Arc<dyn TxSender>,
+    blockhash_cache: Arc<BlockhashCache>,
+) -> Result<(), Box<dyn Error>> {
     let sold_cache = Arc::new(RwLock::new(HashMap::new()));
-    let latest_blockhash = Arc::new(RwLock::new(Hash::default()));
     let wallet = Arc::new(
         Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
             .expect("read fund keypair"),
@@ -41,14 +50,18 @@ pub async fn run_seller() -> Result<(), Box<dyn Error>> {
             .expect("makes searcher client"),
     ));
 
-    // poll for latest blockhash to trim 200ms
     let rpc_client = Arc::new(RpcClient::new(env("RPC_URL")));
-    tokio::spawn(update_latest_blockhash(
+    let pump_global = Arc::new(RwLock::new(PumpGlobal::default()));
+    update_pump_global(
         rpc_client.clone(),
-        latest_blockhash.clone(),
-    ));
+        pump_global.clone(),
+        PUMP_GLOBAL_REFRESH_INTERVAL,
+    );
+    let estimator = Arc::new(ComputeEstimator::new(rpc_client.clone()));
 
-    start_bundle_results_listener(searcher_client.clone()).await;
+    let bundle_tracker = Arc::new(BundleTracker::new());
+    start_bundle_results_listener(searcher_client, bundle_tracker.clone())
+        .await;
 
     let pubsub_client = PubsubClient::new(&env("WS_URL")).await?;
     let (mut stream, unsub) = pubsub_client
@@ -64,9 +77,13 @@ pub async fn run_seller() -> Result<(), Box<dyn Error>> {
     while let Some(res) = stream.next().await {
         let sig = res.value.signature;
         let rpc_client = rpc_client.clone();
-        let latest_blockhash = latest_blockhash.clone();
+        let estimator = estimator.clone();
+        let blockhash_cache = blockhash_cache.clone();
         let wallet = wallet.clone();
         let sold_cache = sold_cache.clone();
+        let pump_global = pump_global.clone();
+        let sender = sender.clone();
+        let bundle_tracker = bundle_tracker.clone();
         tokio::spawn(async move {
             if let Ok(tx) = get_tx_with_retries(
                 &rpc_client,
@@ -94,19 +111,52 @@ pub async fn run_seller() -> Result<(), Box<dyn Error>> {
                     {
                         return;
                     }
+                    // before the sold cache so that the next tx retries
+                    let latest_blockhash = match blockhash_cache.get().await {
+                        Ok(latest_blockhash) => latest_blockhash,
+                        Err(e) => {
+                            warn!("Not selling {}: {}", mint, e);
+                            return;
+                        }
+                    };
                     let mut sold_cache = sold_cache.write().await;
                     sold_cache.insert(sig.clone(), token_amount);
                     // let token_amount = token_amount * 3 / 5;
                     let pump_accounts = mint_to_pump_accounts(&mint);
-                    let latest_blockhash = *latest_blockhash.read().await;
-                    sell_pump_token(
+                    let pump_global = pump_global.read().await.clone();
+                    let res = match sell_pump_token(
                         &wallet,
+                        &rpc_client,
+                        &pump_global,
                         latest_blockhash,
                         pump_accounts,
                         token_amount,
+                        slippage_bps,
+                        sender.as_ref(),
+                        &estimator,
                     )
                     .await
-                    .expect("sell pump token");
+                    {
+                        Ok(res) => res,
+                        Err(e) => {
+                            warn!("Not sold {}: {}", mint, e);
+                            // not sold, left for a retry
+                            sold_cache.remove(&sig);
+                            return;
+                        }
+                    };
+                    if let Some(bundle_id) = res.bundle_id {
+                        bundle_tracker
+                            .track(
+                                bundle_id,
+                                vec![res.signature],
+                                Some(mint),
+                                SELL_TIP,
+                                None,
+                                None,
+                            )
+                            .await;
+                    }
                     info!("Sold {} pump tokens", token_amount);
                 } else {
                     warn!("Error getting token balance for {}", ata);
@@ -195,15 +245,16 @@ pub fn tx_to_mint(
     tx: &EncodedConfirmedTransactionWithStatusMeta,
     owner: &Pubkey,
 ) -> Result<(Pubkey, bool), Box<dyn Error>> {
-    let mut is_bid = false;
-    if let Some(meta) = &tx.transaction.meta {
-        if let OptionSerializer::Some(logs) = &meta.log_messages {
-            for log in logs {
-                if log == "Program log: Instruction: Buy" {
-                    is_bid = true;
-                }
+    // the trade event carries the mint, no need to go through balances
+    for event in parse_events_from_tx(tx) {
+        if let PumpEvent::Trade(trade) = event {
+            if trade.user == *owner {
+                return Ok((trade.mint, trade.is_buy));
             }
         }
+    }
+    // no pump trade by the owner, not a bid
+    if let Some(meta) = &tx.transaction.meta {
         if let OptionSerializer::Some(post_token_balances) =
             &meta.post_token_balances
         {
@@ -213,7 +264,7 @@ pub fn tx_to_mint(
                     if *account_owner != owner.to_string() {
                         continue;
                     }
-                    return Ok((Pubkey::from_str(&balance.mint)?, is_bid));
+                    return Ok((Pubkey::from_str(&balance.mint)?, false));
                 }
             }
         }
@@ -227,5 +278,5 @@ pub fn tx_to_mint(
     //         }
     //     }
     // }
-    Ok((Pubkey::default(), is_bid))
+    Ok((Pubkey::default(), false))
 }
@@ -0,0 +1,655 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use jito_searcher_client::send_bundle_no_wait;
+use log::{info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::nonblocking::tpu_client::TpuClient;
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_client::tpu_client::TpuClientConfig;
+use solana_quic_client::{QuicConfig, QuicConnectionManager, QuicPool};
+use solana_sdk::commitment_config::CommitmentLevel;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use tokio::sync::{mpsc, Mutex, RwLock};
+
+use crate::block_engine::make_regional_jito_sender;
+use crate::dry_run::{get_dry_run, DryRunSender};
+use crate::jito::{
+    block_engine_url, make_searcher_client, SearcherClient, JITO_REGIONS,
+};
+use crate::jito_client::JitoClient;
+use crate::util::env;
+
+/// Transport is how a tx gets to the leader
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transport {
+    /// sendTransaction on the block engine JSON-RPC
+    JitoHttp,
+    /// single tx bundle through the searcher gRPC
+    JitoGrpc,
+    /// sendTransaction on RPC_URL
+    Rpc,
+    /// straight to the leader TPUs over QUIC
+    Tpu,
+    /// all of the above at once, jito http in every region, the first one
+    /// to accept the tx wins, not the first one to land it
+    Race,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::JitoHttp => write!(f, "jito-http"),
+            Self::JitoGrpc => write!(f, "jito-grpc"),
+            Self::Rpc => write!(f, "rpc"),
+            Self::Tpu => write!(f, "tpu"),
+            Self::Race => write!(f, "race"),
+        }
+    }
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jito-http" => Ok(Self::JitoHttp),
+            "jito-grpc" => Ok(Self::JitoGrpc),
+            "rpc" => Ok(Self::Rpc),
+            "tpu" => Ok(Self::Tpu),
+            "race" => Ok(Self::Race),
+            _ => Err(format!("Invalid transport: {}", s)),
+        }
+    }
+}
+
+impl Transport {
+    /// from_env reads TX_SENDER
+    pub fn from_env() -> Option<Self> {
+        let transport = std::env::var("TX_SENDER").ok()?;
+        match transport.parse() {
+            Ok(transport) => Some(transport),
+            Err(e) => {
+                warn!("{}, falling back to the default", e);
+                None
+            }
+        }
+    }
+
+    /// resolve prefers the --sender flag, then TX_SENDER, then the default
+    /// of the command
+    pub fn resolve(flag: Option<Self>, default: Self) -> Self {
+        flag.or_else(Self::from_env).unwrap_or(default)
+    }
+}
+
+/// SendResult is the same for every transport, bundle_id is only there for
+/// the ones that go through a bundle
+#[derive(Debug, Clone)]
+pub struct SendResult {
+    pub signature: Signature,
+    pub bundle_id: Option<String>,
+    pub transport: Transport,
+    /// time until the transport acked the tx, not until it landed
+    pub latency: Duration,
+}
+
+#[async_trait]
+pub trait TxSender: Send + Sync {
+    fn transport(&self) -> Transport;
+
+    /// name tells apart senders of the same transport in the race stats
+    fn name(&self) -> String {
+        self.transport().to_string()
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>>;
+}
+
+fn first_signature(
+    tx: &VersionedTransaction,
+) -> Result<Signature, Box<dyn Error>> {
+    Ok(*tx
+        .signatures
+        .first()
+        .ok_or("Transaction has no signatures")?)
+}
+
+pub struct JitoHttpSender {
+    jito_client: JitoClient,
+}
+
+impl JitoHttpSender {
+    /// block_engine_url is the base url, e.g. block_engine_url("ny")
+    pub fn new(block_engine_url: String) -> Self {
+        Self::with_client(block_engine_url, reqwest::Client::new())
+    }
+
+    /// with_client shares the connection pool between senders
+    pub fn with_client(
+        block_engine_url: String,
+        client: reqwest::Client,
+    ) -> Self {
+        Self {
+            jito_client: JitoClient::with_client(block_engine_url, client),
+        }
+    }
+}
+
+#[async_trait]
+impl TxSender for JitoHttpSender {
+    fn transport(&self) -> Transport {
+        Transport::JitoHttp
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{} {}",
+            self.transport(),
+            self.jito_client.block_engine_url()
+        )
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>> {
+        let start = Instant::now();
+        let sent = self.jito_client.send_transaction(tx).await?;
+        let latency = start.elapsed();
+        Ok(SendResult {
+            signature: sent.signature,
+            // the block engine wraps the tx in a bundle and says which one
+            bundle_id: sent.bundle_id,
+            transport: self.transport(),
+            latency,
+        })
+    }
+}
+
+pub struct JitoGrpcSender {
+    searcher_client: Arc<Mutex<SearcherClient>>,
+}
+
+impl JitoGrpcSender {
+    /// the searcher client can be shared with a bundle results listener
+    pub fn new(searcher_client: Arc<Mutex<SearcherClient>>) -> Self {
+        Self { searcher_client }
+    }
+}
+
+#[async_trait]
+impl TxSender for JitoGrpcSender {
+    fn transport(&self) -> Transport {
+        Transport::JitoGrpc
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>> {
+        let signature = first_signature(tx)?;
+        let mut searcher_client = self.searcher_client.lock().await;
+        let start = Instant::now();
+        let res = send_bundle_no_wait(
+            std::slice::from_ref(tx),
+            &mut searcher_client,
+        )
+        .await?;
+        Ok(SendResult {
+            signature,
+            bundle_id: Some(res.into_inner().uuid),
+            transport: self.transport(),
+            latency: start.elapsed(),
+        })
+    }
+}
+
+pub struct RpcSender {
+    rpc_client: Arc<RpcClient>,
+    config: RpcSendTransactionConfig,
+}
+
+impl RpcSender {
+    /// skips preflight, the txs are time sensitive
+    pub fn new(rpc_client: Arc<RpcClient>) -> Self {
+        Self {
+            rpc_client,
+            config: RpcSendTransactionConfig {
+                skip_preflight: true,
+                preflight_commitment: Some(CommitmentLevel::Processed),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl TxSender for RpcSender {
+    fn transport(&self) -> Transport {
+        Transport::Rpc
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>> {
+        let start = Instant::now();
+        let signature = self
+            .rpc_client
+            .send_transaction_with_config(tx, self.config)
+            .await?;
+        Ok(SendResult {
+            signature,
+            bundle_id: None,
+            transport: self.transport(),
+            latency: start.elapsed(),
+        })
+    }
+}
+
+pub struct TpuSender {
+    tpu_client: TpuClient<QuicPool, QuicConnectionManager, QuicConfig>,
+}
+
+impl TpuSender {
+    /// the rpc is only used for the leader schedule, ws_url for slot updates
+    pub async fn new(
+        rpc_client: Arc<RpcClient>,
+        ws_url: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let tpu_client = TpuClient::new(
+            "pump-rs",
+            rpc_client,
+            ws_url,
+            TpuClientConfig::default(),
+        )
+        .await?;
+        Ok(Self { tpu_client })
+    }
+}
+
+#[async_trait]
+impl TxSender for TpuSender {
+    fn transport(&self) -> Transport {
+        Transport::Tpu
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>> {
+        let signature = first_signature(tx)?;
+        let wire_transaction = bincode::serialize(tx)?;
+        let start = Instant::now();
+        self.tpu_client
+            .try_send_wire_transaction(wire_transaction)
+            .await?;
+        Ok(SendResult {
+            signature,
+            bundle_id: None,
+            transport: self.transport(),
+            latency: start.elapsed(),
+        })
+    }
+}
+
+/// TransportStats is what an AcceptRaceSender saw from one of its senders
+#[derive(Debug, Clone, Default)]
+pub struct TransportStats {
+    pub sent: u64,
+    pub accepted: u64,
+    pub failed: u64,
+    /// summed over the accepted sends
+    pub total_latency: Duration,
+}
+
+impl TransportStats {
+    pub fn avg_latency(&self) -> Option<Duration> {
+        (self.accepted > 0).then(|| self.total_latency / self.accepted as u32)
+    }
+}
+
+/// AcceptRaceSender sends the same signed tx through all of its senders at
+/// once, the signature can only land once so there is no risk of a double
+/// buy, it races on acceptance: the first transport to accept the tx is
+/// returned, which says nothing about which one lands it, the rest finish
+/// in the background and only count towards the stats
+pub struct AcceptRaceSender {
+    senders: Vec<Arc<dyn TxSender>>,
+    stats: Arc<RwLock<HashMap<String, TransportStats>>>,
+}
+
+impl AcceptRaceSender {
+    pub fn new(senders: Vec<Arc<dyn TxSender>>) -> Self {
+        Self {
+            senders,
+            stats: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// stats per sender name, the in-flight sends are not in there yet
+    pub async fn stats(&self) -> HashMap<String, TransportStats> {
+        self.stats.read().await.clone()
+    }
+}
+
+#[async_trait]
+impl TxSender for AcceptRaceSender {
+    fn transport(&self) -> Transport {
+        Transport::Race
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: &VersionedTransaction,
+    ) -> Result<SendResult, Box<dyn Error>> {
+        let signature = first_signature(tx)?;
+        let (results_tx, mut results_rx) = mpsc::unbounded_channel();
+        for sender in &self.senders {
+            let sender = sender.clone();
+            let tx = tx.clone();
+            let stats = self.stats.clone();
+            let results_tx = results_tx.clone();
+            tokio::spawn(async move {
+                let name = sender.name();
+                let res = sender
+                    .send_transaction(&tx)
+                    .await
+                    .map_err(|e| e.to_string());
+                {
+                    let mut stats = stats.write().await;
+                    let stats = stats.entry(name.clone()).or_default();
+                    stats.sent += 1;
+                    match &res {
+                        Ok(res) => {
+                            stats.accepted += 1;
+                            stats.total_latency += res.latency;
+                        }
+                        Err(_) => stats.failed += 1,
+                    }
+                }
+                // the receiver is gone once there is a winner
+                let _ = results_tx.send((name, res));
+            });
+        }
+        drop(results_tx);
+
+        let mut errors = vec![];
+        while let Some((name, res)) = results_rx.recv().await {
+            match res {
+                Ok(res) => {
+                    info!(
+                        "{} accepted {} first, in {:?}",
+                        name, signature, res.latency
+                    );
+                    return Ok(res);
+                }
+                Err(e) => {
+                    warn!("{} failed to send {}: {}", name, signature, e);
+                    errors.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+        Err(format!("All transports failed: {}", errors.join(", ")).into())
+    }
+}
+
+/// JITO_FANOUT is how many of the fastest regions a jito http send races
+pub fn jito_fanout() -> usize {
+    std::env::var("JITO_FANOUT")
+        .ok()
+        .and_then(|fanout| fanout.parse().ok())
+        .unwrap_or(1)
+}
+
+/// the rpc leg goes to STAKED_RPC_URL when there is one
+fn send_rpc_url() -> String {
+    std::env::var("STAKED_RPC_URL").unwrap_or_else(|_| env("RPC_URL"))
+}
+
+async fn _make_tx_sender(
+    transport: Transport,
+) -> Result<Arc<dyn TxSender>, Box<dyn Error>> {
+    Ok(match transport {
+        Transport::JitoHttp => make_regional_jito_sender(jito_fanout()).await,
+        Transport::JitoGrpc => Arc::new(JitoGrpcSender::new(Arc::new(
+            Mutex::new(make_searcher_client().await?),
+        ))),
+        Transport::Rpc => {
+            Arc::new(RpcSender::new(Arc::new(RpcClient::new(send_rpc_url()))))
+        }
+        Transport::Tpu => Arc::new(
+            TpuSender::new(
+                Arc::new(RpcClient::new(env("RPC_URL"))),
+                &env("WS_URL"),
+            )
+            .await?,
+        ),
+        Transport::Race => {
+            return Err("race is made up of the other transports".into())
+        }
+    })
+}
+
+/// make_tx_sender builds the sender off the env, jito http goes to the
+/// fastest JITO_FANOUT regions, BLOCK_ENGINE_URL + AUTH_KEYPAIR_PATH for
+/// gRPC, RPC_URL (or STAKED_RPC_URL) for rpc and RPC_URL + WS_URL for tpu,
+/// the race uses all of them with jito http in every region, a dry run only
+/// records the txs
+pub async fn make_tx_sender(
+    transport: Transport,
+) -> Result<Arc<dyn TxSender>, Box<dyn Error>> {
+    info!("Sending through {}", transport);
+    // a dry run never reaches the block engine, not even for tip accounts
+    if let Some(dry_run) = get_dry_run() {
+        return Ok(Arc::new(DryRunSender::new(transport, dry_run)));
+    }
+    if !matches!(transport, Transport::Rpc | Transport::Tpu) {
+        // tip the accounts the block engine lists, not the hardcoded ones
+        if let Err(e) = JitoClient::shared().tip_accounts().await {
+            warn!("Failed to get tip accounts: {}", e);
+        }
+    }
+    if transport != Transport::Race {
+        return _make_tx_sender(transport).await;
+    }
+    let mut senders = vec![_make_tx_sender(Transport::JitoGrpc).await?];
+    for region in JITO_REGIONS {
+        senders.push(Arc::new(JitoHttpSender::new(block_engine_url(region))));
+    }
+    senders.push(_make_tx_sender(Transport::Rpc).await?);
+    senders.push(_make_tx_sender(Transport::Tpu).await?);
+    Ok(Arc::new(AcceptRaceSender::new(senders)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_jito::{make_tx, BundleSource, MockBlockEngine};
+    use crate::mock_rpc::{MockRpcServer, RpcFixtures};
+    use jito_searcher_client::get_searcher_client;
+    use solana_sdk::signature::Keypair;
+
+    struct StubSender {
+        name: &'static str,
+        delay: Duration,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl TxSender for StubSender {
+        fn transport(&self) -> Transport {
+            Transport::Rpc
+        }
+
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        async fn send_transaction(
+            &self,
+            tx: &VersionedTransaction,
+        ) -> Result<SendResult, Box<dyn Error>> {
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                return Err(format!("{} is down", self.name).into());
+            }
+            Ok(SendResult {
+                signature: tx.signatures[0],
+                bundle_id: None,
+                transport: self.transport(),
+                latency: self.delay,
+            })
+        }
+    }
+
+    fn stub(
+        name: &'static str,
+        delay_ms: u64,
+        fail: bool,
+    ) -> Arc<dyn TxSender> {
+        Arc::new(StubSender {
+            name,
+            delay: Duration::from_millis(delay_ms),
+            fail,
+        })
+    }
+
+    #[test]
+    fn test_transport_from_str() {
+        for transport in [
+            Transport::JitoHttp,
+            Transport::JitoGrpc,
+            Transport::Rpc,
+            Transport::Tpu,
+            Transport::Race,
+        ] {
+            assert_eq!(transport.to_string().parse(), Ok(transport));
+        }
+        assert_eq!("JITO-GRPC".parse(), Ok(Transport::JitoGrpc));
+        assert!("smoke-signals".parse::<Transport>().is_err());
+        assert_eq!(
+            Transport::resolve(Some(Transport::Tpu), Transport::Rpc),
+            Transport::Tpu
+        );
+    }
+
+    #[tokio::test]
+    async fn test_senders() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        let rpc = MockRpcServer::start(RpcFixtures::default()).await.unwrap();
+        let auth = Arc::new(Keypair::new());
+        let searcher_client =
+            get_searcher_client(&engine.url, &auth).await.unwrap();
+        let payer = Keypair::new();
+
+        let senders: Vec<Box<dyn TxSender>> = vec![
+            Box::new(JitoHttpSender::new(engine.http_url.clone())),
+            Box::new(JitoGrpcSender::new(Arc::new(Mutex::new(
+                searcher_client,
+            )))),
+            Box::new(RpcSender::new(Arc::new(RpcClient::new(
+                rpc.rpc_url.clone(),
+            )))),
+        ];
+        let mut txs = vec![];
+        for (i, sender) in senders.iter().enumerate() {
+            let tx = make_tx(&payer, i as u64 + 1);
+            let res = sender.send_transaction(&tx).await.unwrap();
+            assert_eq!(res.signature, tx.signatures[0]);
+            assert_eq!(res.transport, sender.transport());
+            txs.push((tx, res));
+        }
+
+        let (tx, res) = &txs[0];
+        assert_eq!(res.transport, Transport::JitoHttp);
+        assert_eq!(engine.transactions().await, vec![tx.clone()]);
+
+        let (tx, res) = &txs[1];
+        let bundles = engine.bundles().await;
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].source, BundleSource::Grpc);
+        assert_eq!(bundles[0].transactions, vec![tx.clone()]);
+        assert_eq!(res.bundle_id.as_ref(), Some(&bundles[0].uuid));
+
+        let (tx, res) = &txs[2];
+        assert!(res.bundle_id.is_none());
+        assert_eq!(rpc.sent_transactions().await, vec![tx.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_race_sender() {
+        tokio::time::pause();
+        let payer = Keypair::new();
+        let tx = make_tx(&payer, 1);
+
+        let race = AcceptRaceSender::new(vec![
+            stub("slow", 300, false),
+            stub("broken", 0, true),
+            stub("fast", 20, false),
+        ]);
+        let res = race.send_transaction(&tx).await.unwrap();
+        assert_eq!(res.signature, tx.signatures[0]);
+        assert_eq!(res.latency, Duration::from_millis(20));
+
+        // the winner does not wait for the slow one
+        let stats = race.stats().await;
+        assert_eq!(stats["fast"].accepted, 1);
+        assert_eq!(stats["broken"].failed, 1);
+        assert!(!stats.contains_key("slow"));
+        tokio::time::advance(Duration::from_millis(300)).await;
+        // let the woken sender finish
+        tokio::task::yield_now().await;
+        let stats = race.stats().await;
+        assert_eq!(stats["slow"].accepted, 1);
+        assert_eq!(
+            stats["slow"].avg_latency(),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(stats["broken"].avg_latency(), None);
+
+        let race = AcceptRaceSender::new(vec![
+            stub("broken", 0, true),
+            stub("also broken", 10, true),
+        ]);
+        let err = race.send_transaction(&tx).await.unwrap_err();
+        assert!(err.to_string().contains("also broken is down"));
+    }
+
+    #[tokio::test]
+    async fn test_race_sender_same_tx_everywhere() {
+        let engine = MockBlockEngine::start().await.unwrap();
+        let rpc = MockRpcServer::start(RpcFixtures::default()).await.unwrap();
+        let race = AcceptRaceSender::new(vec![
+            Arc::new(JitoHttpSender::new(engine.http_url.clone())),
+            Arc::new(RpcSender::new(Arc::new(RpcClient::new(
+                rpc.rpc_url.clone(),
+            )))),
+        ]);
+        let tx = make_tx(&Keypair::new(), 1);
+        race.send_transaction(&tx).await.unwrap();
+        // wait on the loser, not on the clock
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while race.stats().await.len() < 2 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(engine.transactions().await, vec![tx.clone()]);
+        assert_eq!(rpc.sent_transactions().await, vec![tx]);
+        let stats = race.stats().await;
+        assert_eq!(stats.len(), 2);
+        assert!(stats.values().all(|stats| stats.accepted == 1));
+    }
+}
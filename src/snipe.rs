@@ -1,18 +1,23 @@
 use fastwebsockets::{Frame, OpCode, Payload};
-use futures::StreamExt;
-use jito_protos::searcher::SubscribeBundleResultsRequest;
 use jito_searcher_client::get_searcher_client;
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::EncodableKey;
 use tokio::sync::{Mutex, RwLock};
 
-use crate::pump::PumpBuyRequest;
-use crate::pump_service::{
-    _handle_pump_buy, update_latest_blockhash, BuyConfig,
+use crate::blockhash::BlockhashCache;
+use crate::bundle_tracker::BundleTracker;
+use crate::compute::ComputeEstimator;
+use crate::global::{
+    update_pump_global, PumpGlobal, PUMP_GLOBAL_REFRESH_INTERVAL,
 };
+use crate::jito::{start_bundle_results_listener, subscribe_tips};
+use crate::pump::PumpBuyRequest;
+use crate::pump_service::{_handle_pump_buy, BuyConfig};
+use crate::sender::TxSender;
+use crate::tip::{TipConfig, TipStrategy, DEFAULT_MAX_TIP, SNIPE_MIN_TIP};
+use crate::tip_tuner::{start_tip_tuning, TipTuner, TuningTarget};
 use crate::util::{env, pubkey_to_string, string_to_pubkey};
 use crate::ws::connect_to_pump_websocket;
 use log::{info, warn};
@@ -45,6 +50,12 @@ pub struct NewCoin {
     pub virtual_sol_reserves: u64,
     pub real_token_reserves: u64,
     pub real_sol_reserves: u64,
+    #[serde(
+        default,
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub creator: Pubkey,
 }
 
 pub enum MessageType {
@@ -63,8 +74,13 @@ pub fn get_message_type(data: &str) -> MessageType {
     }
 }
 
-pub async fn snipe_pump(lamports: u64) -> Result<(), Box<dyn Error>> {
-    let latest_blockhash = Arc::new(RwLock::new(Hash::default()));
+pub async fn snipe_pump(
+    lamports: u64,
+    sender: Arc<dyn TxSender>,
+    tip_strategy: TipStrategy,
+    tune_tips: bool,
+    blockhash_cache: Arc<BlockhashCache>,
+) -> Result<(), Box<dyn Error>> {
     let wallet = Arc::new(
         Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
             .expect("read fund keypair"),
@@ -77,30 +93,31 @@ pub async fn snipe_pump(lamports: u64) -> Result<(), Box<dyn Error>> {
             .expect("makes searcher client"),
     ));
 
-    // make parametrized as lamports probably, this will be changed to dynamic
-    // tip calculation soon
-    let tip = 1_000_000;
+    let tip_tuner = Arc::new(TipTuner::new(
+        TipConfig::new(tip_strategy)
+            .with_caps(SNIPE_MIN_TIP, DEFAULT_MAX_TIP),
+        TuningTarget::default(),
+    ));
+    let tip_floor = Arc::new(RwLock::new(None));
+    if tip_strategy.uses_tip_stream() {
+        subscribe_tips(tip_floor.clone());
+    }
 
-    // poll for latest blockhash to trim 200ms
     let rpc_client = Arc::new(RpcClient::new(env("RPC_URL")));
-    tokio::spawn(update_latest_blockhash(
+    let pump_global = Arc::new(RwLock::new(PumpGlobal::default()));
+    update_pump_global(
         rpc_client.clone(),
-        latest_blockhash.clone(),
-    ));
+        pump_global.clone(),
+        PUMP_GLOBAL_REFRESH_INTERVAL,
+    );
+    let estimator = Arc::new(ComputeEstimator::new(rpc_client.clone()));
 
-    // poll for bundle results
-    let mut bundle_results_stream = searcher_client
-        .lock()
-        .await
-        .subscribe_bundle_results(SubscribeBundleResultsRequest {})
-        .await
-        .expect("subscribe bundle results")
-        .into_inner();
-    tokio::spawn(async move {
-        while let Some(res) = bundle_results_stream.next().await {
-            info!("Received bundle result: {:?}", res);
-        }
-    });
+    let bundle_tracker = Arc::new(BundleTracker::new());
+    start_bundle_results_listener(searcher_client, bundle_tracker.clone())
+        .await;
+    if tune_tips {
+        start_tip_tuning(tip_tuner.clone(), bundle_tracker.clone());
+    }
 
     let mut ws = connect_to_pump_websocket().await?;
     ws.set_writev(true);
@@ -129,10 +146,15 @@ pub async fn snipe_pump(lamports: u64) -> Result<(), Box<dyn Error>> {
                         let message_type = get_message_type(&data);
                         match message_type {
                             MessageType::NewCoinCreated => {
-                                let latest_blockhash =
-                                    latest_blockhash.clone();
+                                let blockhash_cache = blockhash_cache.clone();
                                 let wallet = wallet.clone();
-                                let searcher_client = searcher_client.clone();
+                                let sender = sender.clone();
+                                let bundle_tracker = bundle_tracker.clone();
+                                let pump_global = pump_global.clone();
+                                let tip_floor = tip_floor.clone();
+                                let tip_tuner = tip_tuner.clone();
+                                let estimator = estimator.clone();
+                                let rpc_client = rpc_client.clone();
                                 tokio::spawn(async move {
                                     let json_parsable = data
                                         .trim_start_matches(
@@ -145,10 +167,27 @@ pub async fn snipe_pump(lamports: u64) -> Result<(), Box<dyn Error>> {
                                     if !coin_filter(&coin) {
                                         return;
                                     }
-                                    let mut searcher_client =
-                                        searcher_client.lock().await;
                                     let latest_blockhash =
-                                        latest_blockhash.read().await;
+                                        match blockhash_cache.get().await {
+                                            Ok(latest_blockhash) => {
+                                                latest_blockhash
+                                            }
+                                            Err(e) => {
+                                                warn!(
+                                                    "Not buying {}: {}",
+                                                    coin.mint, e
+                                                );
+                                                return;
+                                            }
+                                        };
+                                    let pump_global =
+                                        pump_global.read().await;
+                                    let tip = tip_tuner
+                                        .tip(
+                                            tip_floor.read().await.as_ref(),
+                                            lamports,
+                                        )
+                                        .await;
                                     _handle_pump_buy(
                                         BuyConfig {
                                             lamports,
@@ -167,10 +206,15 @@ pub async fn snipe_pump(lamports: u64) -> Result<(), Box<dyn Error>> {
                                             virtual_sol_reserves: coin
                                                 .virtual_sol_reserves,
                                             slot: None,
+                                            creator: coin.creator,
                                         },
                                         &wallet.clone(),
-                                        &mut searcher_client,
+                                        &rpc_client,
+                                        sender.as_ref(),
+                                        &bundle_tracker,
                                         &latest_blockhash,
+                                        &pump_global,
+                                        &estimator,
                                     )
                                     .await
                                     .expect("handle pump buy");
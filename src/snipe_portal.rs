@@ -1,21 +1,28 @@
 use fastwebsockets::{Frame, OpCode, Payload};
 // use futures::StreamExt;
 // use jito_protos::searcher::SubscribeBundleResultsRequest;
-use jito_searcher_client::get_searcher_client;
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::EncodableKey;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::RwLock;
 use tracing::info;
 
+use crate::blockhash::BlockhashCache;
+use crate::bundle_tracker::{
+    poll_inflight_statuses, BundleTracker, INFLIGHT_POLL_INTERVAL,
+};
+use crate::compute::ComputeEstimator;
+use crate::global::{
+    update_pump_global, PumpGlobal, PUMP_GLOBAL_REFRESH_INTERVAL,
+};
 use crate::jito::subscribe_tips;
 use crate::pump::{mint_to_pump_accounts, PumpBuyRequest};
-use crate::pump_service::{
-    _handle_pump_buy, update_latest_blockhash, BuyConfig,
-};
+use crate::pump_service::{_handle_pump_buy, BuyConfig};
+use crate::sender::TxSender;
 use crate::slot::update_slot;
+use crate::tip::{TipConfig, TipStrategy, DEFAULT_MAX_TIP, SNIPE_MIN_TIP};
+use crate::tip_tuner::{start_tip_tuning, TipTuner, TuningTarget};
 use crate::util::{env, pubkey_to_string, string_to_pubkey};
 use crate::ws::connect_to_pump_portal_websocket;
 use log::warn;
@@ -51,32 +58,49 @@ pub struct NewPumpPortalToken {
     pub initial_buy: f64,
 }
 
-pub async fn snipe_portal(lamports: u64) -> Result<(), Box<dyn Error>> {
-    let latest_blockhash = Arc::new(RwLock::new(Hash::default()));
+pub async fn snipe_portal(
+    lamports: u64,
+    sender: Arc<dyn TxSender>,
+    tip_strategy: TipStrategy,
+    tune_tips: bool,
+    blockhash_cache: Arc<BlockhashCache>,
+) -> Result<(), Box<dyn Error>> {
     let wallet = Arc::new(
         Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
             .expect("read fund keypair"),
     );
-    let auth =
-        Arc::new(Keypair::read_from_file(env("AUTH_KEYPAIR_PATH")).unwrap());
-    let searcher_client = Arc::new(Mutex::new(
-        get_searcher_client(env("BLOCK_ENGINE_URL").as_str(), &auth)
-            .await
-            .expect("makes searcher client"),
-    ));
 
-    let dynamic_tip = Arc::new(RwLock::new(0));
-    subscribe_tips(dynamic_tip.clone());
+    let tip_tuner = Arc::new(TipTuner::new(
+        TipConfig::new(tip_strategy)
+            .with_caps(SNIPE_MIN_TIP, DEFAULT_MAX_TIP),
+        TuningTarget::default(),
+    ));
+    let tip_floor = Arc::new(RwLock::new(None));
+    if tip_strategy.uses_tip_stream() {
+        subscribe_tips(tip_floor.clone());
+    }
 
-    // poll for latest blockhash to trim 200ms
     let rpc_client = Arc::new(RpcClient::new(env("RPC_URL")));
-    tokio::spawn(update_latest_blockhash(
+    let pump_global = Arc::new(RwLock::new(PumpGlobal::default()));
+    update_pump_global(
         rpc_client.clone(),
-        latest_blockhash.clone(),
-    ));
+        pump_global.clone(),
+        PUMP_GLOBAL_REFRESH_INTERVAL,
+    );
+    let estimator = Arc::new(ComputeEstimator::new(rpc_client.clone()));
     let slot = Arc::new(RwLock::new(0));
     update_slot(slot.clone());
 
+    let bundle_tracker = Arc::new(BundleTracker::new());
+    poll_inflight_statuses(
+        bundle_tracker.clone(),
+        env("BLOCK_ENGINE_URL"),
+        INFLIGHT_POLL_INTERVAL,
+    );
+    if tune_tips {
+        start_tip_tuning(tip_tuner.clone(), bundle_tracker.clone());
+    }
+
     let mut ws = connect_to_pump_portal_websocket().await?;
     ws.set_writev(true);
 
@@ -99,14 +123,25 @@ pub async fn snipe_portal(lamports: u64) -> Result<(), Box<dyn Error>> {
                 }
                 let token: NewPumpPortalToken = serde_json::from_str(&data)?;
                 info!("signature {:?}", token.signature);
-                let latest_blockhash = latest_blockhash.clone();
+                let blockhash_cache = blockhash_cache.clone();
                 let wallet = wallet.clone();
-                let searcher_client = searcher_client.clone();
+                let sender = sender.clone();
+                let bundle_tracker = bundle_tracker.clone();
                 let slot = slot.clone();
-                let dynamic_tip = dynamic_tip.clone();
+                let tip_floor = tip_floor.clone();
+                let tip_tuner = tip_tuner.clone();
+                let estimator = estimator.clone();
+                let rpc_client = rpc_client.clone();
+                let pump_global = pump_global.clone();
                 tokio::spawn(async move {
-                    let latest_blockhash = latest_blockhash.read().await;
-                    let mut searcher_client = searcher_client.lock().await;
+                    let latest_blockhash = match blockhash_cache.get().await {
+                        Ok(latest_blockhash) => latest_blockhash,
+                        Err(e) => {
+                            warn!("Not buying {}: {}", token.mint, e);
+                            return;
+                        }
+                    };
+                    let pump_global = pump_global.read().await;
                     // below math is wrong, hardcoding for now
                     let virtual_token_reserves =
                         (token.virtual_token_reserves * 1e6).round() as u64;
@@ -116,7 +151,10 @@ pub async fn snipe_portal(lamports: u64) -> Result<(), Box<dyn Error>> {
                     let associated_bonding_curve =
                         pump_accounts.associated_bonding_curve;
                     // TODO buy based on this, say if
-                    if virtual_sol_reserves > 31_000_000_000 {
+                    if virtual_sol_reserves
+                        > pump_global.initial_virtual_sol_reserves
+                            + 1_000_000_000
+                    {
                         warn!(
                             "dev bought >= 1 sol (vSOL: {})",
                             virtual_sol_reserves,
@@ -132,6 +170,7 @@ pub async fn snipe_portal(lamports: u64) -> Result<(), Box<dyn Error>> {
                         virtual_token_reserves,
                         virtual_sol_reserves,
                         slot: None,
+                        creator: token.dev,
                     };
                     // tl;dr
                     // if there is a deadline, might miss bids coz the pumpportal data comes before
@@ -151,15 +190,24 @@ pub async fn snipe_portal(lamports: u64) -> Result<(), Box<dyn Error>> {
                     _handle_pump_buy(
                         BuyConfig {
                             lamports,
-                            tip: *dynamic_tip.read().await,
+                            tip: tip_tuner
+                                .tip(
+                                    tip_floor.read().await.as_ref(),
+                                    lamports,
+                                )
+                                .await,
                             deadline: None,
                             jitter: 1,
                             num_tries: 1,
                         },
                         buy_req,
                         &wallet.clone(),
-                        &mut searcher_client,
+                        &rpc_client,
+                        sender.as_ref(),
+                        &bundle_tracker,
                         &latest_blockhash,
+                        &pump_global,
+                        &estimator,
                     )
                     .await
                     .expect("handle pump buy");
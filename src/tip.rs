@@ -0,0 +1,308 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use serde::Deserialize;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+/// jito does not take tips below this
+pub const DEFAULT_MIN_TIP: u64 = 1_000;
+
+/// 0.01 SOL, a bad tip stream frame should not burn the wallet
+pub const DEFAULT_MAX_TIP: u64 = 10_000_000;
+
+/// snipes compete for the creation slot, below this they do not land
+pub const SNIPE_MIN_TIP: u64 = 50_000;
+
+/// what the pump service used to hardcode
+pub const DEFAULT_FALLBACK_TIP: u64 = 1_000_000;
+
+/// the tip stream sends a frame every few seconds
+pub const DEFAULT_TIP_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// landed tips percentiles in SOL, as sent by the jito tip stream
+#[derive(Debug, Deserialize)]
+struct TipStreamFrame {
+    landed_tips_25th_percentile: f64,
+    landed_tips_50th_percentile: f64,
+    landed_tips_75th_percentile: f64,
+    landed_tips_95th_percentile: f64,
+    landed_tips_99th_percentile: f64,
+    ema_landed_tips_50th_percentile: f64,
+}
+
+fn sol_to_lamports(sol: f64) -> u64 {
+    (sol * LAMPORTS_PER_SOL as f64).round() as u64
+}
+
+/// TipFloor is the landed tips distribution in lamports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TipFloor {
+    pub p25: u64,
+    pub p50: u64,
+    pub p75: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub ema_p50: u64,
+    pub received_at: Instant,
+}
+
+impl TipFloor {
+    /// parse takes a tip stream text frame, a one element array
+    pub fn parse(frame: &str) -> Result<Self, Box<dyn Error>> {
+        let frame = serde_json::from_str::<Vec<TipStreamFrame>>(frame)?
+            .pop()
+            .ok_or("Empty tip stream frame")?;
+        Ok(Self {
+            p25: sol_to_lamports(frame.landed_tips_25th_percentile),
+            p50: sol_to_lamports(frame.landed_tips_50th_percentile),
+            p75: sol_to_lamports(frame.landed_tips_75th_percentile),
+            p95: sol_to_lamports(frame.landed_tips_95th_percentile),
+            p99: sol_to_lamports(frame.landed_tips_99th_percentile),
+            ema_p50: sol_to_lamports(frame.ema_landed_tips_50th_percentile),
+            received_at: Instant::now(),
+        })
+    }
+
+    /// percentile interpolates linearly between the known percentiles,
+    /// anything outside of 25..=99 is clamped
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let points = [
+            (25., self.p25),
+            (50., self.p50),
+            (75., self.p75),
+            (95., self.p95),
+            (99., self.p99),
+        ];
+        let percentile = percentile.clamp(25., 99.);
+        for window in points.windows(2) {
+            let ((lo, lo_tip), (hi, hi_tip)) = (window[0], window[1]);
+            if percentile <= hi {
+                let t = (percentile - lo) / (hi - lo);
+                return (lo_tip as f64 + (hi_tip as f64 - lo_tip as f64) * t)
+                    .round() as u64;
+            }
+        }
+        self.p99
+    }
+
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.received_at.elapsed() > max_age
+    }
+}
+
+/// TipStrategy is how much goes to the jito tip account
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TipStrategy {
+    /// lamports
+    Fixed(u64),
+    /// percentile of the landed tips from the tip stream
+    Percentile(f64),
+    /// basis points of the position, e.g. 100 tips 1% of the buy
+    PositionSize { bps: u64 },
+}
+
+impl fmt::Display for TipStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(lamports) => write!(f, "fixed:{}", lamports),
+            Self::Percentile(percentile) => {
+                write!(f, "percentile:{}", percentile)
+            }
+            Self::PositionSize { bps } => write!(f, "position:{}", bps),
+        }
+    }
+}
+
+impl FromStr for TipStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid tip strategy: {}", s);
+        let (kind, value) = s.split_once(':').ok_or_else(err)?;
+        match kind.to_lowercase().as_str() {
+            "fixed" => Ok(Self::Fixed(value.parse().map_err(|_| err())?)),
+            "percentile" => {
+                let percentile = value.parse::<f64>().map_err(|_| err())?;
+                if !(0. ..=100.).contains(&percentile) {
+                    return Err(err());
+                }
+                Ok(Self::Percentile(percentile))
+            }
+            "position" => Ok(Self::PositionSize {
+                bps: value.parse().map_err(|_| err())?,
+            }),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl TipStrategy {
+    /// from_env reads TIP_STRATEGY
+    pub fn from_env() -> Option<Self> {
+        let strategy = std::env::var("TIP_STRATEGY").ok()?;
+        match strategy.parse() {
+            Ok(strategy) => Some(strategy),
+            Err(e) => {
+                warn!("{}, falling back to the default", e);
+                None
+            }
+        }
+    }
+
+    /// resolve prefers the --tip flag, then TIP_STRATEGY, then the default
+    /// of the command
+    pub fn resolve(flag: Option<Self>, default: Self) -> Self {
+        flag.or_else(Self::from_env).unwrap_or(default)
+    }
+
+    /// uses_tip_stream is whether the tip stream has to be subscribed to
+    pub fn uses_tip_stream(&self) -> bool {
+        matches!(self, Self::Percentile(_))
+    }
+}
+
+/// TipConfig is a strategy with the caps around it
+#[derive(Debug, Clone, Copy)]
+pub struct TipConfig {
+    pub strategy: TipStrategy,
+    pub min_tip: u64,
+    pub max_tip: u64,
+    /// tip floors older than this are not used
+    pub max_age: Duration,
+    /// tip used when the strategy needs the tip stream and it is stale
+    pub fallback_tip: u64,
+}
+
+impl TipConfig {
+    pub fn new(strategy: TipStrategy) -> Self {
+        Self {
+            strategy,
+            min_tip: DEFAULT_MIN_TIP,
+            max_tip: DEFAULT_MAX_TIP,
+            max_age: DEFAULT_TIP_MAX_AGE,
+            fallback_tip: DEFAULT_FALLBACK_TIP,
+        }
+    }
+
+    pub fn with_caps(mut self, min_tip: u64, max_tip: u64) -> Self {
+        self.min_tip = min_tip;
+        self.max_tip = max_tip.max(min_tip);
+        self
+    }
+
+    /// tip in lamports for a position of position_lamports
+    pub fn tip(
+        &self,
+        floor: Option<&TipFloor>,
+        position_lamports: u64,
+    ) -> u64 {
+        let tip = match self.strategy {
+            TipStrategy::Fixed(lamports) => lamports,
+            TipStrategy::Percentile(percentile) => {
+                match floor.filter(|floor| !floor.is_stale(self.max_age)) {
+                    Some(floor) => floor.percentile(percentile),
+                    None => {
+                        warn!(
+                            "No fresh tip floor, tipping {}",
+                            self.fallback_tip
+                        );
+                        self.fallback_tip
+                    }
+                }
+            }
+            TipStrategy::PositionSize { bps } => {
+                (position_lamports as u128 * bps as u128 / 10_000) as u64
+            }
+        };
+        tip.clamp(self.min_tip, self.max_tip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// recorded from wss://bundles.jito.wtf/api/v1/bundles/tip_stream
+    const FRAMES: [&str; 2] = [
+        r#"[{"time":"2024-09-01T12:58:00Z","landed_tips_25th_percentile":6.001000000000001e-06,"landed_tips_50th_percentile":1e-05,"landed_tips_75th_percentile":3.6196500000000005e-05,"landed_tips_95th_percentile":0.0014479055000000002,"landed_tips_99th_percentile":0.010007999,"ema_landed_tips_50th_percentile":9.836078125000002e-06}]"#,
+        r#"[{"time":"2024-09-01T13:24:00Z","landed_tips_25th_percentile":1.0000000000000003e-05,"landed_tips_50th_percentile":3e-05,"landed_tips_75th_percentile":0.0001,"landed_tips_95th_percentile":0.0020000000000000005,"landed_tips_99th_percentile":0.11262015000000006,"ema_landed_tips_50th_percentile":2.3578217480468756e-05}]"#,
+    ];
+
+    #[test]
+    fn test_parse_tip_stream_frames() {
+        let floor = TipFloor::parse(FRAMES[0]).unwrap();
+        assert_eq!(
+            (floor.p25, floor.p50, floor.p75, floor.p95, floor.p99),
+            (6_001, 10_000, 36_197, 1_447_906, 10_007_999)
+        );
+        assert_eq!(floor.ema_p50, 9_836);
+        // the old 0.95 * p75 + 0.05 * p95 blend, in lamports this time
+        let blend = 0.95 * floor.p75 as f64 + 0.05 * floor.p95 as f64;
+        assert_eq!(blend.round() as u64, 106_782);
+
+        let floor = TipFloor::parse(FRAMES[1]).unwrap();
+        assert_eq!(floor.p50, 30_000);
+        assert_eq!(floor.p99, 112_620_150);
+
+        assert!(TipFloor::parse("[]").is_err());
+        assert!(TipFloor::parse(r#"[{"time":"now"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_percentile_interpolation() {
+        let floor = TipFloor::parse(FRAMES[0]).unwrap();
+        assert_eq!(floor.percentile(50.), 10_000);
+        assert_eq!(floor.percentile(75.), 36_197);
+        assert_eq!(floor.percentile(62.5), 23_099);
+        assert_eq!(floor.percentile(85.), 742_052);
+        assert_eq!(floor.percentile(10.), floor.p25);
+        assert_eq!(floor.percentile(100.), floor.p99);
+    }
+
+    #[test]
+    fn test_tip_config() {
+        let floor = TipFloor::parse(FRAMES[1]).unwrap();
+        let percentile = TipConfig::new(TipStrategy::Percentile(75.));
+        assert_eq!(percentile.tip(Some(&floor), 0), 100_000);
+        // p99 is over the cap
+        let p99 = TipConfig::new(TipStrategy::Percentile(99.));
+        assert_eq!(p99.tip(Some(&floor), 0), DEFAULT_MAX_TIP);
+        let capped = p99.with_caps(50_000, 5_000_000);
+        assert_eq!(capped.tip(Some(&floor), 0), 5_000_000);
+
+        // stale or missing floors fall back
+        let stale = TipFloor {
+            received_at: Instant::now() - Duration::from_secs(120),
+            ..floor
+        };
+        assert_eq!(percentile.tip(Some(&stale), 0), DEFAULT_FALLBACK_TIP);
+        assert_eq!(percentile.tip(None, 0), DEFAULT_FALLBACK_TIP);
+
+        let fixed = TipConfig::new(TipStrategy::Fixed(50));
+        assert_eq!(fixed.tip(None, 0), DEFAULT_MIN_TIP);
+        let snipe = fixed.with_caps(SNIPE_MIN_TIP, DEFAULT_MAX_TIP);
+        assert_eq!(snipe.tip(None, 0), SNIPE_MIN_TIP);
+
+        let position = TipConfig::new(TipStrategy::PositionSize { bps: 100 });
+        assert_eq!(position.tip(None, 200_000_000), 2_000_000);
+        assert_eq!(position.tip(Some(&floor), 10_000), DEFAULT_MIN_TIP);
+    }
+
+    #[test]
+    fn test_tip_strategy_from_str() {
+        for s in ["fixed:1000000", "percentile:75", "position:100"] {
+            let strategy = s.parse::<TipStrategy>().unwrap();
+            assert_eq!(strategy.to_string(), s);
+        }
+        assert_eq!(
+            "percentile:62.5".parse::<TipStrategy>(),
+            Ok(TipStrategy::Percentile(62.5))
+        );
+        assert!("percentile:120".parse::<TipStrategy>().is_err());
+        assert!("fixed".parse::<TipStrategy>().is_err());
+        assert!("dynamic:1".parse::<TipStrategy>().is_err());
+    }
+}
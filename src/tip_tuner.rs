@@ -0,0 +1,520 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use log::{debug, info};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+use crate::bundle_tracker::{
+    BundleRecord, BundleState, BundleTracker, Rejection,
+};
+use crate::preflight::{decode_rejection, PumpError};
+use crate::tip::{TipConfig, TipFloor};
+
+/// outcomes the landing rate is computed over
+pub const TIP_TUNER_WINDOW: usize = 50;
+
+/// how much the tip multiplier moves per adjustment
+const TIP_TUNER_STEP: f64 = 0.2;
+
+/// the multiplier stays within these, the caps of the config still apply
+const MIN_MULTIPLIER: f64 = 0.25;
+const MAX_MULTIPLIER: f64 = 20.;
+
+/// TuningTarget is what the tuner aims for
+#[derive(Debug, Clone, Copy)]
+pub struct TuningTarget {
+    /// share of the bundles that land
+    pub landing_rate: f64,
+    /// average slots between the target slot and landing, runs/final.txt
+    /// has 0 or 1 for good snipes
+    pub slot_delta: f64,
+    /// outcomes needed before the first adjustment, and in between
+    /// adjustments
+    pub min_samples: usize,
+}
+
+impl Default for TuningTarget {
+    fn default() -> Self {
+        Self {
+            landing_rate: 0.8,
+            slot_delta: 1.,
+            min_samples: 10,
+        }
+    }
+}
+
+/// TipObservation is the tip paid for a bundle and how it went
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TipObservation {
+    pub tip: u64,
+    pub landed: bool,
+    pub slot_delta: Option<u64>,
+}
+
+impl TipObservation {
+    /// from_record is None for bundles that are not final yet and for
+    /// outcomes the tip has nothing to do with, e.g. an internal error
+    pub fn from_record(record: &BundleRecord) -> Option<Self> {
+        if record.sent_at.is_none() || record.tip == 0 {
+            return None;
+        }
+        let landed = match &record.state {
+            state if !state.is_final() => return None,
+            state if state.landed() => true,
+            BundleState::Dropped(_)
+            | BundleState::Failed
+            | BundleState::Rejected(Rejection::Outbid(_)) => false,
+            // a deadline that reverted is a late bundle, slippage or a
+            // complete curve is not about the tip
+            BundleState::Rejected(Rejection::SimulationFailure(reason))
+                if record.deadline.is_some()
+                    && decode_rejection(reason, &record.programs)
+                        == Some(PumpError::DeadlinePassed) =>
+            {
+                false
+            }
+            _ => return None,
+        };
+        Some(Self {
+            tip: record.tip,
+            landed,
+            slot_delta: record.slot_delta(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TunerStats {
+    pub samples: usize,
+    pub landing_rate: f64,
+    /// over the landed bundles with a target slot
+    pub avg_slot_delta: Option<f64>,
+    pub avg_tip: f64,
+    pub multiplier: f64,
+}
+
+struct TunerState {
+    observations: VecDeque<TipObservation>,
+    multiplier: f64,
+    since_adjustment: usize,
+}
+
+/// TipTuner scales the tip of a TipConfig up while the bundles miss the
+/// target and back down when they beat it, the caps of the config are the
+/// budget
+pub struct TipTuner {
+    config: TipConfig,
+    target: TuningTarget,
+    state: RwLock<TunerState>,
+}
+
+impl TipTuner {
+    pub fn new(config: TipConfig, target: TuningTarget) -> Self {
+        Self {
+            config,
+            target,
+            state: RwLock::new(TunerState {
+                observations: VecDeque::with_capacity(TIP_TUNER_WINDOW),
+                multiplier: 1.,
+                since_adjustment: 0,
+            }),
+        }
+    }
+
+    /// tip is the tip of the config scaled by what has been learned
+    pub async fn tip(
+        &self,
+        floor: Option<&TipFloor>,
+        position_lamports: u64,
+    ) -> u64 {
+        let tip = self.config.tip(floor, position_lamports) as f64
+            * self.state.read().await.multiplier;
+        (tip.round() as u64).clamp(self.config.min_tip, self.config.max_tip)
+    }
+
+    fn _stats(state: &TunerState) -> TunerStats {
+        let samples = state.observations.len();
+        let landed = state
+            .observations
+            .iter()
+            .filter(|observation| observation.landed)
+            .collect::<Vec<_>>();
+        let slot_deltas = landed
+            .iter()
+            .filter_map(|observation| observation.slot_delta)
+            .collect::<Vec<_>>();
+        TunerStats {
+            samples,
+            landing_rate: landed.len() as f64 / samples.max(1) as f64,
+            avg_slot_delta: (!slot_deltas.is_empty()).then(|| {
+                slot_deltas.iter().sum::<u64>() as f64
+                    / slot_deltas.len() as f64
+            }),
+            avg_tip: state
+                .observations
+                .iter()
+                .map(|observation| observation.tip as f64)
+                .sum::<f64>()
+                / samples.max(1) as f64,
+            multiplier: state.multiplier,
+        }
+    }
+
+    pub async fn stats(&self) -> TunerStats {
+        Self::_stats(&*self.state.read().await)
+    }
+
+    pub async fn observe(&self, observation: TipObservation) {
+        let mut state = self.state.write().await;
+        if state.observations.len() == TIP_TUNER_WINDOW {
+            state.observations.pop_front();
+        }
+        state.observations.push_back(observation);
+        state.since_adjustment += 1;
+        if state.observations.len() < self.target.min_samples
+            || state.since_adjustment < self.target.min_samples
+        {
+            return;
+        }
+        let stats = Self::_stats(&state);
+        let too_slow = stats
+            .avg_slot_delta
+            .is_some_and(|slot_delta| slot_delta > self.target.slot_delta);
+        let multiplier =
+            if stats.landing_rate < self.target.landing_rate || too_slow {
+                state.multiplier * (1. + TIP_TUNER_STEP)
+            } else {
+                // on target, see if it still lands for less
+                state.multiplier * (1. - TIP_TUNER_STEP / 2.)
+            };
+        state.multiplier = multiplier.clamp(MIN_MULTIPLIER, MAX_MULTIPLIER);
+        state.since_adjustment = 0;
+        info!(
+            "Tip tuner: landed {:.0}% with {:?} slot delta at {:.0} avg tip, \
+             multiplier {:.2}",
+            stats.landing_rate * 100.,
+            stats.avg_slot_delta,
+            stats.avg_tip,
+            state.multiplier
+        );
+    }
+}
+
+/// start_tip_tuning feeds the final bundle states of the tracker into the
+/// tuner, once per bundle
+pub fn start_tip_tuning(
+    tuner: Arc<TipTuner>,
+    tracker: Arc<BundleTracker>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut updates = tracker.subscribe();
+        loop {
+            let uuid = match updates.recv().await {
+                Ok(uuid) => uuid,
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Tip tuner skipped {} updates", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let Some(record) = tracker.get(&uuid).await else {
+                continue;
+            };
+            // updates are read after the fact, a bundle can be final for
+            // more than one of them
+            let Some(observation) = TipObservation::from_record(&record)
+            else {
+                continue;
+            };
+            if tracker.mark_observed(&uuid).await {
+                debug!("Tip tuner observed {} {:?}", uuid, observation);
+                tuner.observe(observation).await;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global::PumpGlobal;
+    use crate::pump::{mint_to_pump_accounts, PumpAccounts, PumpIxVersion};
+    use crate::tip::TipStrategy;
+    use crate::tx_builder::PumpTxBuilder;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::transaction::VersionedTransaction;
+    use std::time::Duration;
+
+    async fn wait_for_samples(tuner: &TipTuner, samples: usize) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while tuner.stats().await.samples < samples {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("tuner observed the bundles");
+    }
+
+    /// compute budget x2, ata create, buy, deadline
+    fn deadline_buy_tx(deadline: u64) -> VersionedTransaction {
+        let wallet = Keypair::new();
+        PumpTxBuilder::new(wallet.pubkey())
+            .compute_budget(1_000, 100_000)
+            .buy(
+                PumpAccounts {
+                    dev: Pubkey::new_unique(),
+                    ..mint_to_pump_accounts(&Pubkey::new_unique())
+                },
+                PumpGlobal::default().fee_recipient,
+                1_000,
+                2_000,
+                PumpIxVersion::default(),
+            )
+            .unwrap()
+            .deadline(deadline)
+            .build(&[&wallet], Hash::new_unique())
+            .unwrap()
+    }
+
+    fn simulation_failure(msg: &str) -> BundleState {
+        BundleState::Rejected(Rejection::SimulationFailure(format!(
+            "simulation failure: {}",
+            msg
+        )))
+    }
+
+    fn observation(landed: bool, slot_delta: u64) -> TipObservation {
+        TipObservation {
+            tip: 100_000,
+            landed,
+            slot_delta: landed.then_some(slot_delta),
+        }
+    }
+
+    fn make_tuner() -> TipTuner {
+        TipTuner::new(
+            TipConfig::new(TipStrategy::Fixed(100_000))
+                .with_caps(10_000, 500_000),
+            TuningTarget::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_tip_tuner_raises_on_misses() {
+        let tuner = make_tuner();
+        assert_eq!(tuner.tip(None, 0).await, 100_000);
+        for i in 0..10 {
+            tuner.observe(observation(i % 2 == 0, 0)).await;
+        }
+        let stats = tuner.stats().await;
+        assert_eq!(stats.landing_rate, 0.5);
+        assert_eq!(tuner.tip(None, 0).await, 120_000);
+
+        // never past the budget
+        for _ in 0..200 {
+            tuner.observe(observation(false, 0)).await;
+        }
+        assert_eq!(tuner.tip(None, 0).await, 500_000);
+        assert_eq!(tuner.stats().await.samples, TIP_TUNER_WINDOW);
+
+        // landing but late is a miss too
+        let tuner = make_tuner();
+        for _ in 0..10 {
+            tuner.observe(observation(true, 3)).await;
+        }
+        assert_eq!(tuner.stats().await.landing_rate, 1.);
+        assert_eq!(tuner.tip(None, 0).await, 120_000);
+    }
+
+    #[tokio::test]
+    async fn test_tip_tuner_lowers_on_target() {
+        let tuner = make_tuner();
+        for _ in 0..9 {
+            tuner.observe(observation(true, 0)).await;
+        }
+        // not enough samples yet
+        assert_eq!(tuner.tip(None, 0).await, 100_000);
+        tuner.observe(observation(true, 1)).await;
+        assert_eq!(tuner.tip(None, 0).await, 90_000);
+        let stats = tuner.stats().await;
+        assert_eq!(stats.avg_slot_delta, Some(0.1));
+        assert_eq!(stats.avg_tip, 100_000.);
+    }
+
+    #[tokio::test]
+    async fn test_tip_observation_from_record() {
+        let tracker = Arc::new(BundleTracker::new());
+        let tuner = Arc::new(make_tuner());
+        start_tip_tuning(tuner.clone(), tracker.clone());
+        tokio::task::yield_now().await;
+
+        let cases = [
+            (
+                "landed",
+                BundleState::Processed { slot: 11 },
+                Some(10),
+                None,
+            ),
+            (
+                "dropped",
+                BundleState::Dropped("BlockhashExpired".into()),
+                None,
+                None,
+            ),
+            (
+                "deadline",
+                simulation_failure(
+                    "Error processing Instruction 4: custom program error: 0x1",
+                ),
+                Some(10),
+                Some(11),
+            ),
+            (
+                "slippage",
+                simulation_failure(
+                    "Error processing Instruction 3: custom program error: 0x1772",
+                ),
+                Some(10),
+                Some(11),
+            ),
+            (
+                "complete",
+                simulation_failure(
+                    "Error processing Instruction 3: custom program error: 0x1775",
+                ),
+                Some(10),
+                Some(11),
+            ),
+            (
+                "internal",
+                BundleState::Rejected(Rejection::Other(
+                    "internal error: oops".into(),
+                )),
+                None,
+                None,
+            ),
+        ];
+        for (uuid, state, target_slot, deadline) in cases.iter().cloned() {
+            match deadline {
+                Some(deadline) => {
+                    tracker
+                        .track_tx(
+                            uuid.to_string(),
+                            &deadline_buy_tx(deadline),
+                            None,
+                            100_000,
+                            target_slot,
+                            Some(deadline),
+                        )
+                        .await
+                }
+                None => {
+                    tracker
+                        .track(
+                            uuid.to_string(),
+                            vec![],
+                            None,
+                            100_000,
+                            target_slot,
+                            None,
+                        )
+                        .await
+                }
+            }
+            tracker.update(uuid, state).await;
+        }
+        // processed is not final yet
+        let landed = tracker.get("landed").await.unwrap();
+        assert_eq!(TipObservation::from_record(&landed), None);
+        tracker.update("landed", BundleState::Finalized).await;
+
+        let observations = [
+            TipObservation::from_record(
+                &tracker.get("landed").await.unwrap(),
+            ),
+            TipObservation::from_record(
+                &tracker.get("dropped").await.unwrap(),
+            ),
+            TipObservation::from_record(
+                &tracker.get("deadline").await.unwrap(),
+            ),
+        ];
+        assert_eq!(
+            observations,
+            [
+                Some(TipObservation {
+                    tip: 100_000,
+                    landed: true,
+                    slot_delta: Some(1)
+                }),
+                Some(observation(false, 0)),
+                Some(observation(false, 0)),
+            ]
+        );
+        for uuid in ["slippage", "complete", "internal"] {
+            let record = tracker.get(uuid).await.unwrap();
+            assert_eq!(TipObservation::from_record(&record), None);
+        }
+
+        wait_for_samples(&tuner, 3).await;
+        let stats = tuner.stats().await;
+        assert_eq!(stats.samples, 3);
+        assert!((stats.landing_rate - 1. / 3.).abs() < 1e-9);
+        assert_eq!(stats.avg_slot_delta, Some(1.));
+    }
+
+    #[tokio::test]
+    async fn test_tip_tuner_ignores_slippage() {
+        let tracker = Arc::new(BundleTracker::new());
+        let tuner = Arc::new(make_tuner());
+        start_tip_tuning(tuner.clone(), tracker.clone());
+        tokio::task::yield_now().await;
+
+        // deadline guarded buys that went over the slippage
+        for i in 0..20 {
+            let uuid = format!("slippage-{}", i);
+            tracker
+                .track_tx(
+                    uuid.clone(),
+                    &deadline_buy_tx(11),
+                    None,
+                    100_000,
+                    Some(10),
+                    Some(11),
+                )
+                .await;
+            tracker
+                .update(
+                    &uuid,
+                    simulation_failure(
+                        "Error processing Instruction 3: custom program error: 0x1772",
+                    ),
+                )
+                .await;
+        }
+        // updates are handled in order, once this one is in the ones
+        // before it are too
+        tracker
+            .track(
+                "landed".to_string(),
+                vec![],
+                None,
+                100_000,
+                Some(10),
+                None,
+            )
+            .await;
+        tracker
+            .update("landed", BundleState::Processed { slot: 10 })
+            .await;
+        tracker.update("landed", BundleState::Finalized).await;
+        wait_for_samples(&tuner, 1).await;
+        assert_eq!(tuner.stats().await.samples, 1);
+        assert_eq!(tuner.tip(None, 0).await, 100_000);
+    }
+}
@@ -0,0 +1,603 @@
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use log::warn;
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::clock::Slot;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::{v0, Message, VersionedMessage};
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::system_instruction::transfer;
+use solana_sdk::transaction::VersionedTransaction;
+use spl_associated_token_account::get_associated_token_address;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token::instruction::close_account;
+
+use crate::compute::{ComputeBudget, ComputeEstimator};
+use crate::constants::MEMO_PROGRAM;
+use crate::lookup_table::get_lookup_tables;
+use crate::pump::{
+    make_pump_sell_ix, make_pump_swap_ix, PumpAccounts, PumpIxVersion,
+};
+use crate::slot::make_deadline_ix;
+use crate::util::{get_jito_tip_pubkey, make_compute_budget_ixs};
+use crate::wallet::TxLifetime;
+
+/// mainnet still locks at most 64 accounts per tx
+pub const MAX_TX_ACCOUNTS: usize = 64;
+
+/// PumpTxBuilder puts together a trade tx, the ixs always go in the same
+/// order: nonce advance, compute budget, the body (ata creates, swaps and
+/// anything else in the order added), ata closes, memo, tip and deadline
+#[derive(Debug, Clone)]
+pub struct PumpTxBuilder {
+    payer: Pubkey,
+    compute_budget: Option<(u64, u32)>,
+    body: Vec<Instruction>,
+    /// mints with an ata create in the body already
+    atas: Vec<Pubkey>,
+    close_atas: Vec<Pubkey>,
+    memo: Option<String>,
+    /// lamports and the tip account, picked once so that every
+    /// instructions() call, the estimate included, tips the same account
+    tip: Option<(u64, Pubkey)>,
+    deadline: Option<Slot>,
+    /// Some builds a v0 tx
+    lookup_tables: Option<Vec<AddressLookupTableAccount>>,
+}
+
+impl PumpTxBuilder {
+    pub fn new(payer: Pubkey) -> Self {
+        Self {
+            payer,
+            compute_budget: None,
+            body: vec![],
+            atas: vec![],
+            close_atas: vec![],
+            memo: None,
+            tip: None,
+            deadline: None,
+            lookup_tables: None,
+        }
+    }
+
+    pub fn compute_budget(mut self, price: u64, max_units: u32) -> Self {
+        self.compute_budget = Some((price, max_units));
+        self
+    }
+
+    /// create_ata adds an idempotent create of the payer ata, once per mint
+    pub fn create_ata(mut self, mint: &Pubkey) -> Self {
+        if !self.atas.contains(mint) {
+            self.atas.push(*mint);
+            self.body.push(create_associated_token_account_idempotent(
+                &self.payer,
+                &self.payer,
+                mint,
+                &spl_token::id(),
+            ));
+        }
+        self
+    }
+
+    /// buy creates the ata if needed and spends at most max_sol_cost, the
+    /// fee goes to the fee_recipient of the pump global
+    pub fn buy(
+        self,
+        pump_accounts: PumpAccounts,
+        fee_recipient: Pubkey,
+        token_amount: u64,
+        max_sol_cost: u64,
+        version: PumpIxVersion,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut builder = self.create_ata(&pump_accounts.mint);
+        let ata =
+            get_associated_token_address(&builder.payer, &pump_accounts.mint);
+        builder.body.push(make_pump_swap_ix(
+            builder.payer,
+            pump_accounts,
+            fee_recipient,
+            token_amount,
+            max_sol_cost,
+            ata,
+            version,
+        )?);
+        Ok(builder)
+    }
+
+    pub fn sell(
+        mut self,
+        pump_accounts: PumpAccounts,
+        fee_recipient: Pubkey,
+        token_amount: u64,
+        min_sol_output: u64,
+        version: PumpIxVersion,
+    ) -> Result<Self, Box<dyn Error>> {
+        let ata =
+            get_associated_token_address(&self.payer, &pump_accounts.mint);
+        self.body.push(make_pump_sell_ix(
+            self.payer,
+            pump_accounts,
+            fee_recipient,
+            token_amount,
+            min_sol_output,
+            ata,
+            version,
+        )?);
+        Ok(self)
+    }
+
+    /// instruction adds anything else to the body, e.g. a create
+    pub fn instruction(mut self, ix: Instruction) -> Self {
+        self.body.push(ix);
+        self
+    }
+
+    /// close_ata returns the ata rent to the payer, the ata has to be empty
+    /// by then, e.g. after selling everything
+    pub fn close_ata(mut self, mint: &Pubkey) -> Self {
+        if !self.close_atas.contains(mint) {
+            self.close_atas.push(*mint);
+        }
+        self
+    }
+
+    pub fn memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    /// tip goes to one of the jito tip accounts
+    pub fn tip(mut self, lamports: u64) -> Self {
+        self.tip = Some((lamports, get_jito_tip_pubkey()));
+        self
+    }
+
+    /// deadline makes the tx fail past the slot
+    pub fn deadline(mut self, deadline: Slot) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// lookup_tables builds a v0 tx, an empty vec for one without tables
+    pub fn lookup_tables(
+        mut self,
+        lookup_tables: Vec<AddressLookupTableAccount>,
+    ) -> Self {
+        self.lookup_tables = Some(lookup_tables);
+        self
+    }
+
+    /// cached_lookup_tables uses the tables from LOOKUP_TABLES, if any were
+    /// loaded, the tx stays legacy otherwise
+    pub fn cached_lookup_tables(self) -> Self {
+        let lookup_tables = get_lookup_tables();
+        if lookup_tables.is_empty() {
+            return self;
+        }
+        self.lookup_tables(lookup_tables)
+    }
+
+    /// estimated_compute_budget sets the budget from a simulation of the
+    /// tx and the recent fees of what it writes, fallback if that fails
+    pub async fn estimated_compute_budget(
+        self,
+        estimator: &ComputeEstimator,
+        fallback: ComputeBudget,
+    ) -> Self {
+        let ixs = self.instructions(&TxLifetime::Blockhash(Hash::default()));
+        let lookup_tables = self.lookup_tables.clone().unwrap_or_default();
+        let budget =
+            match estimator.estimate(&self.payer, &ixs, &lookup_tables).await
+            {
+                Ok(budget) => budget,
+                Err(e) => {
+                    warn!("Using the fallback compute budget: {}", e);
+                    fallback
+                }
+            };
+        self.compute_budget(budget.price, budget.units)
+    }
+
+    /// cached_compute_budget sets the budget from earlier estimates of the
+    /// same shape, fallback until there are any, and refreshes them in the
+    /// background, nothing waits on the rpc
+    pub async fn cached_compute_budget(
+        self,
+        estimator: &Arc<ComputeEstimator>,
+        fallback: ComputeBudget,
+    ) -> Self {
+        let ixs = self.instructions(&TxLifetime::Blockhash(Hash::default()));
+        let budget = estimator.cached(&ixs).await.unwrap_or(fallback);
+        estimator
+            .refresh(
+                self.payer,
+                ixs,
+                self.lookup_tables.clone().unwrap_or_default(),
+            )
+            .await;
+        self.compute_budget(budget.price, budget.units)
+    }
+
+    /// instructions in the order they end up in the tx
+    pub fn instructions(&self, lifetime: &TxLifetime) -> Vec<Instruction> {
+        let mut ixs = vec![];
+        if let TxLifetime::Nonce(nonce) = lifetime {
+            ixs.push(nonce.advance_ix());
+        }
+        if let Some((price, max_units)) = self.compute_budget {
+            ixs.append(&mut make_compute_budget_ixs(price, max_units));
+        }
+        ixs.extend(self.body.iter().cloned());
+        for mint in &self.close_atas {
+            ixs.push(
+                close_account(
+                    &spl_token::id(),
+                    &get_associated_token_address(&self.payer, mint),
+                    &self.payer,
+                    &self.payer,
+                    &[],
+                )
+                .expect("close account ix"),
+            );
+        }
+        if let Some(memo) = &self.memo {
+            ixs.push(Instruction::new_with_bytes(
+                Pubkey::from_str(MEMO_PROGRAM).expect("memo program"),
+                memo.as_bytes(),
+                vec![],
+            ));
+        }
+        if let Some((tip, tip_account)) = self.tip {
+            ixs.push(transfer(&self.payer, &tip_account, tip));
+        }
+        if let Some(deadline) = self.deadline {
+            ixs.push(make_deadline_ix(deadline));
+        }
+        ixs
+    }
+
+    /// message compiles the ixs without signing, v0 when there are lookup
+    /// tables
+    pub fn message(
+        &self,
+        lifetime: impl Into<TxLifetime>,
+    ) -> Result<VersionedMessage, Box<dyn Error>> {
+        let lifetime = lifetime.into();
+        let blockhash = match lifetime {
+            TxLifetime::Blockhash(blockhash) => blockhash,
+            TxLifetime::Nonce(nonce) => nonce.blockhash,
+        };
+        let ixs = self.instructions(&lifetime);
+        Ok(match &self.lookup_tables {
+            Some(lookup_tables) => {
+                VersionedMessage::V0(v0::Message::try_compile(
+                    &self.payer,
+                    &ixs,
+                    lookup_tables,
+                    blockhash,
+                )?)
+            }
+            None => {
+                let mut message = Message::new(&ixs, Some(&self.payer));
+                message.recent_blockhash = blockhash;
+                VersionedMessage::Legacy(message)
+            }
+        })
+    }
+
+    /// build checks the signers, the account count and the size before
+    /// signing
+    pub fn build(
+        &self,
+        signers: &[&Keypair],
+        lifetime: impl Into<TxLifetime>,
+    ) -> Result<VersionedTransaction, Box<dyn Error>> {
+        let message = self.message(lifetime)?;
+        validate_message(&message, signers)?;
+        Ok(VersionedTransaction::try_new(message, signers)?)
+    }
+}
+
+/// validate_message is what would get the tx rejected before it is sent,
+/// a missing or extra signer, too many accounts or too many bytes
+pub fn validate_message(
+    message: &VersionedMessage,
+    signers: &[&Keypair],
+) -> Result<(), Box<dyn Error>> {
+    let num_signatures = message.header().num_required_signatures as usize;
+    let required = &message.static_account_keys()[..num_signatures];
+    for key in required {
+        if !signers.iter().any(|signer| signer.pubkey() == *key) {
+            return Err(format!("Missing signer {}", key).into());
+        }
+    }
+    for signer in signers {
+        if !required.contains(&signer.pubkey()) {
+            return Err(format!(
+                "{} is not a signer of the tx",
+                signer.pubkey()
+            )
+            .into());
+        }
+    }
+
+    let loaded = message.address_table_lookups().map_or(0, |lookups| {
+        lookups
+            .iter()
+            .map(|lookup| {
+                lookup.writable_indexes.len() + lookup.readonly_indexes.len()
+            })
+            .sum()
+    });
+    let accounts = message.static_account_keys().len() + loaded;
+    if accounts > MAX_TX_ACCOUNTS {
+        return Err(format!(
+            "Tx locks {} accounts, at most {}",
+            accounts, MAX_TX_ACCOUNTS
+        )
+        .into());
+    }
+
+    let size = bincode::serialized_size(&VersionedTransaction {
+        signatures: vec![Signature::default(); num_signatures],
+        message: message.clone(),
+    })? as usize;
+    if size > PACKET_DATA_SIZE {
+        return Err(format!(
+            "Tx is {} bytes, at most {}",
+            size, PACKET_DATA_SIZE
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global::PumpGlobal;
+    use crate::mock_rpc::{MockRpcServer, RpcFixtures};
+    use crate::pump::mint_to_pump_accounts;
+    use crate::wallet::NonceAccount;
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_sdk::system_program;
+    use std::time::Duration;
+
+    fn pump_accounts() -> PumpAccounts {
+        PumpAccounts {
+            dev: Pubkey::new_unique(),
+            ..mint_to_pump_accounts(&Pubkey::new_unique())
+        }
+    }
+
+    fn program_ids(tx: &VersionedTransaction) -> Vec<Pubkey> {
+        let keys = tx.message.static_account_keys();
+        tx.message
+            .instructions()
+            .iter()
+            .map(|ix| keys[ix.program_id_index as usize])
+            .collect()
+    }
+
+    #[test]
+    fn test_builder_order() {
+        let wallet = Keypair::new();
+        let pump_accounts = pump_accounts();
+        // whatever the global says, not the hardcoded address
+        let fee_recipient = Pubkey::new_unique();
+        let builder = PumpTxBuilder::new(wallet.pubkey())
+            .deadline(420)
+            .tip(50_000)
+            .compute_budget(1_000_069, 69_000)
+            .buy(
+                pump_accounts,
+                fee_recipient,
+                1_000,
+                2_000,
+                PumpIxVersion::default(),
+            )
+            .unwrap()
+            .sell(
+                pump_accounts,
+                fee_recipient,
+                1_000,
+                1,
+                PumpIxVersion::default(),
+            )
+            .unwrap()
+            .create_ata(&pump_accounts.mint)
+            .close_ata(&pump_accounts.mint)
+            .memo("gm");
+        let tx = builder.build(&[&wallet], Hash::new_unique()).unwrap();
+        assert!(matches!(tx.message, VersionedMessage::Legacy(_)));
+        tx.verify_and_hash_message().unwrap();
+
+        let pump =
+            Pubkey::from_str(crate::constants::PUMP_FUN_PROGRAM).unwrap();
+        let slot_checker =
+            Pubkey::from_str(crate::constants::SLOT_CHECKER_MAINNET).unwrap();
+        assert_eq!(
+            program_ids(&tx),
+            vec![
+                solana_sdk::compute_budget::id(),
+                solana_sdk::compute_budget::id(),
+                spl_associated_token_account::id(),
+                pump,
+                pump,
+                spl_token::id(),
+                Pubkey::from_str(MEMO_PROGRAM).unwrap(),
+                system_program::id(),
+                slot_checker,
+            ]
+        );
+        let keys = tx.message.static_account_keys();
+        for ix in &tx.message.instructions()[3..5] {
+            assert_eq!(keys[ix.accounts[1] as usize], fee_recipient);
+        }
+
+        // the tip account does not change between builds
+        let lifetime = TxLifetime::Blockhash(Hash::default());
+        let tip_ix = &builder.instructions(&lifetime)[7];
+        for _ in 0..10 {
+            assert_eq!(&builder.instructions(&lifetime)[7], tip_ix);
+        }
+    }
+
+    #[test]
+    fn test_builder_nonce_and_v0() {
+        let wallet = Keypair::new();
+        let pump_accounts = pump_accounts();
+        let nonce = NonceAccount {
+            account: Pubkey::new_unique(),
+            authority: wallet.pubkey(),
+            blockhash: Hash::new_unique(),
+        };
+        let builder = PumpTxBuilder::new(wallet.pubkey())
+            .buy(
+                pump_accounts,
+                PumpGlobal::default().fee_recipient,
+                1_000,
+                2_000,
+                PumpIxVersion::default(),
+            )
+            .unwrap()
+            .tip(50_000);
+
+        let legacy = builder.build(&[&wallet], nonce).unwrap();
+        assert_eq!(*legacy.message.recent_blockhash(), nonce.blockhash);
+        assert_eq!(program_ids(&legacy)[0], system_program::id());
+
+        // signers and programs stay static, the rest comes from the table
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: legacy.message.static_account_keys().to_vec(),
+        };
+        let v0 = builder
+            .clone()
+            .lookup_tables(vec![table])
+            .build(&[&wallet], nonce)
+            .unwrap();
+        assert!(matches!(v0.message, VersionedMessage::V0(_)));
+        assert_eq!(program_ids(&v0)[0], system_program::id());
+        assert!(
+            bincode::serialized_size(&v0).unwrap()
+                < bincode::serialized_size(&legacy).unwrap()
+        );
+        v0.verify_and_hash_message().unwrap();
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let wallet = Keypair::new();
+        let builder = PumpTxBuilder::new(wallet.pubkey()).tip(50_000);
+
+        let err = builder.build(&[], Hash::new_unique()).unwrap_err();
+        assert!(err.to_string().starts_with("Missing signer"));
+        let other = Keypair::new();
+        let err = builder
+            .build(&[&wallet, &other], Hash::new_unique())
+            .unwrap_err();
+        assert!(err.to_string().contains("is not a signer"));
+
+        let err = builder
+            .clone()
+            .memo("x".repeat(PACKET_DATA_SIZE))
+            .build(&[&wallet], Hash::new_unique())
+            .unwrap_err();
+        assert!(err.to_string().contains("bytes, at most 1232"));
+
+        let mut builder = builder;
+        for _ in 0..20 {
+            builder = builder
+                .buy(
+                    pump_accounts(),
+                    PumpGlobal::default().fee_recipient,
+                    1_000,
+                    2_000,
+                    PumpIxVersion::default(),
+                )
+                .unwrap();
+        }
+        let err = builder
+            .lookup_tables(vec![])
+            .build(&[&wallet], Hash::new_unique())
+            .unwrap_err();
+        assert!(err.to_string().contains("at most 64"));
+    }
+
+    #[tokio::test]
+    async fn test_builder_estimated_compute_budget() {
+        let wallet = Keypair::new();
+        let builder = PumpTxBuilder::new(wallet.pubkey()).tip(50_000);
+        let fallback = ComputeBudget { price: 1, units: 2 };
+
+        // nothing listens there
+        let estimator = ComputeEstimator::new(Arc::new(RpcClient::new(
+            "http://127.0.0.1:1".to_string(),
+        )));
+        let estimated = builder
+            .clone()
+            .estimated_compute_budget(&estimator, fallback)
+            .await;
+        assert_eq!(estimated.compute_budget, Some((1, 2)));
+
+        let server = MockRpcServer::start(RpcFixtures {
+            units_consumed: 1_000,
+            prioritization_fees: vec![42],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let estimator = ComputeEstimator::new(Arc::new(RpcClient::new(
+            server.rpc_url.clone(),
+        )));
+        let estimated =
+            builder.estimated_compute_budget(&estimator, fallback).await;
+        assert_eq!(estimated.compute_budget, Some((42, 1_200)));
+    }
+
+    #[tokio::test]
+    async fn test_builder_cached_compute_budget() {
+        let wallet = Keypair::new();
+        let builder = PumpTxBuilder::new(wallet.pubkey()).tip(50_000);
+        let fallback = ComputeBudget { price: 1, units: 2 };
+        let server = MockRpcServer::start(RpcFixtures {
+            units_consumed: 1_000,
+            prioritization_fees: vec![42],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let estimator = Arc::new(ComputeEstimator::new(Arc::new(
+            RpcClient::new(server.rpc_url.clone()),
+        )));
+
+        // the first one does not wait for the estimate
+        let cached = builder
+            .clone()
+            .cached_compute_budget(&estimator, fallback)
+            .await;
+        assert_eq!(cached.compute_budget, Some((1, 2)));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let cached = builder
+                    .clone()
+                    .cached_compute_budget(&estimator, fallback)
+                    .await;
+                if cached.compute_budget == Some((42, 1_200)) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("refreshed");
+        assert_eq!(server.simulated_transactions().await.len(), 1);
+    }
+}
@@ -17,6 +17,9 @@ use std::cell::RefCell;
 use std::error::Error;
 use std::io::Write;
 use std::str::FromStr;
+use std::sync::RwLock;
+
+use crate::constants::JITO_TIP_ACCOUNTS;
 
 pub fn env(var: &str) -> String {
     std::env::var(var).unwrap_or_else(|_| panic!("{} env var not set", var))
@@ -69,19 +72,31 @@ pub fn fast_random_0_to_7() -> u8 {
     RNG.with(|rng| rng.borrow_mut().gen_range(0..8))
 }
 
+/// tip accounts from getTipAccounts, JITO_TIP_ACCOUNTS until fetched
+static TIP_ACCOUNTS: RwLock<Vec<Pubkey>> = RwLock::new(Vec::new());
+
+pub fn set_jito_tip_accounts(tip_accounts: Vec<Pubkey>) {
+    *TIP_ACCOUNTS.write().expect("tip accounts") = tip_accounts;
+}
+
 pub fn get_jito_tip_pubkey() -> Pubkey {
-    const PUBKEYS: [&str; 8] = [
-        "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
-        "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
-        "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
-        "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
-        "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
-        "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
-        "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
-        "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
-    ];
+    let tip_accounts = TIP_ACCOUNTS.read().expect("tip accounts");
+    if !tip_accounts.is_empty() {
+        let index =
+            RNG.with(|rng| rng.borrow_mut().gen_range(0..tip_accounts.len()));
+        return tip_accounts[index];
+    }
     let index = fast_random_0_to_7();
-    Pubkey::from_str(PUBKEYS[index as usize]).expect("parse tip pubkey")
+    Pubkey::from_str(JITO_TIP_ACCOUNTS[index as usize])
+        .expect("parse tip pubkey")
+}
+
+/// is_jito_tip_account is true for the fetched and the known tip accounts
+pub fn is_jito_tip_account(pubkey: &Pubkey) -> bool {
+    TIP_ACCOUNTS.read().expect("tip accounts").contains(pubkey)
+        || JITO_TIP_ACCOUNTS
+            .iter()
+            .any(|address| Pubkey::from_str(address).ok() == Some(*pubkey))
 }
 
 #[cfg(test)]
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, error::Error};
+use std::{collections::HashMap, error::Error, sync::Arc};
 
 use rand::seq::SliceRandom;
 use solana_client::nonblocking::rpc_client::RpcClient;
@@ -7,21 +7,26 @@ use spl_associated_token_account::get_associated_token_address;
 use tracing::error;
 
 use crate::{
+    compute::ComputeEstimator,
+    global::{get_pump_global, PumpGlobal},
     pump::{
         buy_pump_token, get_bonding_curve, get_token_amount,
         mint_to_pump_accounts, sell_pump_token,
     },
+    sender::TxSender,
     util::env,
     wallet::{wait_token_balance, WalletManager},
 };
 
 /// buy_ratio 0-100, buys per 100 transactions
+/// slippage_bps bounds the min_sol_output of the sells
 #[derive(Debug, Default, Clone, Copy)]
 pub struct VolumeConfig {
     pub buy_ratio: u8,
     pub lamports: u64,
     pub mint: Pubkey,
     pub tip: u64,
+    pub slippage_bps: u64,
 }
 
 #[derive(Debug, Default)]
@@ -35,11 +40,15 @@ pub struct Volume {
     pub config: VolumeConfig,
     pub wallets: HashMap<Pubkey, Balances>,
     pub queue: Vec<bool>,
+    pub pump_global: PumpGlobal,
+    pub sender: Arc<dyn TxSender>,
+    pub estimator: ComputeEstimator,
 }
 
 pub async fn init(
     config: VolumeConfig,
     wallet_manager: WalletManager,
+    sender: Arc<dyn TxSender>,
 ) -> Result<Volume, Box<dyn Error>> {
     // initialize the queue with ratio buy_ratio
     let mut queue = vec![false; 1000];
@@ -56,11 +65,16 @@ pub async fn init(
             token_amounts: HashMap::new(),
         });
     }
+    let pump_global = get_pump_global(&wallet_manager.rpc_client).await?;
+    let estimator = ComputeEstimator::new(wallet_manager.rpc_client.clone());
     Ok(Volume {
         queue,
         config,
         wallets,
         wallet_manager,
+        pump_global,
+        sender,
+        estimator,
     })
 }
 
@@ -107,11 +121,15 @@ impl Volume {
                     )?;
                     buy_pump_token(
                         fresh_wallet,
+                        &self.pump_global,
                         latest_blockhash,
                         pump_accounts,
+                        &bonding_curve,
                         token_amount,
                         self.config.lamports,
                         self.config.tip,
+                        self.sender.as_ref(),
+                        &self.estimator,
                     )
                     .await?;
 
@@ -148,12 +166,17 @@ impl Volume {
                         if token_amount > 0 {
                             sell_pump_token(
                                 wallet_with_balance,
+                                &rpc_client,
+                                &self.pump_global,
                                 wallet_manager
                                     .rpc_client
                                     .get_latest_blockhash()
                                     .await?,
                                 mint_to_pump_accounts(&self.config.mint),
                                 token_amount,
+                                self.config.slippage_bps,
+                                self.sender.as_ref(),
+                                &self.estimator,
                             )
                             .await?;
 
@@ -1,18 +1,24 @@
+use std::error::Error;
 use std::sync::Arc;
 
 use futures::future::join_all;
-use jito_searcher_client::send_bundle_no_wait;
 use log::info;
 use solana_account_decoder::UiAccountData;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::Message;
+use solana_sdk::nonce::state::{State, Versions};
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::Keypair;
+use solana_sdk::signature::{Keypair, Signature};
 use solana_sdk::signer::{EncodableKey, Signer};
+use solana_sdk::system_instruction;
 use solana_sdk::transaction::{Transaction, VersionedTransaction};
 use tokio::sync::RwLock;
 
-use crate::jito::{make_searcher_client, SearcherClient};
+use crate::dry_run::{get_dry_run, send_and_confirm};
+use crate::jito::{make_searcher_client, send_bundle, SearcherClient};
 use crate::util::{env, get_jito_tip_pubkey};
 
 pub struct WalletManager {
@@ -229,7 +235,7 @@ impl WalletManager {
             ));
 
         let mut searcher_client = self.searcher_client.write().await;
-        send_bundle_no_wait(&[tx], &mut searcher_client).await?;
+        send_bundle(&[tx], &mut searcher_client).await?;
 
         wait_balance(
             &self.rpc_client,
@@ -312,7 +318,7 @@ impl WalletManager {
         }
 
         let mut searcher_client = self.searcher_client.write().await;
-        send_bundle_no_wait(&transactions, &mut searcher_client).await?;
+        send_bundle(&transactions, &mut searcher_client).await?;
 
         info!(
             "Drained {} lamports from {} wallets in {} transactions",
@@ -350,6 +356,10 @@ pub async fn wait_balance(
     pubkey: &Pubkey,
     amount: u64,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // a dry run moves nothing, the balance would never get there
+    if get_dry_run().is_some() {
+        return Ok(());
+    }
     info!("Waiting for wallet balance to reach {}", amount);
     loop {
         let balance = rpc_client.get_balance(pubkey).await?;
@@ -372,6 +382,9 @@ pub async fn wait_token_balance(
     pubkey: &Pubkey,
     amount: u64,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    if get_dry_run().is_some() {
+        return Ok(());
+    }
     info!("Waiting for token balance to reach {}", amount);
     loop {
         let balance = rpc_client
@@ -392,3 +405,325 @@ pub async fn wait_token_balance(
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
     }
 }
+
+/// NonceAccount is a durable nonce, txs signed against it stay valid until
+/// the nonce is advanced instead of for ~150 slots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceAccount {
+    pub account: Pubkey,
+    pub authority: Pubkey,
+    /// what goes into recent_blockhash
+    pub blockhash: Hash,
+}
+
+impl NonceAccount {
+    pub fn parse(
+        account: Pubkey,
+        data: &[u8],
+    ) -> Result<Self, Box<dyn Error>> {
+        match bincode::deserialize::<Versions>(data)?.state() {
+            State::Initialized(nonce) => Ok(Self {
+                account,
+                authority: nonce.authority,
+                blockhash: nonce.blockhash(),
+            }),
+            State::Uninitialized => {
+                Err(format!("Nonce account {} not initialized", account)
+                    .into())
+            }
+        }
+    }
+
+    /// advance_ix has to be the first instruction of a tx using the nonce
+    pub fn advance_ix(&self) -> Instruction {
+        system_instruction::advance_nonce_account(
+            &self.account,
+            &self.authority,
+        )
+    }
+}
+
+/// TxLifetime is what a tx is signed against, a recent blockhash or a
+/// durable nonce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxLifetime {
+    Blockhash(Hash),
+    Nonce(NonceAccount),
+}
+
+impl From<Hash> for TxLifetime {
+    fn from(blockhash: Hash) -> Self {
+        Self::Blockhash(blockhash)
+    }
+}
+
+impl From<NonceAccount> for TxLifetime {
+    fn from(nonce: NonceAccount) -> Self {
+        Self::Nonce(nonce)
+    }
+}
+
+/// make_signed_tx signs the ixs against the lifetime, for a nonce the
+/// advance ix is prepended and the nonce authority has to be one of the
+/// signers
+pub fn make_signed_tx(
+    ixs: &[Instruction],
+    payer: &Pubkey,
+    signers: &[&Keypair],
+    lifetime: impl Into<TxLifetime>,
+) -> Transaction {
+    match lifetime.into() {
+        TxLifetime::Blockhash(blockhash) => {
+            Transaction::new_signed_with_payer(
+                ixs,
+                Some(payer),
+                signers,
+                blockhash,
+            )
+        }
+        TxLifetime::Nonce(nonce) => {
+            let message = Message::new_with_nonce(
+                ixs.to_vec(),
+                Some(payer),
+                &nonce.account,
+                &nonce.authority,
+            );
+            Transaction::new(signers, message, nonce.blockhash)
+        }
+    }
+}
+
+pub async fn get_nonce_account(
+    rpc_client: &RpcClient,
+    account: &Pubkey,
+) -> Result<NonceAccount, Box<dyn Error>> {
+    let data = rpc_client.get_account_data(account).await?;
+    NonceAccount::parse(*account, &data)
+}
+
+/// create_nonce_account funds nonce_keypair with the rent exempt minimum and
+/// initializes it with the given authority
+pub async fn create_nonce_account(
+    rpc_client: &RpcClient,
+    payer: &Keypair,
+    nonce_keypair: &Keypair,
+    authority: &Pubkey,
+) -> Result<Signature, Box<dyn Error>> {
+    let rent = rpc_client
+        .get_minimum_balance_for_rent_exemption(State::size())
+        .await?;
+    let ixs = system_instruction::create_nonce_account(
+        &payer.pubkey(),
+        &nonce_keypair.pubkey(),
+        authority,
+        rent,
+    );
+    let tx = make_signed_tx(
+        &ixs,
+        &payer.pubkey(),
+        &[payer, nonce_keypair],
+        rpc_client.get_latest_blockhash().await?,
+    );
+    let signature = send_and_confirm(rpc_client, &tx).await?;
+    info!(
+        "Created nonce account {} ({} lamports): {}",
+        nonce_keypair.pubkey(),
+        rent,
+        signature
+    );
+    Ok(signature)
+}
+
+/// advance_nonce invalidates every tx signed against the current nonce,
+/// e.g. to cancel a pre-signed exit
+pub async fn advance_nonce(
+    rpc_client: &RpcClient,
+    authority: &Keypair,
+    account: &Pubkey,
+) -> Result<Signature, Box<dyn Error>> {
+    let tx = make_signed_tx(
+        &[system_instruction::advance_nonce_account(
+            account,
+            &authority.pubkey(),
+        )],
+        &authority.pubkey(),
+        &[authority],
+        rpc_client.get_latest_blockhash().await?,
+    );
+    let signature = send_and_confirm(rpc_client, &tx).await?;
+    info!("Advanced nonce {}: {}", account, signature);
+    Ok(signature)
+}
+
+/// withdraw_nonce moves lamports out of the nonce account, None withdraws
+/// everything and closes it
+pub async fn withdraw_nonce(
+    rpc_client: &RpcClient,
+    authority: &Keypair,
+    account: &Pubkey,
+    to: &Pubkey,
+    lamports: Option<u64>,
+) -> Result<Signature, Box<dyn Error>> {
+    let lamports = match lamports {
+        Some(lamports) => lamports,
+        None => rpc_client.get_balance(account).await?,
+    };
+    let tx = make_signed_tx(
+        &[system_instruction::withdraw_nonce_account(
+            account,
+            &authority.pubkey(),
+            to,
+            lamports,
+        )],
+        &authority.pubkey(),
+        &[authority],
+        rpc_client.get_latest_blockhash().await?,
+    );
+    let signature = send_and_confirm(rpc_client, &tx).await?;
+    info!(
+        "Withdrew {} lamports from nonce {} to {}: {}",
+        lamports, account, to, signature
+    );
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jito::{_send_jito_tx, _send_out_bundle};
+    use crate::mock_jito::MockBlockEngine;
+    use crate::mock_rpc::{MockRpcServer, RpcFixtures};
+    use futures::future::join_all;
+    use solana_sdk::account::Account;
+    use solana_sdk::nonce::state::{Data, DurableNonce};
+    use solana_sdk::system_instruction::SystemInstruction;
+    use solana_sdk::system_program;
+    use solana_sdk::transaction::uses_durable_nonce;
+
+    fn nonce_data(authority: &Pubkey, blockhash: &Hash) -> Vec<u8> {
+        bincode::serialize(&Versions::new(State::Initialized(Data::new(
+            *authority,
+            DurableNonce::from_blockhash(blockhash),
+            5_000,
+        ))))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_nonce_account_helpers() {
+        let payer = Keypair::new();
+        let nonce_keypair = Keypair::new();
+        let blockhash = Hash::new_unique();
+        let server =
+            MockRpcServer::start(RpcFixtures::default().with_account(
+                nonce_keypair.pubkey(),
+                Account {
+                    lamports: 1_447_680,
+                    data: nonce_data(&payer.pubkey(), &blockhash),
+                    owner: system_program::id(),
+                    executable: false,
+                    rent_epoch: 0,
+                },
+            ))
+            .await
+            .unwrap();
+        let rpc_client = RpcClient::new(server.rpc_url.clone());
+
+        create_nonce_account(
+            &rpc_client,
+            &payer,
+            &nonce_keypair,
+            &payer.pubkey(),
+        )
+        .await
+        .unwrap();
+        let nonce = get_nonce_account(&rpc_client, &nonce_keypair.pubkey())
+            .await
+            .unwrap();
+        // the durable nonce is derived from the blockhash, not equal to it
+        assert_eq!(
+            nonce,
+            NonceAccount {
+                account: nonce_keypair.pubkey(),
+                authority: payer.pubkey(),
+                blockhash: *DurableNonce::from_blockhash(&blockhash)
+                    .as_hash(),
+            }
+        );
+        advance_nonce(&rpc_client, &payer, &nonce.account)
+            .await
+            .unwrap();
+        withdraw_nonce(
+            &rpc_client,
+            &payer,
+            &nonce.account,
+            &payer.pubkey(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let sent = server.sent_transactions().await;
+        assert_eq!(sent.len(), 3);
+        let instructions = sent
+            .iter()
+            .map(|tx| {
+                let ix = tx.message.instructions().last().unwrap();
+                bincode::deserialize::<SystemInstruction>(&ix.data).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            instructions[0],
+            SystemInstruction::InitializeNonceAccount(authority)
+                if authority == payer.pubkey()
+        ));
+        assert_eq!(instructions[1], SystemInstruction::AdvanceNonceAccount);
+        assert_eq!(
+            instructions[2],
+            SystemInstruction::WithdrawNonceAccount(1_447_680)
+        );
+
+        let uninitialized =
+            bincode::serialize(&Versions::new(State::Uninitialized)).unwrap();
+        assert!(NonceAccount::parse(nonce.account, &uninitialized).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_nonce_tx_through_jito() {
+        let payer = Keypair::new();
+        let nonce = NonceAccount {
+            account: Pubkey::new_unique(),
+            authority: payer.pubkey(),
+            blockhash: Hash::new_unique(),
+        };
+        let tx = make_signed_tx(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &get_jito_tip_pubkey(),
+                10_000,
+            )],
+            &payer.pubkey(),
+            &[&payer],
+            nonce,
+        );
+        assert!(uses_durable_nonce(&tx).is_some());
+        assert_eq!(tx.message.recent_blockhash, nonce.blockhash);
+        assert_eq!(tx.message.instructions.len(), 2);
+        tx.verify().unwrap();
+
+        let engine = MockBlockEngine::start().await.unwrap();
+        _send_jito_tx(&engine.http_url, tx.clone()).await.unwrap();
+        let tx = VersionedTransaction::from(tx);
+        join_all(
+            _send_out_bundle(
+                std::slice::from_ref(&engine.http_url),
+                std::slice::from_ref(&tx),
+            )
+            .await
+            .unwrap(),
+        )
+        .await;
+        assert_eq!(engine.transactions().await, vec![tx.clone()]);
+        assert_eq!(engine.bundles().await[0].transactions, vec![tx]);
+    }
+}